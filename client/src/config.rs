@@ -0,0 +1,321 @@
+use cuda_over_ip_common::cuda::{CUresult, CUDA_ERROR_UNKNOWN, CUDA_SUCCESS};
+use cuda_over_ip_common::frame::MAX_MEMCPY_CHUNK_SIZE;
+use serde::Deserialize;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+pub(crate) const SERVER_ENV: &str = "CUDA_OVER_IP_SERVER";
+pub(crate) const PORT_ENV: &str = "CUDA_OVER_IP_PORT";
+pub(crate) const CONNECT_TIMEOUT_MS_ENV: &str = "CUDA_OVER_IP_CONNECT_TIMEOUT_MS";
+pub(crate) const TRANSPORT_ERROR_ENV: &str = "CUDA_OVER_IP_TRANSPORT_ERROR";
+pub(crate) const RECONNECT_ATTEMPTS_ENV: &str = "CUDA_OVER_IP_RECONNECT_ATTEMPTS";
+pub(crate) const RECONNECT_BACKOFF_MS_ENV: &str = "CUDA_OVER_IP_RECONNECT_BACKOFF_MS";
+pub(crate) const MAX_CONNECTIONS_ENV: &str = "CUDA_OVER_IP_MAX_CONNECTIONS";
+pub(crate) const MEMCPY_CHUNK_SIZE_ENV: &str = "CUDA_OVER_IP_MEMCPY_CHUNK_SIZE";
+pub(crate) const CONFIG_FILE_ENV: &str = "CUDA_OVER_IP_CONFIG";
+
+const DEFAULT_CONFIG_FILE: &str = "/etc/cuda-over-ip/client.toml";
+const DEFAULT_SERVER_HOST: &str = "127.0.0.1";
+const DEFAULT_SERVER_PORT: u16 = 19999;
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const DEFAULT_TRANSPORT_ERROR: CUresult = CUDA_ERROR_UNKNOWN;
+const DEFAULT_RECONNECT_ATTEMPTS: u32 = 5;
+const DEFAULT_RECONNECT_BACKOFF: Duration = Duration::from_millis(100);
+const DEFAULT_MAX_CONNECTIONS: u32 = 8;
+const DEFAULT_MEMCPY_CHUNK_SIZE: u32 = 1024 * 1024;
+
+/// How the client shim connects to the server.
+///
+/// Values are taken from the environment first, then from the config file
+/// (`$CUDA_OVER_IP_CONFIG` or `/etc/cuda-over-ip/client.toml`), then from the defaults.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ClientConfig {
+    pub(crate) server_host: String,
+    pub(crate) server_port: u16,
+    pub(crate) connect_timeout: Duration,
+    /// Returned by intercepted functions when the connection to the server fails.
+    pub(crate) transport_error: CUresult,
+    /// How many times to try to reconnect and resume the session after the connection drops.
+    pub(crate) reconnect_attempts: u32,
+    /// Delay before the first reconnect attempt, doubled after every failed attempt.
+    pub(crate) reconnect_backoff: Duration,
+    /// How many connections to open at most. Threads beyond that share connections, and with them the server thread
+    /// and its current context, so this must be at least the number of threads using contexts.
+    pub(crate) max_connections: u32,
+    /// Memory copies are sent in chunks of at most this many bytes, so neither side buffers a whole copy.
+    pub(crate) memcpy_chunk_size: u32,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        ClientConfig {
+            server_host: DEFAULT_SERVER_HOST.to_string(),
+            server_port: DEFAULT_SERVER_PORT,
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            transport_error: DEFAULT_TRANSPORT_ERROR,
+            reconnect_attempts: DEFAULT_RECONNECT_ATTEMPTS,
+            reconnect_backoff: DEFAULT_RECONNECT_BACKOFF,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            memcpy_chunk_size: DEFAULT_MEMCPY_CHUNK_SIZE,
+        }
+    }
+}
+
+/// Contents of the client config file. Every field is optional.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    server: Option<String>,
+    port: Option<u16>,
+    connect_timeout_ms: Option<u64>,
+    transport_error: Option<CUresult>,
+    reconnect_attempts: Option<u32>,
+    reconnect_backoff_ms: Option<u64>,
+    max_connections: Option<u32>,
+    memcpy_chunk_size: Option<u32>,
+}
+
+#[derive(Debug)]
+pub(crate) enum ConfigError {
+    InvalidValue { source: String, value: String, reason: String },
+    ReadFile { path: PathBuf, error: std::io::Error },
+    ParseFile { path: PathBuf, error: toml::de::Error },
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::InvalidValue { source, value, reason } =>
+                write!(f, "invalid value {:?} in {}: {}", value, source, reason),
+            ConfigError::ReadFile { path, error } =>
+                write!(f, "cannot read config file {}: {}", path.display(), error),
+            ConfigError::ParseFile { path, error } =>
+                write!(f, "cannot parse config file {}: {}", path.display(), error),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl ClientConfig {
+    pub(crate) fn load() -> Result<Self, ConfigError> {
+        Self::load_from(|name| std::env::var(name).ok())
+    }
+
+    fn load_from(env: impl Fn(&str) -> Option<String>) -> Result<Self, ConfigError> {
+        let mut config = ClientConfig::default();
+
+        // The explicitly requested config file must exist, the default one is optional.
+        let config_file = match env(CONFIG_FILE_ENV) {
+            Some(path) => Some(read_config_file(Path::new(&path))?),
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() =>
+                Some(read_config_file(Path::new(DEFAULT_CONFIG_FILE))?),
+            None => None,
+        };
+        if let Some((path, file)) = config_file {
+            let source = format!("config file {}", path.display());
+            if let Some(server) = file.server {
+                config.apply_server(&server, &source)?;
+            }
+            if let Some(port) = file.port {
+                config.server_port = port;
+            }
+            if let Some(timeout_ms) = file.connect_timeout_ms {
+                config.connect_timeout = timeout_from_millis(timeout_ms, &source)?;
+            }
+            if let Some(transport_error) = file.transport_error {
+                config.transport_error = cuda_error(transport_error, &source)?;
+            }
+            if let Some(attempts) = file.reconnect_attempts {
+                config.reconnect_attempts = attempts;
+            }
+            if let Some(backoff_ms) = file.reconnect_backoff_ms {
+                config.reconnect_backoff = Duration::from_millis(backoff_ms);
+            }
+            if let Some(max_connections) = file.max_connections {
+                config.max_connections = positive(max_connections, &source)?;
+            }
+            if let Some(chunk_size) = file.memcpy_chunk_size {
+                config.memcpy_chunk_size = chunk_size_in_range(chunk_size, &source)?;
+            }
+        }
+
+        if let Some(server) = env(SERVER_ENV) {
+            config.apply_server(&server, &format!("environment variable {}", SERVER_ENV))?;
+        }
+        if let Some(port) = env(PORT_ENV) {
+            config.server_port = parse_env(PORT_ENV, &port)?;
+        }
+        if let Some(timeout_ms) = env(CONNECT_TIMEOUT_MS_ENV) {
+            let source = format!("environment variable {}", CONNECT_TIMEOUT_MS_ENV);
+            config.connect_timeout = timeout_from_millis(parse_env(CONNECT_TIMEOUT_MS_ENV, &timeout_ms)?, &source)?;
+        }
+        if let Some(transport_error) = env(TRANSPORT_ERROR_ENV) {
+            let source = format!("environment variable {}", TRANSPORT_ERROR_ENV);
+            config.transport_error = cuda_error(parse_env(TRANSPORT_ERROR_ENV, &transport_error)?, &source)?;
+        }
+        if let Some(attempts) = env(RECONNECT_ATTEMPTS_ENV) {
+            config.reconnect_attempts = parse_env(RECONNECT_ATTEMPTS_ENV, &attempts)?;
+        }
+        if let Some(backoff_ms) = env(RECONNECT_BACKOFF_MS_ENV) {
+            config.reconnect_backoff = Duration::from_millis(parse_env(RECONNECT_BACKOFF_MS_ENV, &backoff_ms)?);
+        }
+        if let Some(max_connections) = env(MAX_CONNECTIONS_ENV) {
+            let source = format!("environment variable {}", MAX_CONNECTIONS_ENV);
+            config.max_connections = positive(parse_env(MAX_CONNECTIONS_ENV, &max_connections)?, &source)?;
+        }
+        if let Some(chunk_size) = env(MEMCPY_CHUNK_SIZE_ENV) {
+            let source = format!("environment variable {}", MEMCPY_CHUNK_SIZE_ENV);
+            config.memcpy_chunk_size = chunk_size_in_range(parse_env(MEMCPY_CHUNK_SIZE_ENV, &chunk_size)?, &source)?;
+        }
+
+        Ok(config)
+    }
+
+    /// Accepts `host`, `host:port`, `[ipv6]` and `[ipv6]:port`.
+    fn apply_server(&mut self, value: &str, source: &str) -> Result<(), ConfigError> {
+        let invalid = |reason: &str| ConfigError::InvalidValue {
+            source: source.to_string(),
+            value: value.to_string(),
+            reason: reason.to_string(),
+        };
+
+        let (host, port) = if let Some(rest) = value.strip_prefix('[') {
+            let (host, after) = rest.split_once(']').ok_or_else(|| invalid("missing closing ']'"))?;
+            match after {
+                "" => (host, None),
+                _ => (host, Some(after.strip_prefix(':').ok_or_else(|| invalid("expected ':' after ']'"))?)),
+            }
+        } else {
+            match value.rsplit_once(':') {
+                // More than one colon without brackets is a bare IPv6 address.
+                Some((host, _)) if host.contains(':') => (value, None),
+                Some((host, port)) => (host, Some(port)),
+                None => (value, None),
+            }
+        };
+
+        if host.is_empty() {
+            return Err(invalid("empty host"));
+        }
+        if let Some(port) = port {
+            self.server_port = port.parse().map_err(|_| invalid("port must be a number between 0 and 65535"))?;
+        }
+        self.server_host = host.to_string();
+        Ok(())
+    }
+}
+
+fn read_config_file(path: &Path) -> Result<(PathBuf, ConfigFile), ConfigError> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|error| ConfigError::ReadFile { path: path.to_path_buf(), error })?;
+    let file = toml::from_str(&text)
+        .map_err(|error| ConfigError::ParseFile { path: path.to_path_buf(), error })?;
+    Ok((path.to_path_buf(), file))
+}
+
+fn parse_env<T: FromStr>(name: &str, value: &str) -> Result<T, ConfigError> {
+    value.trim().parse().map_err(|_| ConfigError::InvalidValue {
+        source: format!("environment variable {}", name),
+        value: value.to_string(),
+        reason: format!("expected {}", std::any::type_name::<T>()),
+    })
+}
+
+fn timeout_from_millis(millis: u64, source: &str) -> Result<Duration, ConfigError> {
+    if millis == 0 {
+        return Err(ConfigError::InvalidValue {
+            source: source.to_string(),
+            value: millis.to_string(),
+            reason: "connect timeout must be positive".to_string(),
+        });
+    }
+    Ok(Duration::from_millis(millis))
+}
+
+fn positive(value: u32, source: &str) -> Result<u32, ConfigError> {
+    if value == 0 {
+        return Err(ConfigError::InvalidValue {
+            source: source.to_string(),
+            value: value.to_string(),
+            reason: "must be positive".to_string(),
+        });
+    }
+    Ok(value)
+}
+
+/// An error result, as functions returning success would leave their out parameters unwritten.
+fn cuda_error(value: CUresult, source: &str) -> Result<CUresult, ConfigError> {
+    if value == CUDA_SUCCESS {
+        return Err(ConfigError::InvalidValue {
+            source: source.to_string(),
+            value: value.to_string(),
+            reason: "must be a CUDA error, not CUDA_SUCCESS".to_string(),
+        });
+    }
+    Ok(value)
+}
+
+fn chunk_size_in_range(value: u32, source: &str) -> Result<u32, ConfigError> {
+    if !(1..=MAX_MEMCPY_CHUNK_SIZE).contains(&value) {
+        return Err(ConfigError::InvalidValue {
+            source: source.to_string(),
+            value: value.to_string(),
+            reason: format!("must be between 1 and {}", MAX_MEMCPY_CHUNK_SIZE),
+        });
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn load(vars: &[(&str, &str)]) -> Result<ClientConfig, ConfigError> {
+        let vars: HashMap<String, String> = vars.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        ClientConfig::load_from(|name| vars.get(name).cloned())
+    }
+
+    #[test]
+    fn env_overrides() {
+        let config = load(&[(SERVER_ENV, "gpu-box:2000"), (CONNECT_TIMEOUT_MS_ENV, "250"), (TRANSPORT_ERROR_ENV, "3")]).unwrap();
+        assert_eq!(config.server_host, "gpu-box");
+        assert_eq!(config.server_port, 2000);
+        assert_eq!(config.connect_timeout, Duration::from_millis(250));
+        assert_eq!(config.transport_error, 3);
+
+        let config = load(&[(SERVER_ENV, "[::1]"), (PORT_ENV, "3000")]).unwrap();
+        assert_eq!(config.server_host, "::1");
+        assert_eq!(config.server_port, 3000);
+
+        let config = load(&[(RECONNECT_ATTEMPTS_ENV, "0"), (RECONNECT_BACKOFF_MS_ENV, "20")]).unwrap();
+        assert_eq!(config.reconnect_attempts, 0);
+        assert_eq!(config.reconnect_backoff, Duration::from_millis(20));
+
+        let config = load(&[(MEMCPY_CHUNK_SIZE_ENV, "4096")]).unwrap();
+        assert_eq!(config.memcpy_chunk_size, 4096);
+    }
+
+    #[test]
+    fn invalid_values() {
+        assert!(matches!(load(&[(PORT_ENV, "http")]), Err(ConfigError::InvalidValue { .. })));
+        assert!(matches!(load(&[(SERVER_ENV, "host:99999")]), Err(ConfigError::InvalidValue { .. })));
+        assert!(matches!(load(&[(CONNECT_TIMEOUT_MS_ENV, "0")]), Err(ConfigError::InvalidValue { .. })));
+        assert!(matches!(load(&[(MAX_CONNECTIONS_ENV, "0")]), Err(ConfigError::InvalidValue { .. })));
+        assert!(matches!(load(&[(MEMCPY_CHUNK_SIZE_ENV, "0")]), Err(ConfigError::InvalidValue { .. })));
+        assert!(matches!(load(&[(MEMCPY_CHUNK_SIZE_ENV, "1073741824")]), Err(ConfigError::InvalidValue { .. })));
+        assert!(matches!(load(&[(CONFIG_FILE_ENV, "/nonexistent/client.toml")]), Err(ConfigError::ReadFile { .. })));
+        assert!(matches!(load(&[(TRANSPORT_ERROR_ENV, "0")]), Err(ConfigError::InvalidValue { .. })));
+
+        let path = std::env::temp_dir().join(format!("cuda-over-ip-client-{}.toml", std::process::id()));
+        std::fs::write(&path, "transport_error = 0\n").unwrap();
+        let result = load(&[(CONFIG_FILE_ENV, path.to_str().unwrap())]);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(ConfigError::InvalidValue { .. })));
+    }
+}
@@ -0,0 +1,450 @@
+// Generated by parser_wip from parser_wip/functions.yaml, do not edit.
+
+#![allow(non_snake_case, unused_imports)]
+use byteorder::{BigEndian, NativeEndian, ReadBytesExt};
+use std::io::Read;
+use cuda_over_ip_common::RPC;
+use cuda_over_ip_common::cuda::{CUresult, CUDA_ERROR_INVALID_VALUE, CUDA_SUCCESS};
+use cuda_over_ip_common::handle::Handle;
+use cuda_over_ip_common::wire::{to_wire, Wire};
+use crate::non_generated::{self, array_as_u8_slice, as_u8_slice, ptr_as_u8_slice};
+#[no_mangle]
+pub unsafe extern "C" fn cuDriverGetVersion(driverVersion: *mut i32) -> CUresult {
+    if driverVersion.is_null() {
+        return CUDA_ERROR_INVALID_VALUE;
+    }
+    non_generated::call(
+        RPC::cuDriverGetVersion,
+        Vec::new(),
+        |reply| {
+            let result = reply.read_i32::<BigEndian>()?;
+            if result == CUDA_SUCCESS {
+                reply.read_exact(ptr_as_u8_slice(driverVersion))?;
+            }
+            Ok(result)
+        },
+    )
+}
+#[no_mangle]
+pub unsafe extern "C" fn cuInit(Flags: u32) -> CUresult {
+    non_generated::call(
+        RPC::cuInit,
+        [as_u8_slice(&Flags)].concat(),
+        |reply| reply.read_i32::<BigEndian>(),
+    )
+}
+#[no_mangle]
+pub unsafe extern "C" fn cuCtxSynchronize() -> CUresult {
+    non_generated::call(
+        RPC::cuCtxSynchronize,
+        Vec::new(),
+        |reply| reply.read_i32::<BigEndian>(),
+    )
+}
+#[no_mangle]
+pub unsafe extern "C" fn cuDeviceGetCount(count: *mut i32) -> CUresult {
+    if count.is_null() {
+        return CUDA_ERROR_INVALID_VALUE;
+    }
+    non_generated::call(
+        RPC::cuDeviceGetCount,
+        Vec::new(),
+        |reply| {
+            let result = reply.read_i32::<BigEndian>()?;
+            if result == CUDA_SUCCESS {
+                reply.read_exact(ptr_as_u8_slice(count))?;
+            }
+            Ok(result)
+        },
+    )
+}
+#[no_mangle]
+pub unsafe extern "C" fn cuDeviceGet(device: *mut i32, ordinal: i32) -> CUresult {
+    if device.is_null() {
+        return CUDA_ERROR_INVALID_VALUE;
+    }
+    non_generated::call(
+        RPC::cuDeviceGet,
+        [as_u8_slice(&ordinal)].concat(),
+        |reply| {
+            let result = reply.read_i32::<BigEndian>()?;
+            if result == CUDA_SUCCESS {
+                reply.read_exact(ptr_as_u8_slice(device))?;
+            }
+            Ok(result)
+        },
+    )
+}
+#[no_mangle]
+pub unsafe extern "C" fn cuDeviceGetName(name: *mut i8, len: i32, dev: i32) -> CUresult {
+    let Ok(name_length) = usize::try_from(len) else {
+        return CUDA_ERROR_INVALID_VALUE;
+    };
+    if name.is_null() {
+        return CUDA_ERROR_INVALID_VALUE;
+    }
+    non_generated::call(
+        RPC::cuDeviceGetName,
+        [as_u8_slice(&len), as_u8_slice(&dev)].concat(),
+        |reply| {
+            let result = reply.read_i32::<BigEndian>()?;
+            if result == CUDA_SUCCESS {
+                reply.read_exact(array_as_u8_slice(name, name_length))?;
+            }
+            Ok(result)
+        },
+    )
+}
+#[no_mangle]
+pub unsafe extern "C" fn cuDeviceGetUuid(
+    uuid: *mut cuda_over_ip_common::CUuuid_st,
+    dev: i32,
+) -> CUresult {
+    if uuid.is_null() {
+        return CUDA_ERROR_INVALID_VALUE;
+    }
+    non_generated::call(
+        RPC::cuDeviceGetUuid,
+        [as_u8_slice(&dev)].concat(),
+        |reply| {
+            let result = reply.read_i32::<BigEndian>()?;
+            if result == CUDA_SUCCESS {
+                *uuid = Wire::read_from(reply)?;
+            }
+            Ok(result)
+        },
+    )
+}
+#[no_mangle]
+pub unsafe extern "C" fn cuDeviceGetProperties(
+    prop: *mut cuda_over_ip_common::CUdevprop_st,
+    dev: i32,
+) -> CUresult {
+    if prop.is_null() {
+        return CUDA_ERROR_INVALID_VALUE;
+    }
+    non_generated::call(
+        RPC::cuDeviceGetProperties,
+        [as_u8_slice(&dev)].concat(),
+        |reply| {
+            let result = reply.read_i32::<BigEndian>()?;
+            if result == CUDA_SUCCESS {
+                *prop = Wire::read_from(reply)?;
+            }
+            Ok(result)
+        },
+    )
+}
+#[no_mangle]
+pub unsafe extern "C" fn cuCtxCreate_v2(
+    pctx: *mut *mut std::ffi::c_void,
+    flags: u32,
+    dev: i32,
+) -> CUresult {
+    if pctx.is_null() {
+        return CUDA_ERROR_INVALID_VALUE;
+    }
+    non_generated::call(
+        RPC::cuCtxCreate_v2,
+        [as_u8_slice(&flags), as_u8_slice(&dev)].concat(),
+        |reply| {
+            let result = reply.read_i32::<BigEndian>()?;
+            if result == CUDA_SUCCESS {
+                *pctx = Handle::from_u64(reply.read_u64::<NativeEndian>()?);
+            }
+            Ok(result)
+        },
+    )
+}
+#[no_mangle]
+pub unsafe extern "C" fn cuCtxDestroy_v2(ctx: *mut std::ffi::c_void) -> CUresult {
+    non_generated::call(
+        RPC::cuCtxDestroy_v2,
+        [as_u8_slice(&ctx.to_u64())].concat(),
+        |reply| reply.read_i32::<BigEndian>(),
+    )
+}
+#[no_mangle]
+pub unsafe extern "C" fn cuCtxSetCurrent(ctx: *mut std::ffi::c_void) -> CUresult {
+    non_generated::call(
+        RPC::cuCtxSetCurrent,
+        [as_u8_slice(&ctx.to_u64())].concat(),
+        |reply| reply.read_i32::<BigEndian>(),
+    )
+}
+#[no_mangle]
+pub unsafe extern "C" fn cuCtxGetCurrent(pctx: *mut *mut std::ffi::c_void) -> CUresult {
+    if pctx.is_null() {
+        return CUDA_ERROR_INVALID_VALUE;
+    }
+    non_generated::call(
+        RPC::cuCtxGetCurrent,
+        Vec::new(),
+        |reply| {
+            let result = reply.read_i32::<BigEndian>()?;
+            if result == CUDA_SUCCESS {
+                *pctx = Handle::from_u64(reply.read_u64::<NativeEndian>()?);
+            }
+            Ok(result)
+        },
+    )
+}
+#[no_mangle]
+pub unsafe extern "C" fn cuCtxGetDevice(device: *mut i32) -> CUresult {
+    if device.is_null() {
+        return CUDA_ERROR_INVALID_VALUE;
+    }
+    non_generated::call(
+        RPC::cuCtxGetDevice,
+        Vec::new(),
+        |reply| {
+            let result = reply.read_i32::<BigEndian>()?;
+            if result == CUDA_SUCCESS {
+                reply.read_exact(ptr_as_u8_slice(device))?;
+            }
+            Ok(result)
+        },
+    )
+}
+#[no_mangle]
+pub unsafe extern "C" fn cuDevicePrimaryCtxRetain(
+    pctx: *mut *mut std::ffi::c_void,
+    dev: i32,
+) -> CUresult {
+    if pctx.is_null() {
+        return CUDA_ERROR_INVALID_VALUE;
+    }
+    non_generated::call(
+        RPC::cuDevicePrimaryCtxRetain,
+        [as_u8_slice(&dev)].concat(),
+        |reply| {
+            let result = reply.read_i32::<BigEndian>()?;
+            if result == CUDA_SUCCESS {
+                *pctx = Handle::from_u64(reply.read_u64::<NativeEndian>()?);
+            }
+            Ok(result)
+        },
+    )
+}
+#[no_mangle]
+pub unsafe extern "C" fn cuDevicePrimaryCtxRelease_v2(dev: i32) -> CUresult {
+    non_generated::call(
+        RPC::cuDevicePrimaryCtxRelease_v2,
+        [as_u8_slice(&dev)].concat(),
+        |reply| reply.read_i32::<BigEndian>(),
+    )
+}
+#[no_mangle]
+pub unsafe extern "C" fn cuStreamCreate(
+    phStream: *mut *mut std::ffi::c_void,
+    Flags: u32,
+) -> CUresult {
+    if phStream.is_null() {
+        return CUDA_ERROR_INVALID_VALUE;
+    }
+    non_generated::call(
+        RPC::cuStreamCreate,
+        [as_u8_slice(&Flags)].concat(),
+        |reply| {
+            let result = reply.read_i32::<BigEndian>()?;
+            if result == CUDA_SUCCESS {
+                *phStream = Handle::from_u64(reply.read_u64::<NativeEndian>()?);
+            }
+            Ok(result)
+        },
+    )
+}
+#[no_mangle]
+pub unsafe extern "C" fn cuStreamDestroy_v2(hStream: *mut std::ffi::c_void) -> CUresult {
+    non_generated::call(
+        RPC::cuStreamDestroy_v2,
+        [as_u8_slice(&hStream.to_u64())].concat(),
+        |reply| reply.read_i32::<BigEndian>(),
+    )
+}
+#[no_mangle]
+pub unsafe extern "C" fn cuStreamSynchronize(
+    hStream: *mut std::ffi::c_void,
+) -> CUresult {
+    non_generated::call(
+        RPC::cuStreamSynchronize,
+        [as_u8_slice(&hStream.to_u64())].concat(),
+        |reply| reply.read_i32::<BigEndian>(),
+    )
+}
+#[no_mangle]
+pub unsafe extern "C" fn cuEventCreate(
+    phEvent: *mut *mut std::ffi::c_void,
+    Flags: u32,
+) -> CUresult {
+    if phEvent.is_null() {
+        return CUDA_ERROR_INVALID_VALUE;
+    }
+    non_generated::call(
+        RPC::cuEventCreate,
+        [as_u8_slice(&Flags)].concat(),
+        |reply| {
+            let result = reply.read_i32::<BigEndian>()?;
+            if result == CUDA_SUCCESS {
+                *phEvent = Handle::from_u64(reply.read_u64::<NativeEndian>()?);
+            }
+            Ok(result)
+        },
+    )
+}
+#[no_mangle]
+pub unsafe extern "C" fn cuEventDestroy_v2(hEvent: *mut std::ffi::c_void) -> CUresult {
+    non_generated::call(
+        RPC::cuEventDestroy_v2,
+        [as_u8_slice(&hEvent.to_u64())].concat(),
+        |reply| reply.read_i32::<BigEndian>(),
+    )
+}
+#[no_mangle]
+pub unsafe extern "C" fn cuEventRecord(
+    hEvent: *mut std::ffi::c_void,
+    hStream: *mut std::ffi::c_void,
+) -> CUresult {
+    non_generated::call_async(
+        RPC::cuEventRecord,
+        [as_u8_slice(&hEvent.to_u64()), as_u8_slice(&hStream.to_u64())].concat(),
+    )
+}
+#[no_mangle]
+pub unsafe extern "C" fn cuEventSynchronize(hEvent: *mut std::ffi::c_void) -> CUresult {
+    non_generated::call(
+        RPC::cuEventSynchronize,
+        [as_u8_slice(&hEvent.to_u64())].concat(),
+        |reply| reply.read_i32::<BigEndian>(),
+    )
+}
+#[no_mangle]
+pub unsafe extern "C" fn cuMemGetInfo_v2(
+    free: *mut usize,
+    total: *mut usize,
+) -> CUresult {
+    if free.is_null() || total.is_null() {
+        return CUDA_ERROR_INVALID_VALUE;
+    }
+    non_generated::call(
+        RPC::cuMemGetInfo_v2,
+        Vec::new(),
+        |reply| {
+            let result = reply.read_i32::<BigEndian>()?;
+            if result == CUDA_SUCCESS {
+                reply.read_exact(ptr_as_u8_slice(free))?;
+                reply.read_exact(ptr_as_u8_slice(total))?;
+            }
+            Ok(result)
+        },
+    )
+}
+#[no_mangle]
+pub unsafe extern "C" fn cuMemAlloc_v2(dptr: *mut u64, bytesize: usize) -> CUresult {
+    if dptr.is_null() {
+        return CUDA_ERROR_INVALID_VALUE;
+    }
+    non_generated::call(
+        RPC::cuMemAlloc_v2,
+        [as_u8_slice(&bytesize)].concat(),
+        |reply| {
+            let result = reply.read_i32::<BigEndian>()?;
+            if result == CUDA_SUCCESS {
+                *dptr = Handle::from_u64(reply.read_u64::<NativeEndian>()?);
+            }
+            Ok(result)
+        },
+    )
+}
+#[no_mangle]
+pub unsafe extern "C" fn cuMemFree_v2(dptr: u64) -> CUresult {
+    non_generated::call(
+        RPC::cuMemFree_v2,
+        [as_u8_slice(&dptr.to_u64())].concat(),
+        |reply| reply.read_i32::<BigEndian>(),
+    )
+}
+#[no_mangle]
+pub unsafe extern "C" fn cuMemAllocPitch_v2(
+    dptr: *mut u64,
+    pPitch: *mut usize,
+    WidthInBytes: usize,
+    Height: usize,
+    ElementSizeBytes: u32,
+) -> CUresult {
+    if dptr.is_null() || pPitch.is_null() {
+        return CUDA_ERROR_INVALID_VALUE;
+    }
+    non_generated::call(
+        RPC::cuMemAllocPitch_v2,
+        [
+            as_u8_slice(&WidthInBytes),
+            as_u8_slice(&Height),
+            as_u8_slice(&ElementSizeBytes),
+        ]
+            .concat(),
+        |reply| {
+            let result = reply.read_i32::<BigEndian>()?;
+            if result == CUDA_SUCCESS {
+                *dptr = Handle::from_u64(reply.read_u64::<NativeEndian>()?);
+                reply.read_exact(ptr_as_u8_slice(pPitch))?;
+            }
+            Ok(result)
+        },
+    )
+}
+#[no_mangle]
+pub unsafe extern "C" fn cuMemsetD8_v2(dstDevice: u64, uc: u8, N: usize) -> CUresult {
+    non_generated::call(
+        RPC::cuMemsetD8_v2,
+        [as_u8_slice(&dstDevice.to_u64()), as_u8_slice(&uc), as_u8_slice(&N)].concat(),
+        |reply| reply.read_i32::<BigEndian>(),
+    )
+}
+#[no_mangle]
+pub unsafe extern "C" fn cuMemsetD32_v2(dstDevice: u64, ui: u32, N: usize) -> CUresult {
+    non_generated::call(
+        RPC::cuMemsetD32_v2,
+        [as_u8_slice(&dstDevice.to_u64()), as_u8_slice(&ui), as_u8_slice(&N)].concat(),
+        |reply| reply.read_i32::<BigEndian>(),
+    )
+}
+#[no_mangle]
+pub unsafe extern "C" fn cuFuncGetParamInfo(
+    func: *mut std::ffi::c_void,
+    paramIndex: usize,
+    paramOffset: *mut usize,
+    paramSize: *mut usize,
+) -> CUresult {
+    if paramOffset.is_null() || paramSize.is_null() {
+        return CUDA_ERROR_INVALID_VALUE;
+    }
+    non_generated::call(
+        RPC::cuFuncGetParamInfo,
+        [as_u8_slice(&func.to_u64()), as_u8_slice(&paramIndex)].concat(),
+        |reply| {
+            let result = reply.read_i32::<BigEndian>()?;
+            if result == CUDA_SUCCESS {
+                reply.read_exact(ptr_as_u8_slice(paramOffset))?;
+                reply.read_exact(ptr_as_u8_slice(paramSize))?;
+            }
+            Ok(result)
+        },
+    )
+}
+#[no_mangle]
+pub unsafe extern "C" fn cuStreamWaitEvent(
+    hStream: *mut std::ffi::c_void,
+    hEvent: *mut std::ffi::c_void,
+    Flags: u32,
+) -> CUresult {
+    non_generated::call_async(
+        RPC::cuStreamWaitEvent,
+        [
+            as_u8_slice(&hStream.to_u64()),
+            as_u8_slice(&hEvent.to_u64()),
+            as_u8_slice(&Flags),
+        ]
+            .concat(),
+    )
+}
@@ -0,0 +1,256 @@
+//! The extent of the module images passed to `cuModuleLoadData` and friends, which have no size parameter.
+//!
+//! A cubin is an ELF file whose headers give the extent of its contents. A fatbin starts with a header giving
+//! its size, and is often passed wrapped by the structure nvcc generates. Anything else is NUL-terminated PTX.
+//!
+//! The `.nv.info.<kernel>` sections of a cubin give the layout of the parameters of its kernels.
+
+use byteorder::{ByteOrder, LittleEndian};
+use std::collections::HashMap;
+use cuda_over_ip_common::cuda::{CUresult, CUDA_ERROR_INVALID_IMAGE};
+use cuda_over_ip_common::frame::MAX_PAYLOAD_LENGTH;
+
+const ELF_MAGIC: &[u8] = b"\x7fELF";
+const ELF_CLASS_64: u8 = 2;
+const ELF_DATA_LITTLE_ENDIAN: u8 = 1;
+const ELF_HEADER_SIZE: usize = 64;
+const SECTION_TYPE_NO_BITS: u32 = 8;
+const SECTION_HEADER_SIZE: usize = 64;
+
+const NV_INFO_PREFIX: &[u8] = b".nv.info.";
+/// The format of the attributes followed by as many bytes of value as given in their header.
+const NV_INFO_FORMAT_SIZED: u8 = 0x04;
+/// The attribute with the index, the offset and the size of a parameter.
+const NV_INFO_KPARAM_INFO: u8 = 0x17;
+
+const FATBIN_MAGIC: u32 = 0xBA55_ED50;
+const FATBIN_HEADER_SIZE: usize = 16;
+/// The magic of `__fatBinC_Wrapper_t`, which points to the fatbin.
+const FATBIN_WRAPPER_MAGIC: u32 = 0x4662_43B1;
+
+/// Images must fit in a request, with room for the other arguments.
+const MAX_IMAGE_SIZE: usize = MAX_PAYLOAD_LENGTH as usize - 4096;
+
+/// The bytes of the image `image` points to, that of the fatbin if it points to a fatbin wrapper.
+///
+/// # Safety
+/// `image` must point to a cubin, a fatbin, a fatbin wrapper or NUL-terminated PTX.
+pub(crate) unsafe fn image<'a>(image: *const u8) -> Result<&'a [u8], CUresult> {
+    let magic = std::slice::from_raw_parts(image, 4);
+    let (image, size) = if magic == ELF_MAGIC {
+        (image, elf_size(image)?)
+    } else if LittleEndian::read_u32(magic) == FATBIN_MAGIC {
+        (image, fatbin_size(image)?)
+    } else if LittleEndian::read_u32(magic) == FATBIN_WRAPPER_MAGIC {
+        let fatbin = std::ptr::read_unaligned(image.add(8) as *const *const u8);
+        if fatbin.is_null() || LittleEndian::read_u32(std::slice::from_raw_parts(fatbin, 4)) != FATBIN_MAGIC {
+            return Err(CUDA_ERROR_INVALID_IMAGE);
+        }
+        (fatbin, fatbin_size(fatbin)?)
+    } else {
+        // The terminating NUL is part of the image.
+        (image, std::ffi::CStr::from_ptr(image.cast()).to_bytes_with_nul().len())
+    };
+    match size <= MAX_IMAGE_SIZE {
+        true => Ok(std::slice::from_raw_parts(image, size)),
+        false => Err(CUDA_ERROR_INVALID_IMAGE),
+    }
+}
+
+/// The end of the last of the headers, the program header table, the section header table and the sections.
+unsafe fn elf_size(image: *const u8) -> Result<usize, CUresult> {
+    let header = std::slice::from_raw_parts(image, ELF_HEADER_SIZE);
+    // Cubins are 64-bit little-endian.
+    if header[4] != ELF_CLASS_64 || header[5] != ELF_DATA_LITTLE_ENDIAN {
+        return Err(CUDA_ERROR_INVALID_IMAGE);
+    }
+    // The program header table and the section header table, with their entries.
+    let mut size = ELF_HEADER_SIZE;
+    let mut table = |offset: u64, entry_size: u16, count: u16| -> Result<Vec<&[u8]>, CUresult> {
+        let table_size = entry_size as usize * count as usize;
+        size = size.max(extent(offset, table_size as u64)?);
+        let table = std::slice::from_raw_parts(image.add(offset as usize), table_size);
+        Ok(table.chunks_exact(entry_size.max(1) as usize).collect())
+    };
+    let program_headers = table(LittleEndian::read_u64(&header[0x20..]),
+                                LittleEndian::read_u16(&header[0x36..]),
+                                LittleEndian::read_u16(&header[0x38..]))?;
+    let section_headers = table(LittleEndian::read_u64(&header[0x28..]),
+                                LittleEndian::read_u16(&header[0x3A..]),
+                                LittleEndian::read_u16(&header[0x3C..]))?;
+
+    for entry in program_headers {
+        size = size.max(extent(LittleEndian::read_u64(&entry[0x08..]), LittleEndian::read_u64(&entry[0x20..]))?);
+    }
+    for entry in section_headers {
+        if LittleEndian::read_u32(&entry[0x04..]) != SECTION_TYPE_NO_BITS {
+            size = size.max(extent(LittleEndian::read_u64(&entry[0x18..]), LittleEndian::read_u64(&entry[0x20..]))?);
+        }
+    }
+    Ok(size)
+}
+
+fn extent(offset: u64, size: u64) -> Result<usize, CUresult> {
+    match offset.checked_add(size) {
+        Some(end) if end <= MAX_IMAGE_SIZE as u64 => Ok(end as usize),
+        _ => Err(CUDA_ERROR_INVALID_IMAGE),
+    }
+}
+
+unsafe fn fatbin_size(fatbin: *const u8) -> Result<usize, CUresult> {
+    let header = std::slice::from_raw_parts(fatbin, FATBIN_HEADER_SIZE);
+    let header_size = LittleEndian::read_u16(&header[6..]) as u64;
+    extent(header_size, LittleEndian::read_u64(&header[8..]))
+}
+
+/// The offset and the size of every parameter of the kernels of a cubin, by kernel name. Empty for other images,
+/// and for cubins whose metadata can't be read.
+pub(crate) fn kernel_params(image: &[u8]) -> HashMap<String, Vec<(usize, usize)>> {
+    match image.starts_with(ELF_MAGIC) {
+        true => nv_info(image).unwrap_or_default(),
+        false => HashMap::new(),
+    }
+}
+
+fn nv_info(elf: &[u8]) -> Option<HashMap<String, Vec<(usize, usize)>>> {
+    let section_table = LittleEndian::read_u64(elf.get(0x28..0x30)?) as usize;
+    let section_count = LittleEndian::read_u16(elf.get(0x3C..0x3E)?) as usize;
+    let section_names = LittleEndian::read_u16(elf.get(0x3E..0x40)?) as usize;
+    let section = |index: usize| -> Option<(u32, &[u8])> {
+        let header = elf.get(section_table + index * SECTION_HEADER_SIZE..)?.get(..SECTION_HEADER_SIZE)?;
+        let offset = LittleEndian::read_u64(&header[0x18..]) as usize;
+        let size = LittleEndian::read_u64(&header[0x20..]) as usize;
+        Some((LittleEndian::read_u32(header), elf.get(offset..offset.checked_add(size)?)?))
+    };
+    let (_, names) = section(section_names)?;
+
+    let mut kernels = HashMap::new();
+    for index in 0..section_count {
+        let (name_offset, mut attributes) = section(index)?;
+        let name = names.get(name_offset as usize..)?;
+        let name = &name[..name.iter().position(|byte| *byte == 0)?];
+        let Some(kernel) = name.strip_prefix(NV_INFO_PREFIX) else {
+            continue;
+        };
+        // Attributes have a header of the format, the attribute and a 16-bit value or size.
+        let mut params = Vec::new();
+        while attributes.len() >= 4 {
+            let (format, attribute, value) = (attributes[0], attributes[1], LittleEndian::read_u16(&attributes[2..]));
+            attributes = &attributes[4..];
+            if format != NV_INFO_FORMAT_SIZED {
+                continue;
+            }
+            let data = attributes.get(..value as usize)?;
+            attributes = &attributes[value as usize..];
+            if attribute == NV_INFO_KPARAM_INFO && data.len() >= 12 {
+                let ordinal = LittleEndian::read_u16(&data[4..]) as usize;
+                let offset = LittleEndian::read_u16(&data[6..]) as usize;
+                let size = (LittleEndian::read_u32(&data[8..]) >> 18 & 0x3FFF) as usize;
+                params.push((ordinal, offset, size));
+            }
+        }
+        params.sort_unstable();
+        // Leave out kernels whose parameters are not all described.
+        if params.iter().enumerate().all(|(index, (ordinal, _, _))| index == *ordinal) {
+            let params = params.into_iter().map(|(_, offset, size)| (offset, size)).collect();
+            kernels.insert(String::from_utf8_lossy(kernel).into_owned(), params);
+        }
+    }
+    Some(kernels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A cubin of `sections` sections of `section_size` bytes each, the last of which takes no space.
+    fn elf(sections: usize, section_size: usize) -> Vec<u8> {
+        let section_table = ELF_HEADER_SIZE + sections * section_size;
+        let mut elf = vec![0; section_table + sections * SECTION_HEADER_SIZE];
+        elf[..6].copy_from_slice(b"\x7fELF\x02\x01");
+        LittleEndian::write_u64(&mut elf[0x28..], section_table as u64);
+        LittleEndian::write_u16(&mut elf[0x3A..], SECTION_HEADER_SIZE as u16);
+        LittleEndian::write_u16(&mut elf[0x3C..], sections as u16);
+        for section in 0..sections {
+            let entry = &mut elf[section_table + section * SECTION_HEADER_SIZE..];
+            let (section_type, size) = match section + 1 == sections {
+                true => (SECTION_TYPE_NO_BITS, section_size * 1000),
+                false => (1, section_size),
+            };
+            LittleEndian::write_u32(&mut entry[0x04..], section_type);
+            LittleEndian::write_u64(&mut entry[0x18..], (ELF_HEADER_SIZE + section * section_size) as u64);
+            LittleEndian::write_u64(&mut entry[0x20..], size as u64);
+        }
+        elf
+    }
+
+    fn fatbin(payload: &[u8]) -> Vec<u8> {
+        let mut fatbin = vec![0; FATBIN_HEADER_SIZE];
+        LittleEndian::write_u32(&mut fatbin, FATBIN_MAGIC);
+        LittleEndian::write_u16(&mut fatbin[6..], FATBIN_HEADER_SIZE as u16);
+        LittleEndian::write_u64(&mut fatbin[8..], payload.len() as u64);
+        [fatbin.as_slice(), payload].concat()
+    }
+
+    #[test]
+    fn image_sizes() {
+        unsafe {
+            let ptx = b".version 8.0\0garbage";
+            assert_eq!(image(ptx.as_ptr()).unwrap(), b".version 8.0\0");
+
+            let fatbin = [fatbin(&[1, 0, 2]), vec![0xff; 8]].concat();
+            assert_eq!(image(fatbin.as_ptr()).unwrap(), &fatbin[..FATBIN_HEADER_SIZE + 3]);
+            let mut wrapper = vec![0; 24];
+            LittleEndian::write_u32(&mut wrapper, FATBIN_WRAPPER_MAGIC);
+            wrapper[8..16].copy_from_slice(&(fatbin.as_ptr() as usize).to_ne_bytes());
+            assert_eq!(image(wrapper.as_ptr()).unwrap(), &fatbin[..FATBIN_HEADER_SIZE + 3]);
+
+            // The section header table at the end, the section without bits doesn't extend the image.
+            let elf = [elf(2, 100), vec![0xff; 8]].concat();
+            assert_eq!(image(elf.as_ptr()).unwrap().len(), elf.len() - 8);
+
+            let mut elf = elf;
+            LittleEndian::write_u64(&mut elf[0x28..], u64::MAX - 10);
+            assert_eq!(image(elf.as_ptr()), Err(CUDA_ERROR_INVALID_IMAGE));
+            elf[4] = 1;
+            assert_eq!(image(elf.as_ptr()), Err(CUDA_ERROR_INVALID_IMAGE));
+        }
+    }
+
+    #[test]
+    fn nv_info_params() {
+        // Sections: none, the names, the parameters of `scale` in reverse order and an attribute of another format.
+        let names = b"\0.shstrtab\0.nv.info.scale\0.text.scale\0";
+        let kparam_info = |ordinal: u16, offset: u16, size: u32| {
+            let mut attribute = vec![NV_INFO_FORMAT_SIZED, NV_INFO_KPARAM_INFO, 12, 0, 0, 0, 0, 0];
+            attribute.extend_from_slice(&ordinal.to_le_bytes());
+            attribute.extend_from_slice(&offset.to_le_bytes());
+            attribute.extend_from_slice(&(size << 18 | 0x1f000).to_le_bytes());
+            attribute
+        };
+        let nv_info = [vec![0x03, 0x1b, 0xff, 0], kparam_info(1, 8, 4), kparam_info(0, 0, 8)].concat();
+        let sections: [(u32, &[u8]); 4] = [(0, b""), (1, names), (11, &nv_info), (26, b"code")];
+
+        let mut elf = vec![0; ELF_HEADER_SIZE];
+        elf[..6].copy_from_slice(b"\x7fELF\x02\x01");
+        let mut headers = Vec::new();
+        for (name, data) in sections {
+            let mut header = [0; SECTION_HEADER_SIZE];
+            LittleEndian::write_u32(&mut header, name);
+            LittleEndian::write_u64(&mut header[0x18..], elf.len() as u64);
+            LittleEndian::write_u64(&mut header[0x20..], data.len() as u64);
+            headers.extend_from_slice(&header);
+            elf.extend_from_slice(data);
+        }
+        let section_table = elf.len() as u64;
+        LittleEndian::write_u64(&mut elf[0x28..], section_table);
+        LittleEndian::write_u16(&mut elf[0x3A..], SECTION_HEADER_SIZE as u16);
+        LittleEndian::write_u16(&mut elf[0x3C..], sections.len() as u16);
+        LittleEndian::write_u16(&mut elf[0x3E..], 1);
+        elf.extend_from_slice(&headers);
+
+        assert_eq!(kernel_params(&elf), HashMap::from([("scale".to_string(), vec![(0, 8), (8, 4)])]));
+        assert!(kernel_params(&elf[..elf.len() - 1]).is_empty());
+        assert!(kernel_params(b".version 8.0\0").is_empty());
+    }
+}
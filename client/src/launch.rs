@@ -0,0 +1,164 @@
+//! Launching kernels, whose parameters are passed without their sizes.
+//!
+//! The client learns the offset and the size of every parameter of a kernel from the `.nv.info` sections of the cubin
+//! it was loaded from, or else from `cuFuncGetParamInfo` on the server. Without them, the parameters can only be
+//! passed in one buffer with `extra`. The request has the layout, or `u32::MAX` if it is unknown, followed by the
+//! parameters in one buffer. The server translates the 64-bit parameters that are
+//! IDs of device pointers, and looks the layout up itself if the client did not know it.
+#![allow(non_snake_case)]
+
+use crate::generated::cuFuncGetParamInfo;
+use crate::image::kernel_params;
+use crate::non_generated::{self, array_as_u8_slice, as_u8_slice};
+use cuda_over_ip_common::RPC;
+use cuda_over_ip_common::cuda::*;
+use cuda_over_ip_common::handle::Handle;
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::{c_uint, c_void};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// The offset and the size of every parameter of a kernel.
+type Layout = Arc<Vec<(usize, usize)>>;
+
+/// The most parameters a kernel can have: the driver takes up to 32764 bytes of them, one byte each at least.
+const MAX_PARAMS: usize = 32764;
+
+/// The layouts of the kernels of the modules loaded from cubins, by the ID of the module and the name of the kernel.
+static MODULE_LAYOUTS: Mutex<BTreeMap<u64, HashMap<String, Layout>>> = Mutex::new(BTreeMap::new());
+
+/// The IDs of the modules of the functions and their layouts, `None` until learned from the server, by the ID of the
+/// function.
+static FUNCTION_LAYOUTS: Mutex<BTreeMap<u64, (u64, Option<Layout>)>> = Mutex::new(BTreeMap::new());
+
+/// Keeps the layouts of the kernels of a module loaded from `image`.
+pub(crate) fn module_loaded(module: *mut c_void, image: &[u8]) {
+    let layouts = kernel_params(image).into_iter().map(|(name, layout)| (name, Arc::new(layout))).collect();
+    // The ID of an unloaded module may be reused.
+    lock(&MODULE_LAYOUTS).insert(module.to_u64(), layouts);
+}
+
+/// Keeps the layout of a function got from a module, if known.
+pub(crate) fn function_got(function: *mut c_void, module: *mut c_void, name: &str) {
+    let layout = lock(&MODULE_LAYOUTS).get(&module.to_u64()).and_then(|layouts| layouts.get(name).cloned());
+    lock(&FUNCTION_LAYOUTS).insert(function.to_u64(), (module.to_u64(), layout));
+}
+
+/// Forgets the layouts of an unloaded module and of its functions.
+pub(crate) fn module_unloaded(module: *mut c_void) {
+    let module = module.to_u64();
+    lock(&MODULE_LAYOUTS).remove(&module);
+    lock(&FUNCTION_LAYOUTS).retain(|_, (function_module, _)| *function_module != module);
+}
+
+/// The layout of the parameters of the function, if known without asking the server.
+fn known_layout(function: *mut c_void) -> Option<Layout> {
+    lock(&FUNCTION_LAYOUTS).get(&function.to_u64()).and_then(|(_, layout)| layout.clone())
+}
+
+/// The layout of the parameters of the function, asking the server if it is not known yet. `None` if the driver does
+/// not know it either, e.g. for kernels of cubins before CUDA 12.4 or without `cuFuncGetParamInfo`.
+unsafe fn layout(function: *mut c_void) -> Option<Layout> {
+    if let Some(layout) = known_layout(function) {
+        return Some(layout);
+    }
+    let mut layout = Vec::new();
+    while layout.len() <= MAX_PARAMS {
+        let (mut offset, mut size) = (0, 0);
+        match cuFuncGetParamInfo(function, layout.len(), &mut offset, &mut size) {
+            CUDA_SUCCESS => layout.push((offset, size)),
+            // Past the last parameter.
+            CUDA_ERROR_INVALID_VALUE => {
+                let layout = Arc::new(layout);
+                // Only for the functions got from a module, which are forgotten when it is unloaded.
+                if let Some((_, known_layout)) = lock(&FUNCTION_LAYOUTS).get_mut(&function.to_u64()) {
+                    *known_layout = Some(layout.clone());
+                }
+                return Some(layout);
+            }
+            _ => return None,
+        }
+    }
+    None
+}
+
+/// Returns without waiting for the launch, whose error, e.g. of the configuration, a later call returns.
+#[no_mangle]
+pub unsafe extern "C" fn cuLaunchKernel(f: *mut c_void,
+                                        gridDimX: c_uint,
+                                        gridDimY: c_uint,
+                                        gridDimZ: c_uint,
+                                        blockDimX: c_uint,
+                                        blockDimY: c_uint,
+                                        blockDimZ: c_uint,
+                                        sharedMemBytes: c_uint,
+                                        hStream: *mut c_void,
+                                        kernelParams: *mut *mut c_void,
+                                        extra: *mut *mut c_void) -> CUresult {
+    if !kernelParams.is_null() && !extra.is_null() {
+        return CUDA_ERROR_INVALID_VALUE;
+    }
+    let (layout, buffer) = if !kernelParams.is_null() {
+        // Without the sizes of the parameters, they can only be passed in `extra`, which has them in one buffer.
+        let Some(layout) = layout(f) else {
+            return CUDA_ERROR_NOT_SUPPORTED;
+        };
+        let mut buffer = vec![0; layout.iter().map(|(offset, size)| offset + size).max().unwrap_or(0)];
+        for (index, (offset, size)) in layout.iter().enumerate() {
+            let param = *kernelParams.add(index);
+            if param.is_null() {
+                return CUDA_ERROR_INVALID_VALUE;
+            }
+            buffer[*offset..offset + size].copy_from_slice(std::slice::from_raw_parts(param.cast::<u8>(), *size));
+        }
+        (Some(layout), buffer)
+    } else if !extra.is_null() {
+        let buffer = match extra_buffer(extra) {
+            Ok(buffer) => buffer.to_vec(),
+            Err(result) => return result,
+        };
+        // The buffer is complete without the layout, which only tells the server where the parameters are.
+        (known_layout(f), buffer)
+    } else {
+        (Some(Layout::default()), Vec::new())
+    };
+
+    let layout_arguments = match &layout {
+        Some(layout) => {
+            let entries: Vec<usize> = layout.iter().flat_map(|(offset, size)| [*offset, *size]).collect();
+            [as_u8_slice(&(layout.len() as u32)), array_as_u8_slice(entries.as_ptr(), entries.len())].concat()
+        }
+        None => as_u8_slice(&u32::MAX).to_vec(),
+    };
+    let dimensions = [gridDimX, gridDimY, gridDimZ, blockDimX, blockDimY, blockDimZ, sharedMemBytes];
+    non_generated::call_async(
+        RPC::cuLaunchKernel,
+        [as_u8_slice(&f.to_u64()), as_u8_slice(&hStream.to_u64()), as_u8_slice(&dimensions),
+         &layout_arguments, as_u8_slice(&buffer.len()), &buffer].concat(),
+    )
+}
+
+/// The buffer `extra` points to, a list of markers and values ending with `CU_LAUNCH_PARAM_END`.
+unsafe fn extra_buffer<'a>(extra: *mut *mut c_void) -> Result<&'a [u8], CUresult> {
+    let (mut buffer, mut buffer_size) = (None, None);
+    let mut entry = extra;
+    while *entry as usize != CU_LAUNCH_PARAM_END {
+        let value = *entry.add(1);
+        match *entry as usize {
+            CU_LAUNCH_PARAM_BUFFER_POINTER => buffer = Some(value.cast::<u8>()),
+            CU_LAUNCH_PARAM_BUFFER_SIZE if !value.is_null() => buffer_size = Some(*value.cast::<usize>()),
+            _ => return Err(CUDA_ERROR_INVALID_VALUE),
+        }
+        entry = entry.add(2);
+    }
+    match (buffer, buffer_size) {
+        (Some(buffer), Some(size)) if !buffer.is_null() || size == 0 => Ok(match size {
+            0 => &[],
+            _ => std::slice::from_raw_parts(buffer, size),
+        }),
+        _ => Err(CUDA_ERROR_INVALID_VALUE),
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
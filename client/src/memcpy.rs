@@ -0,0 +1,77 @@
+//! Copies between host and device memory, sent in chunks of the configured size.
+//!
+//! The request of a chunk has the device address of the chunk, its length, and for copies to the device its bytes.
+//! The reply has the CUDA result, and for copies to the host the bytes of the chunk if the copy succeeded.
+//!
+//! The copies in progress can be cancelled from another thread with [`cuda_over_ip_cancel_copies`].
+//!
+//! 2D copies are sent as their struct in one request, and only within the device.
+#![allow(non_snake_case)]
+
+use crate::non_generated::{self, as_u8_slice};
+use byteorder::{BigEndian, ReadBytesExt};
+use cuda_over_ip_common::{CUDA_MEMCPY2D_st, RPC};
+use cuda_over_ip_common::cuda::{CUresult, CUDA_ERROR_INVALID_VALUE, CUDA_ERROR_NOT_SUPPORTED, CUDA_SUCCESS, CU_MEMORYTYPE_HOST};
+use cuda_over_ip_common::wire::to_wire;
+use std::ffi::c_void;
+use std::io::Read;
+
+#[no_mangle]
+pub unsafe extern "C" fn cuMemcpyHtoD_v2(dstDevice: u64, srcHost: *const c_void, ByteCount: usize) -> CUresult {
+    // The addresses of the chunks are past the start, which must not overflow.
+    if (srcHost.is_null() && ByteCount > 0) || dstDevice.checked_add(ByteCount as u64).is_none() {
+        return CUDA_ERROR_INVALID_VALUE;
+    }
+    non_generated::call_chunked(
+        RPC::cuMemcpyHtoD_v2,
+        ByteCount,
+        |offset, length| {
+            let chunk = match length {
+                0 => &[][..],
+                _ => std::slice::from_raw_parts(srcHost.cast::<u8>().add(offset), length),
+            };
+            [as_u8_slice(&(dstDevice + offset as u64)), as_u8_slice(&length), chunk].concat()
+        },
+        |_, _, reply| reply.read_i32::<BigEndian>(),
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuMemcpyDtoH_v2(dstHost: *mut c_void, srcDevice: u64, ByteCount: usize) -> CUresult {
+    // The addresses of the chunks are past the start, which must not overflow.
+    if (dstHost.is_null() && ByteCount > 0) || srcDevice.checked_add(ByteCount as u64).is_none() {
+        return CUDA_ERROR_INVALID_VALUE;
+    }
+    non_generated::call_chunked(
+        RPC::cuMemcpyDtoH_v2,
+        ByteCount,
+        |offset, length| [as_u8_slice(&(srcDevice + offset as u64)), as_u8_slice(&length)].concat(),
+        |offset, length, reply| {
+            let result = reply.read_i32::<BigEndian>()?;
+            if result == CUDA_SUCCESS && length > 0 {
+                reply.read_exact(std::slice::from_raw_parts_mut(dstHost.cast::<u8>().add(offset), length))?;
+            }
+            Ok(result)
+        },
+    )
+}
+
+/// The server can't reach the memory of the client, so copies from or to host memory are not supported.
+#[no_mangle]
+pub unsafe extern "C" fn cuMemcpy2D_v2(pCopy: *const CUDA_MEMCPY2D_st) -> CUresult {
+    let Some(copy) = pCopy.as_ref() else {
+        return CUDA_ERROR_INVALID_VALUE;
+    };
+    if copy.srcMemoryType == CU_MEMORYTYPE_HOST || copy.dstMemoryType == CU_MEMORYTYPE_HOST {
+        return CUDA_ERROR_NOT_SUPPORTED;
+    }
+    non_generated::call(RPC::cuMemcpy2D_v2, to_wire(copy), |reply| reply.read_i32::<BigEndian>())
+}
+
+/// Not a function of the driver: cancels the copies between host and device memory in progress, e.g. when the
+/// application is interrupted. They return `CUDA_ERROR_NOT_PERMITTED` instead of sending their next chunk, the
+/// chunks sent before are copied. Copies started later are not cancelled.
+#[no_mangle]
+pub extern "C" fn cuda_over_ip_cancel_copies() {
+    non_generated::cancel_chunked_calls();
+}
@@ -0,0 +1,153 @@
+//! Loading modules, whose images are sent to the server, and getting their functions.
+//!
+//! The request to load a module ends with the hash of the image, see [`cuda_over_ip_common::image`]. The client sends
+//! it again with the length of the image and its bytes, see [`crate::image`], if the server has not cached the image.
+//! The JIT options of `cuModuleLoadDataEx` come before, with their values. Only options whose values are not
+//! pointers are supported, see [`CU_JIT_SCALAR_OPTIONS`], and the log buffers: the driver writes the logs to buffers
+//! of the server, which are sent back after the option values, before the ID of the module.
+#![allow(non_snake_case)]
+
+use crate::image::image;
+use crate::launch;
+use crate::non_generated::{self, array_as_u8_slice, as_u8_slice};
+use byteorder::{BigEndian, NativeEndian, ReadBytesExt};
+use cuda_over_ip_common::RPC;
+use cuda_over_ip_common::cuda::*;
+use cuda_over_ip_common::handle::Handle;
+use cuda_over_ip_common::image::{image_hash, IMAGE_BYTES, IMAGE_BY_HASH, IMAGE_MISSING};
+use std::ffi::{c_char, c_uint, c_void, CStr};
+use std::io::Read;
+
+#[no_mangle]
+pub unsafe extern "C" fn cuModuleLoadData(module: *mut *mut c_void, image: *const c_void) -> CUresult {
+    load(RPC::cuModuleLoadData, module, image, Vec::new(), |_| Ok(()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuModuleLoadFatBinary(module: *mut *mut c_void, fatCubin: *const c_void) -> CUresult {
+    load(RPC::cuModuleLoadFatBinary, module, fatCubin, Vec::new(), |_| Ok(()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuModuleLoadDataEx(module: *mut *mut c_void,
+                                            image: *const c_void,
+                                            numOptions: c_uint,
+                                            options: *const i32,
+                                            optionValues: *mut *mut c_void) -> CUresult {
+    let length = numOptions as usize;
+    if length > 0 && (options.is_null() || optionValues.is_null()) {
+        return CUDA_ERROR_INVALID_VALUE;
+    }
+    let (options, option_values) = match length {
+        0 => (&[][..], &mut [][..]),
+        _ => (std::slice::from_raw_parts(options, length), std::slice::from_raw_parts_mut(optionValues, length)),
+    };
+    // The server passes buffers of its own for the logs.
+    let mut values = Vec::with_capacity(length);
+    for (option, value) in options.iter().zip(option_values.iter()) {
+        values.push(match *option {
+            CU_JIT_INFO_LOG_BUFFER | CU_JIT_ERROR_LOG_BUFFER => 0,
+            option if CU_JIT_SCALAR_OPTIONS.contains(&option) => *value as u64,
+            _ => return CUDA_ERROR_NOT_SUPPORTED,
+        });
+    }
+
+    let arguments = [as_u8_slice(&numOptions), array_as_u8_slice(options.as_ptr(), length),
+                     array_as_u8_slice(values.as_ptr(), length)].concat();
+    load(RPC::cuModuleLoadDataEx, module, image, arguments, |reply| {
+        for (option, value) in options.iter().zip(option_values.iter_mut()) {
+            let returned = reply.read_u64::<NativeEndian>()?;
+            if !matches!(*option, CU_JIT_INFO_LOG_BUFFER | CU_JIT_ERROR_LOG_BUFFER) {
+                *value = returned as *mut c_void;
+            }
+        }
+        for (option, value) in options.iter().zip(option_values.iter()) {
+            if matches!(*option, CU_JIT_INFO_LOG_BUFFER | CU_JIT_ERROR_LOG_BUFFER) {
+                let log_length = reply.read_u64::<NativeEndian>()? as usize;
+                if log_length > 0 {
+                    reply.read_exact(std::slice::from_raw_parts_mut(value.cast::<u8>(), log_length))?;
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Sends the arguments followed by the hash of the image, then by the image if the server does not have it.
+/// `read_jit_outputs` reads what precedes the module in the reply.
+unsafe fn load(rpc: RPC,
+               module: *mut *mut c_void,
+               image_pointer: *const c_void,
+               arguments: Vec<u8>,
+               mut read_jit_outputs: impl FnMut(&mut &[u8]) -> std::io::Result<()>) -> CUresult {
+    if module.is_null() || image_pointer.is_null() {
+        return CUDA_ERROR_INVALID_VALUE;
+    }
+    let image = match image(image_pointer.cast()) {
+        Ok(image) => image,
+        Err(result) => return result,
+    };
+    let hash = image_hash(image);
+    for send_image in [false, true] {
+        let image_argument = match send_image {
+            false => [&[IMAGE_BY_HASH][..], &hash].concat(),
+            true => [&[IMAGE_BYTES][..], as_u8_slice(&image.len()), image].concat(),
+        };
+        let mut missing = false;
+        let result = non_generated::call(
+            rpc,
+            [arguments.as_slice(), &image_argument].concat(),
+            |reply| {
+                if reply.read_u8()? == IMAGE_MISSING {
+                    missing = true;
+                    return Ok(CUDA_SUCCESS);
+                }
+                let result = reply.read_i32::<BigEndian>()?;
+                read_jit_outputs(reply)?;
+                if result == CUDA_SUCCESS {
+                    *module = Handle::from_u64(reply.read_u64::<NativeEndian>()?);
+                    launch::module_loaded(*module, image);
+                }
+                Ok(result)
+            },
+        );
+        if !missing {
+            return result;
+        }
+    }
+    // The server has the image it got with the request.
+    CUDA_ERROR_UNKNOWN
+}
+
+/// Forgets the layouts of the kernels of the module once unloaded, see `launch.rs`.
+#[no_mangle]
+pub unsafe extern "C" fn cuModuleUnload(hmod: *mut c_void) -> CUresult {
+    non_generated::call(RPC::cuModuleUnload, as_u8_slice(&hmod.to_u64()).to_vec(), |reply| {
+        let result = reply.read_i32::<BigEndian>()?;
+        if result == CUDA_SUCCESS {
+            launch::module_unloaded(hmod);
+        }
+        Ok(result)
+    })
+}
+
+/// The name is sent with its length, without the NUL.
+#[no_mangle]
+pub unsafe extern "C" fn cuModuleGetFunction(hfunc: *mut *mut c_void, hmod: *mut c_void, name: *const c_char) -> CUresult {
+    if hfunc.is_null() || name.is_null() {
+        return CUDA_ERROR_INVALID_VALUE;
+    }
+    let name = CStr::from_ptr(name).to_bytes();
+    non_generated::call(
+        RPC::cuModuleGetFunction,
+        [as_u8_slice(&hmod.to_u64()), as_u8_slice(&name.len()), name].concat(),
+        |reply| {
+            let result = reply.read_i32::<BigEndian>()?;
+            if result == CUDA_SUCCESS {
+                *hfunc = Handle::from_u64(reply.read_u64::<NativeEndian>()?);
+                launch::function_got(*hfunc, hmod, &String::from_utf8_lossy(name));
+            }
+            Ok(result)
+        },
+    )
+}
@@ -1,108 +1,686 @@
-use std::io::{BufReader, BufWriter, IoSlice, IoSliceMut, Read, Write};
-use std::net::TcpStream;
-use std::ops::Deref;
-use std::process::exit;
-use std::sync::Mutex;
-use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
-use prost::Message;
-use static_init::dynamic;
-use cuda_over_ip_protocol::protocol::{FuncCall, FuncResult};
-
-#[dynamic(drop)]
-pub(crate) static mut WRITER_AND_READER: Mutex<(BufWriter<TcpStream>, BufReader<TcpStream>)> = {
-    let read_stream = match TcpStream::connect("127.0.0.1:19999") {
-        Ok(s) => s,
-        Err(e) => {
-            eprintln!("Error connecting to server: {}", e);
-            exit(1);
-        }
-    };
-    read_stream.set_nodelay(true).expect("set_nodelay call failed");
-    let write_stream = match read_stream.try_clone() {
-        Ok(s) => s,
-        Err(e) => {
-            eprintln!("Error connecting to server: {}", e);
-            exit(1);
-        }
+use std::cell::RefCell;
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{BufReader, BufWriter, Write};
+use std::net::{Shutdown, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, OnceLock};
+use std::thread;
+use std::time::Duration;
+use byteorder::{BigEndian, ReadBytesExt};
+use cuda_over_ip_common::RPC;
+use cuda_over_ip_common::cuda::*;
+use cuda_over_ip_common::frame::{Frame, HEADER_SIZE, MAX_IN_FLIGHT_REQUESTS, MAX_MEMCPY_CHUNKS_IN_FLIGHT, STATUS_MALFORMED_REQUEST, STATUS_SERVER_ERROR, STATUS_UNSUPPORTED_RPC};
+use cuda_over_ip_common::handshake::{Capabilities, ClientHello, CAPABILITY_BATCH, HandshakeError, ServerHello, SessionToken, PROTOCOL_VERSION};
+use crate::config::ClientConfig;
+
+/// Created on the first intercepted call, so loading the library doesn't touch the network.
+static CLIENT: OnceLock<Client> = OnceLock::new();
+
+thread_local! {
+    /// The connection the calling thread uses. The server executes the requests of a connection in order,
+    /// so the calls of a thread keep their order, also when the thread doesn't wait for their replies.
+    static CONNECTION: RefCell<Option<Arc<Connection>>> = const { RefCell::new(None) };
+
+    static BATCH: RefCell<Batch> = const {
+        RefCell::new(Batch { connection: None, calls: Vec::new(), bytes: 0, last_sent: None })
     };
+}
 
-    Mutex::new((BufWriter::new(write_stream), BufReader::new(read_stream)))
-};
+/// Limits of a batch of asynchronous calls, which is sent when reaching either.
+const MAX_BATCH_CALLS: usize = 256;
+const MAX_BATCH_BYTES: usize = 1024 * 1024;
 
-pub(crate) unsafe fn ptr_as_u8_slice<T: Sized>(p: *const T) -> &'static mut [u8] {
-    std::slice::from_raw_parts_mut(p as *mut u8, size_of::<T>())
+/// Upper bound of the delay between reconnect attempts.
+const MAX_RECONNECT_BACKOFF: Duration = Duration::from_secs(5);
+
+enum SessionState {
+    /// Connect on the next call. The initial state, kept until the first connect succeeds.
+    NotConnected,
+    Connected(SessionToken),
+    /// The session is lost for good, calls return the error.
+    Failed(CUresult),
 }
 
-pub(crate) unsafe fn as_u8_slice<T: Sized>(p: &T) -> &[u8] {
-    std::slice::from_raw_parts((p as *const T) as *const u8, size_of::<T>())
+struct Client {
+    config: ClientConfig,
+    session: Mutex<SessionState>,
+    /// Connections to the server, all attached to the same server-side session.
+    /// Threads get a connection of their own while there are fewer than `max_connections`, then share them.
+    connections: Mutex<Vec<Arc<Connection>>>,
+    next_shared_connection: AtomicUsize,
+    /// Request IDs are unique within the session, so a request resent after reconnecting is recognized.
+    next_request_id: AtomicU64,
+    /// The first error of a call that didn't wait for its reply, returned by the next call like CUDA returns errors
+    /// of earlier asynchronous calls. An error CUDA keeps, see [`is_sticky`], is returned by every later call.
+    deferred_error: Mutex<Option<CUresult>>,
+    /// Incremented to cancel the chunked calls in progress, see [`cancel_chunked_calls`].
+    chunked_calls_cancelled: AtomicU64,
 }
 
-pub(crate) fn send_call(buf_writer: &mut BufWriter<TcpStream>,
-                        io_slices: Vec<IoSlice>) {
-    let write_result = buf_writer.write_vectored(&io_slices)
-        .and_then(|_| buf_writer.flush());
-    // TODO check completeness of write
-    if let Err(e) = write_result {
-        eprintln!("Error sending call: {}", e);
-        exit(1);
+impl Client {
+    fn new() -> Self {
+        let (config, session) = match ClientConfig::load() {
+            Ok(c) => (c, SessionState::NotConnected),
+            Err(e) => {
+                eprintln!("cuda-over-ip: invalid client configuration: {}", e);
+                (ClientConfig::default(), SessionState::Failed(CUDA_ERROR_NOT_INITIALIZED))
+            }
+        };
+        Client {
+            config,
+            session: Mutex::new(session),
+            connections: Mutex::new(Vec::new()),
+            next_shared_connection: AtomicUsize::new(0),
+            next_request_id: AtomicU64::new(0),
+            deferred_error: Mutex::new(None),
+            chunked_calls_cancelled: AtomicU64::new(0),
+        }
+    }
+
+    /// Performs a call, see [`call`].
+    fn call(&'static self,
+            rpc: RPC,
+            payload: Vec<u8>,
+            read_reply: impl FnOnce(&mut &[u8]) -> std::io::Result<CUresult>) -> CUresult {
+        let result = self.prepare_call()
+            .and_then(|connection| self.send(&connection, rpc, payload));
+        match result {
+            Ok(reply_receiver) => self.receive(rpc, reply_receiver, read_reply),
+            Err(code) => code,
+        }
+    }
+
+    /// Performs a call per chunk, see [`call_chunked`].
+    fn call_chunked(&'static self,
+                    rpc: RPC,
+                    length: usize,
+                    mut make_payload: impl FnMut(usize, usize) -> Vec<u8>,
+                    mut read_reply: impl FnMut(usize, usize, &mut &[u8]) -> std::io::Result<CUresult>) -> CUresult {
+        let cancelled = self.chunked_calls_cancelled.load(Ordering::Relaxed);
+        let connection = match self.prepare_call() {
+            Ok(c) => c,
+            Err(code) => return code,
+        };
+        let chunk_size = self.config.memcpy_chunk_size as usize;
+        let mut chunks = (0..length.max(1)).step_by(chunk_size).map(|offset| (offset, chunk_size.min(length - offset)));
+        let mut in_flight = VecDeque::new();
+        loop {
+            // Sending waits for the oldest chunk once enough are in flight, which bounds the memory either side
+            // holds, and a failed chunk cancels the chunks not sent yet.
+            let result = match in_flight.len() < MAX_MEMCPY_CHUNKS_IN_FLIGHT {
+                true => match chunks.next() {
+                    // The chunks in flight are still executed, their replies are dropped.
+                    Some(_) if self.chunked_calls_cancelled.load(Ordering::Relaxed) != cancelled =>
+                        return CUDA_ERROR_NOT_PERMITTED,
+                    Some((offset, chunk_length)) => {
+                        match self.send(&connection, rpc, make_payload(offset, chunk_length)) {
+                            Ok(reply_receiver) => in_flight.push_back((offset, chunk_length, reply_receiver)),
+                            Err(code) => return code,
+                        }
+                        continue;
+                    }
+                    None => match in_flight.pop_front() {
+                        Some((offset, chunk_length, reply_receiver)) => self.receive(rpc, reply_receiver,
+                            |payload| read_reply(offset, chunk_length, payload)),
+                        None => return CUDA_SUCCESS,
+                    },
+                },
+                false => {
+                    let (offset, chunk_length, reply_receiver) = in_flight.pop_front().unwrap();
+                    self.receive(rpc, reply_receiver, |payload| read_reply(offset, chunk_length, payload))
+                }
+            };
+            if result != CUDA_SUCCESS {
+                return result;
+            }
+        }
+    }
+
+    /// The connection to send a call on, after the replies to the calls queued before. Their errors are returned by
+    /// this call instead of executing it, so a call the caller sees fail has no effects on the server.
+    fn prepare_call(&'static self) -> Result<Arc<Connection>, CUresult> {
+        BATCH.with(|batch| batch.borrow_mut().wait_for_replies(&self.next_request_id))?;
+        if let Some(code) = self.take_deferred_error() {
+            return Err(code);
+        }
+        self.connection()
+    }
+
+    fn send(&self, connection: &Connection, rpc: RPC, payload: Vec<u8>) -> Result<ReplyReceiver, CUresult> {
+        let (reply_sender, reply_receiver) = mpsc::sync_channel(1);
+        let request = |request_id| Frame::request(request_id, rpc as i32, payload);
+        connection.send(&self.next_request_id, request, Some(reply_sender))?;
+        Ok(reply_receiver)
+    }
+
+    /// Waits for the reply and decodes it with `read_reply`.
+    fn receive(&self,
+               rpc: RPC,
+               reply_receiver: ReplyReceiver,
+               read_reply: impl FnOnce(&mut &[u8]) -> std::io::Result<CUresult>) -> CUresult {
+        let reply = match reply_receiver.recv() {
+            Ok(Ok(reply)) => reply,
+            Ok(Err(code)) => return code,
+            Err(_) => return self.config.transport_error,
+        };
+
+        if let Some(status) = reply.error_status() {
+            return error_status_to_cuda_error(status);
+        }
+
+        let mut payload = reply.payload.as_slice();
+        match read_reply(&mut payload) {
+            Ok(_) if !payload.is_empty() => self.give_up(rpc, std::io::Error::new(
+                std::io::ErrorKind::InvalidData, format!("{} unexpected trailing bytes in reply", payload.len()))),
+            Ok(result) => result,
+            Err(e) => self.give_up(rpc, e),
+        }
+    }
+
+    /// Queues a call without waiting for the reply, see [`call_async`].
+    fn call_async(&'static self, rpc: RPC, payload: Vec<u8>) -> CUresult {
+        if let Some(code) = self.take_deferred_error() {
+            return code;
+        }
+        let result = self.connection().and_then(|connection| {
+            let call = Frame::request(0, rpc as i32, payload);
+            BATCH.with(|batch| {
+                let mut batch = batch.borrow_mut();
+                match connection.capabilities & CAPABILITY_BATCH {
+                    0 => batch.send(&self.next_request_id, connection, |request_id| call.with_request_id(request_id)),
+                    _ => batch.push(&self.next_request_id, connection, call),
+                }
+            })
+        });
+        match result {
+            Ok(()) => CUDA_SUCCESS,
+            Err(code) => code,
+        }
+    }
+
+    /// The connection of the calling thread, establishing the session first if necessary.
+    fn connection(&'static self) -> Result<Arc<Connection>, CUresult> {
+        let session_token = self.session_token()?;
+        if let Some(connection) = CONNECTION.with(|c| c.borrow().clone()) {
+            return Ok(connection);
+        }
+
+        let mut connections = self.connections();
+        let opened = match (connections.len() as u32) < self.config.max_connections {
+            true => match Connection::open(self, Some(session_token), connections.len() as u32) {
+                Ok((connection, _)) => {
+                    connections.push(connection.clone());
+                    Some(connection)
+                }
+                // The thread shares a connection instead, like once there are `max_connections`.
+                Err(e) if !connections.is_empty() => {
+                    eprintln!("cuda-over-ip: error opening additional connection to server, sharing one: {}", e);
+                    None
+                }
+                Err(e) => {
+                    eprintln!("cuda-over-ip: error opening additional connection to server: {}", e);
+                    return Err(self.config.transport_error);
+                }
+            },
+            false => None,
+        };
+        let connection = opened.unwrap_or_else(|| {
+            let i = self.next_shared_connection.fetch_add(1, Ordering::Relaxed);
+            connections[i % connections.len()].clone()
+        });
+        CONNECTION.with(|c| *c.borrow_mut() = Some(connection.clone()));
+        Ok(connection)
+    }
+
+    fn session_token(&'static self) -> Result<SessionToken, CUresult> {
+        let mut session = self.session();
+        match *session {
+            SessionState::Connected(token) => Ok(token),
+            SessionState::Failed(code) => Err(code),
+            SessionState::NotConnected => match Connection::open(self, None, 0) {
+                Ok((connection, token)) => {
+                    *session = SessionState::Connected(token);
+                    self.connections().push(connection.clone());
+                    CONNECTION.with(|c| *c.borrow_mut() = Some(connection));
+                    Ok(token)
+                }
+                Err(e) => {
+                    eprintln!("cuda-over-ip: error connecting to server {}:{}: {}",
+                              self.config.server_host, self.config.server_port, e);
+                    Err(CUDA_ERROR_NOT_INITIALIZED)
+                }
+            },
+        }
+    }
+
+    /// Reconnects the connection in `slot` with exponential backoff and resumes the session.
+    /// Fails the session if that's not possible.
+    fn reconnect(&self, session_token: SessionToken, slot: u32) -> Result<Socket, CUresult> {
+        let mut backoff = self.config.reconnect_backoff;
+        for attempt in 1..=self.config.reconnect_attempts {
+            thread::sleep(backoff);
+            backoff = (backoff * 2).min(MAX_RECONNECT_BACKOFF);
+
+            match Socket::connect(&self.config, Some(session_token), slot) {
+                Ok((socket, _)) => {
+                    eprintln!("cuda-over-ip: reconnected to server, session resumed");
+                    return Ok(socket);
+                }
+                Err(e @ HandshakeError::Io(_)) => {
+                    eprintln!("cuda-over-ip: reconnect attempt {}/{} failed: {}", attempt, self.config.reconnect_attempts, e);
+                }
+                Err(e) => {
+                    eprintln!("cuda-over-ip: cannot resume session: {}", e);
+                    break;
+                }
+            }
+        }
+
+        self.fail(self.config.transport_error);
+        Err(self.config.transport_error)
+    }
+
+    /// The stream can't be trusted after a malformed reply, so the session is dropped.
+    fn give_up(&self, rpc: RPC, error: std::io::Error) -> CUresult {
+        eprintln!("cuda-over-ip: invalid reply from server to {:?}: {}", rpc, error);
+        self.fail(self.config.transport_error);
+        self.config.transport_error
+    }
+
+    /// Fails the session for good, together with the calls waiting for replies.
+    fn fail(&self, code: CUresult) {
+        *self.session() = SessionState::Failed(code);
+        for connection in self.connections().iter() {
+            connection.fail(code);
+        }
+    }
+
+    fn session(&self) -> MutexGuard<'_, SessionState> {
+        self.session.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn connections(&self) -> MutexGuard<'_, Vec<Arc<Connection>>> {
+        self.connections.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn deferred_error(&self) -> MutexGuard<'_, Option<CUresult>> {
+        self.deferred_error.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// The deferred error for a call to return, which is kept for the later calls only if sticky.
+    fn take_deferred_error(&self) -> Option<CUresult> {
+        let mut deferred_error = self.deferred_error();
+        let code = (*deferred_error)?;
+        if !is_sticky(code) {
+            *deferred_error = None;
+        }
+        Some(code)
     }
 }
 
-pub(crate) fn read_result(buf_reader: &mut BufReader<TcpStream>,
-                          mut out_slices: Vec<IoSliceMut>) {
-    // TODO check completeness of read
-    if let Err(e) = buf_reader.read_vectored(&mut out_slices) {
-        eprintln!("Error reading result: {}", e);
-        exit(1);
+/// Sends `payload` as a call of `rpc` and decodes the reply with `read_reply`, which returns the CUDA result.
+///
+/// Transport failures, error replies and malformed replies are logged and turned into CUDA errors,
+/// in which case `read_reply` is not called or its effects must be ignored by the caller.
+/// A dropped connection is reestablished transparently if the session can be resumed.
+pub(crate) fn call(rpc: RPC,
+                   payload: Vec<u8>,
+                   read_reply: impl FnOnce(&mut &[u8]) -> std::io::Result<CUresult>) -> CUresult {
+    CLIENT.get_or_init(Client::new).call(rpc, payload, read_reply)
+}
+
+/// Performs a call of `rpc` per chunk of the `length` bytes of a memory copy, with the payload `make_payload` makes
+/// from the offset and the length of the chunk. `read_reply` decodes the reply to a chunk given the same.
+///
+/// A few chunks are in flight at a time, so the copy streams without either side holding all of it.
+/// The copy stops at the first chunk that fails, whose result is returned. A copy of no bytes is one empty chunk.
+pub(crate) fn call_chunked(rpc: RPC,
+                           length: usize,
+                           make_payload: impl FnMut(usize, usize) -> Vec<u8>,
+                           read_reply: impl FnMut(usize, usize, &mut &[u8]) -> std::io::Result<CUresult>) -> CUresult {
+    CLIENT.get_or_init(Client::new).call_chunked(rpc, length, make_payload, read_reply)
+}
+
+/// Cancels the calls of [`call_chunked`] in progress, which return `CUDA_ERROR_NOT_PERMITTED` instead of sending their
+/// next chunk.
+pub(crate) fn cancel_chunked_calls() {
+    if let Some(client) = CLIENT.get() {
+        client.chunked_calls_cancelled.fetch_add(1, Ordering::Relaxed);
     }
 }
 
-/*pub(crate) fn send_call_and_get_result(buf_writer: &mut BufWriter<TcpStream>,
-                                       buf_reader: &mut BufReader<TcpStream>,
-                                       call: FuncCall) -> FuncResult {
-    send_call(buf_writer, call);
-    read_result(buf_reader)
+/// Queues `payload` as a call of `rpc` and returns without waiting for the reply, for functions CUDA defines
+/// as asynchronous. The queued calls of the thread are sent together before its next [`call`], which waits for their
+/// replies before it is sent. The reply must start with the CUDA result. An error result is returned by the next call
+/// instead of executing it, and by all later ones if sticky.
+pub(crate) fn call_async(rpc: RPC, payload: Vec<u8>) -> CUresult {
+    CLIENT.get_or_init(Client::new).call_async(rpc, payload)
+}
+
+/// Whether CUDA returns the error from every later call, as the context can't be used anymore.
+fn is_sticky(code: CUresult) -> bool {
+    matches!(code, CUDA_ERROR_ILLEGAL_ADDRESS | CUDA_ERROR_LAUNCH_TIMEOUT | CUDA_ERROR_HARDWARE_STACK_ERROR
+        | CUDA_ERROR_ILLEGAL_INSTRUCTION | CUDA_ERROR_MISALIGNED_ADDRESS | CUDA_ERROR_INVALID_ADDRESS_SPACE
+        | CUDA_ERROR_INVALID_PC | CUDA_ERROR_LAUNCH_FAILED)
+}
+
+/// Asynchronous calls of a thread not sent yet. Sent as one request before the next call of the thread
+/// that waits for its reply, when reaching the limits of a batch, or when the thread exits.
+struct Batch {
+    connection: Option<Arc<Connection>>,
+    calls: Vec<Frame>,
+    bytes: usize,
+    /// The connection and the request ID of the last asynchronous calls sent. The replies on a connection come in
+    /// order, so those to the calls before have come with the reply to them.
+    last_sent: Option<(Arc<Connection>, u64)>,
 }
-*/
-/*fn send_call(buf_writer: &mut BufWriter<TcpStream>, call: FuncCall) {
-    fn int(buf_writer: &mut BufWriter<TcpStream>, call: FuncCall) -> std::io::Result<()> {
-        let mut buf = Vec::<u8>::with_capacity(call.encoded_len());
-        call.encode(&mut buf)?;
 
-        buf_writer.write_u32::<BigEndian>(call.encoded_len() as u32)?;
-        buf_writer.write_all(&buf)?;
-        buf_writer.flush()
+impl Batch {
+    fn push(&mut self, next_request_id: &AtomicU64, connection: Arc<Connection>, call: Frame) -> Result<(), CUresult> {
+        let bytes = HEADER_SIZE + call.payload.len();
+        if self.bytes + bytes > MAX_BATCH_BYTES {
+            self.flush(next_request_id)?;
+        }
+        if bytes > MAX_BATCH_BYTES {
+            return self.send(next_request_id, connection, |request_id| call.with_request_id(request_id));
+        }
+
+        self.connection = Some(connection);
+        self.calls.push(call);
+        self.bytes += bytes;
+        if self.calls.len() == MAX_BATCH_CALLS {
+            self.flush(next_request_id)?;
+        }
+        Ok(())
     }
 
-    if let Err(e) = int(buf_writer, call) {
-        eprintln!("Error sending call: {}", e);
-        exit(1);
-    };
+    fn flush(&mut self, next_request_id: &AtomicU64) -> Result<(), CUresult> {
+        let Some(connection) = self.connection.take() else {
+            return Ok(());
+        };
+        let calls = std::mem::take(&mut self.calls);
+        self.bytes = 0;
+        self.send(next_request_id, connection, |request_id| Frame::batch(request_id, &calls))
+    }
+
+    /// Sends asynchronous calls without waiting for the reply.
+    fn send(&mut self,
+            next_request_id: &AtomicU64,
+            connection: Arc<Connection>,
+            make_request: impl FnOnce(u64) -> Frame) -> Result<(), CUresult> {
+        let request_id = connection.send(next_request_id, make_request, None)?;
+        self.last_sent = Some((connection, request_id));
+        Ok(())
+    }
+
+    /// Sends the queued calls and waits for the replies to all calls sent, whose errors are deferred.
+    fn wait_for_replies(&mut self, next_request_id: &AtomicU64) -> Result<(), CUresult> {
+        self.flush(next_request_id)?;
+        match self.last_sent.take() {
+            Some((connection, request_id)) => connection.wait_for_reply(request_id),
+            None => Ok(()),
+        }
+    }
 }
 
-fn read_result(buf_reader: &mut BufReader<TcpStream>) -> FuncResult {
-    fn int(buf_reader: &mut BufReader<TcpStream>) -> std::io::Result<Vec<u8>> {
-        let size = buf_reader.read_u32::<BigEndian>()? as usize;
-        let mut buf = vec![0_u8; size];
-        buf_reader.read_exact(&mut buf)?;
-        Ok(buf)
+impl Drop for Batch {
+    fn drop(&mut self) {
+        if let Some(client) = CLIENT.get() {
+            let _ = self.flush(&client.next_request_id);
+        }
     }
+}
+
+/// Receives the reply to a request, or the error the call fails with if there will be none.
+type ReplySender = SyncSender<Result<Frame, CUresult>>;
+type ReplyReceiver = Receiver<Result<Frame, CUresult>>;
+
+/// A connection to the server shared by the threads using it. Requests are written by the calling threads,
+/// replies are read by a thread of the connection and handed to the callers waiting for them.
+/// That thread also reconnects when the connection is lost and resends the requests left without a reply.
+struct Connection {
+    slot: u32,
+    /// Negotiated in the handshake.
+    capabilities: Capabilities,
+    state: Mutex<ConnectionState>,
+    /// Signalled when a request leaves `in_flight`.
+    request_completed: Condvar,
+}
 
-    match int(buf_reader) {
-        Ok(buf) => {
-            match FuncResult::decode(buf.deref()) {
-                Ok(r) => r,
+struct ConnectionState {
+    writer: BufWriter<TcpStream>,
+    /// Requests without a reply yet, by request ID.
+    in_flight: BTreeMap<u64, InFlightRequest>,
+    failed: Option<CUresult>,
+}
+
+struct InFlightRequest {
+    request: Frame,
+    /// `None` if nobody waits for the reply.
+    reply_sender: Option<ReplySender>,
+}
+
+impl Connection {
+    /// Connects, joining the given session or starting a new one, and starts reading replies.
+    fn open(client: &'static Client, session_token: Option<SessionToken>, slot: u32) -> Result<(Arc<Self>, SessionToken), HandshakeError> {
+        let (socket, session_token) = Socket::connect(&client.config, session_token, slot)?;
+        let connection = Arc::new(Connection {
+            slot,
+            capabilities: socket.capabilities,
+            state: Mutex::new(ConnectionState {
+                writer: socket.writer,
+                in_flight: BTreeMap::new(),
+                failed: None,
+            }),
+            request_completed: Condvar::new(),
+        });
+        let reading_connection = connection.clone();
+        thread::Builder::new()
+            .name(format!("cuda-over-ip-{}", slot))
+            .spawn(move || reading_connection.read_replies(client, session_token, socket.reader))?;
+        Ok((connection, session_token))
+    }
+
+    /// Sends the request made by `make_request` from its request ID, which is returned. `reply_sender` receives the
+    /// reply.
+    fn send(&self,
+            next_request_id: &AtomicU64,
+            make_request: impl FnOnce(u64) -> Frame,
+            reply_sender: Option<ReplySender>) -> Result<u64, CUresult> {
+        let mut state = self.state();
+        while state.failed.is_none() && state.in_flight.len() >= MAX_IN_FLIGHT_REQUESTS {
+            state = self.request_completed.wait(state).unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+        if let Some(code) = state.failed {
+            return Err(code);
+        }
+
+        // Taken under the lock, so the request IDs of a connection increase in the order they are sent.
+        let request = make_request(next_request_id.fetch_add(1, Ordering::Relaxed));
+        if let Err(e) = request.write_to(&mut state.writer).and_then(|_| state.writer.flush()) {
+            eprintln!("cuda-over-ip: connection to server lost sending request {}: {}", request.header.request_id, e);
+            // Wakes up the reading thread, which resends the request after reconnecting.
+            let _ = state.writer.get_ref().shutdown(Shutdown::Both);
+        }
+        let request_id = request.header.request_id;
+        state.in_flight.insert(request_id, InFlightRequest { request, reply_sender });
+        Ok(request_id)
+    }
+
+    /// Waits until the reply to the request came, and was handed to the caller or its error deferred.
+    fn wait_for_reply(&self, request_id: u64) -> Result<(), CUresult> {
+        let mut state = self.state();
+        while state.failed.is_none() && state.in_flight.contains_key(&request_id) {
+            state = self.request_completed.wait(state).unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+        match state.failed {
+            Some(code) => Err(code),
+            None => Ok(()),
+        }
+    }
+
+    fn read_replies(&self, client: &'static Client, session_token: SessionToken, mut reader: BufReader<TcpStream>) {
+        loop {
+            let error = match Frame::read_from(&mut reader) {
+                Ok(reply) => match self.complete(client, reply) {
+                    Ok(()) => continue,
+                    Err(e) => e,
+                },
+                Err(_) if self.state().failed.is_some() => return,
                 Err(e) => {
-                    eprintln!("Error decoding result: {}", e);
-                    exit(1);
+                    if !self.state().in_flight.is_empty() {
+                        eprintln!("cuda-over-ip: connection to server lost: {}", e);
+                    }
+                    match client.reconnect(session_token, self.slot) {
+                        Ok(socket) => {
+                            reader = socket.reader;
+                            self.resend(socket.writer);
+                            continue;
+                        }
+                        Err(_) => return,
+                    }
+                }
+            };
+            eprintln!("cuda-over-ip: invalid reply from server: {}", error);
+            client.fail(client.config.transport_error);
+            return;
+        }
+    }
+
+    /// Hands the reply to the caller waiting for it.
+    fn complete(&self, client: &Client, reply: Frame) -> std::io::Result<()> {
+        let mut state = self.state();
+        let in_flight = match state.in_flight.get(&reply.header.request_id) {
+            Some(in_flight) if reply.is_reply()
+                && in_flight.request.header.rpc_id == reply.header.rpc_id
+                && in_flight.request.is_batch() == reply.is_batch() =>
+                state.in_flight.remove(&reply.header.request_id).unwrap(),
+            _ => return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("unexpected frame {:?}", reply.header),
+            )),
+        };
+        match in_flight.reply_sender {
+            Some(reply_sender) => {
+                drop(state);
+                let _ = reply_sender.send(Ok(reply));
+            }
+            // Deferred before the request leaves `in_flight` for those waiting for the reply.
+            None => {
+                let failure = match reply.is_batch() {
+                    true => reply.batch_failure()?,
+                    false => Some((0, reply)),
+                };
+                if let Some((_, reply)) = failure {
+                    let result = match reply.error_status() {
+                        Some(status) => error_status_to_cuda_error(status),
+                        None => reply.payload.as_slice().read_i32::<BigEndian>()?,
+                    };
+                    if result != CUDA_SUCCESS {
+                        eprintln!("cuda-over-ip: asynchronous call of RPC {} failed with {}", reply.header.rpc_id, result);
+                        client.deferred_error().get_or_insert(result);
+                    }
                 }
+                drop(state);
             }
         }
+        self.request_completed.notify_all();
+        Ok(())
+    }
 
-        Err(e) => {
-            eprintln!("Error reading result: {}", e);
-            exit(1);
+    /// Continues on a new socket, resending the requests left without a reply in their original order.
+    fn resend(&self, writer: BufWriter<TcpStream>) {
+        let mut state = self.state();
+        state.writer = writer;
+        let ConnectionState { writer, in_flight, .. } = &mut *state;
+        let result = in_flight.values().try_for_each(|in_flight| in_flight.request.write_to(writer));
+        if let Err(e) = result.and_then(|_| writer.flush()) {
+            eprintln!("cuda-over-ip: error resending requests: {}", e);
+            let _ = writer.get_ref().shutdown(Shutdown::Both);
         }
     }
+
+    /// Fails the calls waiting for replies and the calls to come.
+    fn fail(&self, code: CUresult) {
+        let mut state = self.state();
+        state.failed = Some(code);
+        for (_, in_flight) in std::mem::take(&mut state.in_flight) {
+            if let Some(reply_sender) = in_flight.reply_sender {
+                let _ = reply_sender.send(Err(code));
+            }
+        }
+        let _ = state.writer.get_ref().shutdown(Shutdown::Both);
+        self.request_completed.notify_all();
+    }
+
+    fn state(&self) -> MutexGuard<'_, ConnectionState> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+struct Socket {
+    writer: BufWriter<TcpStream>,
+    reader: BufReader<TcpStream>,
+    capabilities: Capabilities,
+}
+
+impl Socket {
+    /// Connects and performs the handshake, joining the given session or starting a new one.
+    fn connect(config: &ClientConfig, session_token: Option<SessionToken>, slot: u32) -> Result<(Self, SessionToken), HandshakeError> {
+        let mut last_error = None;
+        for addr in (config.server_host.as_str(), config.server_port).to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, config.connect_timeout) {
+                Ok(read_stream) => {
+                    read_stream.set_nodelay(true)?;
+                    let write_stream = read_stream.try_clone()?;
+                    let mut socket = Socket {
+                        writer: BufWriter::new(write_stream),
+                        reader: BufReader::new(read_stream),
+                        capabilities: 0,
+                    };
+                    let session_token = socket.handshake(session_token, slot)?;
+                    return Ok((socket, session_token));
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "host resolved to no addresses")).into())
+    }
+
+    fn handshake(&mut self, session_token: Option<SessionToken>, slot: u32) -> Result<SessionToken, HandshakeError> {
+        let hello = ClientHello {
+            protocol_version: PROTOCOL_VERSION,
+            cuda_api_version: CUDA_VERSION,
+            capabilities: CAPABILITY_BATCH,
+            session_token,
+            connection_slot: slot,
+        };
+        hello.write_to(&mut self.writer)?;
+        let (capabilities, session_token) = ServerHello::read_from(&mut self.reader)?.into_result()?;
+        self.capabilities = capabilities;
+        Ok(session_token)
+    }
+}
+
+/// Maps the status of an error reply to the CUDA error the intercepted function returns.
+fn error_status_to_cuda_error(status: u32) -> CUresult {
+    match status {
+        STATUS_UNSUPPORTED_RPC => CUDA_ERROR_NOT_SUPPORTED,
+        STATUS_MALFORMED_REQUEST => CUDA_ERROR_INVALID_VALUE,
+        STATUS_SERVER_ERROR => CUDA_ERROR_UNKNOWN,
+        _ => CUDA_ERROR_UNKNOWN,
+    }
+}
+
+pub(crate) unsafe fn ptr_as_u8_slice<T: Sized>(p: *const T) -> &'static mut [u8] {
+    std::slice::from_raw_parts_mut(p as *mut u8, size_of::<T>())
+}
+
+pub(crate) unsafe fn array_as_u8_slice<T: Sized>(p: *const T, length: usize) -> &'static mut [u8] {
+    std::slice::from_raw_parts_mut(p as *mut u8, length * size_of::<T>())
+}
+
+pub(crate) unsafe fn as_u8_slice<T: Sized>(p: &T) -> &[u8] {
+    std::slice::from_raw_parts((p as *const T) as *const u8, size_of::<T>())
 }
-*/
\ No newline at end of file
@@ -0,0 +1,75 @@
+//! Subset of the CUDA driver API definitions shared by the client, the server and the mock driver.
+
+/// The CUDA API version the client shim implements, in the `cuda.h` `CUDA_VERSION` format.
+pub const CUDA_VERSION: i32 = 12000;
+
+#[allow(non_camel_case_types)]
+pub type CUresult = i32;
+
+pub const CUDA_SUCCESS: CUresult = 0;
+pub const CUDA_ERROR_INVALID_VALUE: CUresult = 1;
+pub const CUDA_ERROR_OUT_OF_MEMORY: CUresult = 2;
+pub const CUDA_ERROR_NOT_INITIALIZED: CUresult = 3;
+pub const CUDA_ERROR_INVALID_DEVICE: CUresult = 101;
+pub const CUDA_ERROR_INVALID_IMAGE: CUresult = 200;
+pub const CUDA_ERROR_INVALID_CONTEXT: CUresult = 201;
+pub const CUDA_ERROR_FILE_NOT_FOUND: CUresult = 301;
+pub const CUDA_ERROR_INVALID_HANDLE: CUresult = 400;
+pub const CUDA_ERROR_NOT_FOUND: CUresult = 500;
+pub const CUDA_ERROR_ILLEGAL_ADDRESS: CUresult = 700;
+pub const CUDA_ERROR_LAUNCH_TIMEOUT: CUresult = 702;
+pub const CUDA_ERROR_HARDWARE_STACK_ERROR: CUresult = 714;
+pub const CUDA_ERROR_ILLEGAL_INSTRUCTION: CUresult = 715;
+pub const CUDA_ERROR_MISALIGNED_ADDRESS: CUresult = 716;
+pub const CUDA_ERROR_INVALID_ADDRESS_SPACE: CUresult = 717;
+pub const CUDA_ERROR_INVALID_PC: CUresult = 718;
+pub const CUDA_ERROR_LAUNCH_FAILED: CUresult = 719;
+pub const CUDA_ERROR_NOT_PERMITTED: CUresult = 800;
+pub const CUDA_ERROR_NOT_SUPPORTED: CUresult = 801;
+pub const CUDA_ERROR_UNKNOWN: CUresult = 999;
+
+/// The `CUpointer_attribute` asking `cuPointerGetAttribute` for the context of an allocation.
+pub const CU_POINTER_ATTRIBUTE_CONTEXT: i32 = 1;
+
+/// The `CUjit_option`s whose values are log buffers the driver writes to, with the sizes of the buffers, which the
+/// driver sets to the length of the log.
+pub const CU_JIT_INFO_LOG_BUFFER: i32 = 3;
+pub const CU_JIT_INFO_LOG_BUFFER_SIZE_BYTES: i32 = 4;
+pub const CU_JIT_ERROR_LOG_BUFFER: i32 = 5;
+pub const CU_JIT_ERROR_LOG_BUFFER_SIZE_BYTES: i32 = 6;
+/// The `CUjit_option`s whose values are numbers or flags rather than pointers, forwarded as they are besides the log
+/// buffers. Others, e.g. the arrays of symbols, are not forwarded.
+pub const CU_JIT_SCALAR_OPTIONS: [i32; 19] = [
+    0,  // CU_JIT_MAX_REGISTERS
+    1,  // CU_JIT_THREADS_PER_BLOCK
+    2,  // CU_JIT_WALL_TIME
+    CU_JIT_INFO_LOG_BUFFER_SIZE_BYTES,
+    CU_JIT_ERROR_LOG_BUFFER_SIZE_BYTES,
+    7,  // CU_JIT_OPTIMIZATION_LEVEL
+    8,  // CU_JIT_TARGET_FROM_CUCONTEXT
+    9,  // CU_JIT_TARGET
+    10, // CU_JIT_FALLBACK_STRATEGY
+    11, // CU_JIT_GENERATE_DEBUG_INFO
+    12, // CU_JIT_LOG_VERBOSE
+    13, // CU_JIT_GENERATE_LINE_INFO
+    14, // CU_JIT_CACHE_MODE
+    16, // CU_JIT_FAST_COMPILE
+    29, // CU_JIT_OPTIMIZE_UNUSED_DEVICE_VARIABLES
+    30, // CU_JIT_POSITION_INDEPENDENT_CODE
+    31, // CU_JIT_MIN_CTA_PER_SM
+    32, // CU_JIT_MAX_THREADS_PER_BLOCK
+    33, // CU_JIT_OVERRIDE_DIRECTIVE_VALUES
+];
+/// A `CUjit_option` whose value points to an array of names, which is not forwarded.
+pub const CU_JIT_GLOBAL_SYMBOL_NAMES: i32 = 17;
+
+/// The markers of the `extra` argument of `cuLaunchKernel`, which passes the parameters in one buffer.
+pub const CU_LAUNCH_PARAM_END: usize = 0;
+pub const CU_LAUNCH_PARAM_BUFFER_POINTER: usize = 1;
+pub const CU_LAUNCH_PARAM_BUFFER_SIZE: usize = 2;
+
+/// The `CUmemorytype`s of the source and the destination of `cuMemcpy2D`.
+pub const CU_MEMORYTYPE_HOST: i32 = 1;
+pub const CU_MEMORYTYPE_DEVICE: i32 = 2;
+pub const CU_MEMORYTYPE_ARRAY: i32 = 3;
+pub const CU_MEMORYTYPE_UNIFIED: i32 = 4;
@@ -0,0 +1,239 @@
+//! Framing of RPC messages exchanged after the handshake.
+//!
+//! Every message is a fixed size [`FrameHeader`] followed by `length` bytes of payload.
+//! A reply carries the request ID and RPC ID of the request it answers. All header fields are big endian.
+//!
+//! A client may send further requests on a connection before the replies to the earlier ones arrive,
+//! up to [`MAX_IN_FLIGHT_REQUESTS`], and matches replies to requests by their ID rather than by order.
+//! The server executes the requests of a connection in the order they arrive.
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{Read, Write};
+
+pub const HEADER_SIZE: usize = 4 + 8 + 4 + 4;
+
+/// Upper bound for a payload, protects against allocating garbage lengths of a desynchronized stream.
+pub const MAX_PAYLOAD_LENGTH: u32 = 256 * 1024 * 1024;
+
+/// Upper bound of the chunks a memory copy is split into, each sent as a request of its own.
+pub const MAX_MEMCPY_CHUNK_SIZE: u32 = 16 * 1024 * 1024;
+
+/// How many chunks of a memory copy a client may have sent without having received their replies.
+pub const MAX_MEMCPY_CHUNKS_IN_FLIGHT: usize = 4;
+
+/// How many requests a client may have sent on a connection without having received their replies.
+/// The server keeps as many replies per connection, to answer requests the client resends after reconnecting.
+pub const MAX_IN_FLIGHT_REQUESTS: usize = 64;
+
+/// The frame is a reply to a request.
+pub const FLAG_REPLY: u32 = 1;
+/// The request could not be handled; the payload is a single `u32` status, see the `STATUS_*` constants.
+pub const FLAG_ERROR: u32 = 1 << 1;
+
+/// The frame carries a batch of requests, see [`Frame::batch`]. Requires [`CAPABILITY_BATCH`].
+///
+/// [`CAPABILITY_BATCH`]: crate::handshake::CAPABILITY_BATCH
+pub const FLAG_BATCH: u32 = 1 << 2;
+
+/// The server does not know the requested RPC.
+pub const STATUS_UNSUPPORTED_RPC: u32 = 1;
+/// The request payload does not match the RPC.
+pub const STATUS_MALFORMED_REQUEST: u32 = 2;
+/// The server failed to handle the request, e.g. the reply it was resent for was discarded.
+pub const STATUS_SERVER_ERROR: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameHeader {
+    pub length: u32,
+    pub request_id: u64,
+    pub rpc_id: i32,
+    pub flags: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub header: FrameHeader,
+    pub payload: Vec<u8>,
+}
+
+impl FrameHeader {
+    pub fn write_to(&self, writer: &mut impl Write) -> std::io::Result<()> {
+        writer.write_u32::<BigEndian>(self.length)?;
+        writer.write_u64::<BigEndian>(self.request_id)?;
+        writer.write_i32::<BigEndian>(self.rpc_id)?;
+        writer.write_u32::<BigEndian>(self.flags)
+    }
+
+    pub fn read_from(reader: &mut impl Read) -> std::io::Result<Self> {
+        let header = FrameHeader {
+            length: reader.read_u32::<BigEndian>()?,
+            request_id: reader.read_u64::<BigEndian>()?,
+            rpc_id: reader.read_i32::<BigEndian>()?,
+            flags: reader.read_u32::<BigEndian>()?,
+        };
+        if header.length > MAX_PAYLOAD_LENGTH {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("frame payload of {} bytes exceeds the limit of {} bytes", header.length, MAX_PAYLOAD_LENGTH),
+            ));
+        }
+        Ok(header)
+    }
+}
+
+impl Frame {
+    pub fn request(request_id: u64, rpc_id: i32, payload: Vec<u8>) -> Self {
+        Self::new(request_id, rpc_id, 0, payload)
+    }
+
+    pub fn reply(request: &FrameHeader, payload: Vec<u8>) -> Self {
+        Self::new(request.request_id, request.rpc_id, FLAG_REPLY, payload)
+    }
+
+    pub fn error_reply(request: &FrameHeader, status: u32) -> Self {
+        Self::new(request.request_id, request.rpc_id, FLAG_REPLY | FLAG_ERROR, status.to_be_bytes().to_vec())
+    }
+
+    /// A request executing `calls` in order. Their request IDs are not used.
+    ///
+    /// The batch is answered by a single reply, see [`Frame::batch_reply`].
+    /// The server stops at the first call that fails, and skips the rest of the batch.
+    pub fn batch(request_id: u64, calls: &[Frame]) -> Self {
+        let mut payload = Vec::with_capacity(calls.iter().map(|call| HEADER_SIZE + call.payload.len()).sum());
+        for call in calls {
+            call.write_to(&mut payload).expect("writing to a Vec does not fail");
+        }
+        Self::new(request_id, 0, FLAG_BATCH, payload)
+    }
+
+    /// The reply to a batch. Its payload is empty if all calls succeeded,
+    /// otherwise the `u32` index of the failed call followed by the reply frame of that call.
+    pub fn batch_reply(request: &FrameHeader, failure: Option<(u32, &Frame)>) -> Self {
+        let mut payload = Vec::new();
+        if let Some((index, reply)) = failure {
+            payload.write_u32::<BigEndian>(index).expect("writing to a Vec does not fail");
+            reply.write_to(&mut payload).expect("writing to a Vec does not fail");
+        }
+        Self::new(request.request_id, request.rpc_id, FLAG_REPLY | FLAG_BATCH, payload)
+    }
+
+    pub fn with_request_id(mut self, request_id: u64) -> Self {
+        self.header.request_id = request_id;
+        self
+    }
+
+    fn new(request_id: u64, rpc_id: i32, flags: u32, payload: Vec<u8>) -> Self {
+        Frame {
+            header: FrameHeader { length: payload.len() as u32, request_id, rpc_id, flags },
+            payload,
+        }
+    }
+
+    pub fn is_reply(&self) -> bool {
+        self.header.flags & FLAG_REPLY != 0
+    }
+
+    pub fn is_batch(&self) -> bool {
+        self.header.flags & FLAG_BATCH != 0
+    }
+
+    /// The calls of a batch request.
+    pub fn batch_calls(&self) -> std::io::Result<Vec<Frame>> {
+        let mut payload = self.payload.as_slice();
+        let mut calls = Vec::new();
+        while !payload.is_empty() {
+            calls.push(Frame::read_from(&mut payload)?);
+        }
+        Ok(calls)
+    }
+
+    /// The index and reply of the failed call of a batch reply, `None` if all calls succeeded.
+    pub fn batch_failure(&self) -> std::io::Result<Option<(u32, Frame)>> {
+        if self.payload.is_empty() {
+            return Ok(None);
+        }
+        let mut payload = self.payload.as_slice();
+        Ok(Some((payload.read_u32::<BigEndian>()?, Frame::read_from(&mut payload)?)))
+    }
+
+    /// The status of an error reply, `None` if the frame is not an error.
+    pub fn error_status(&self) -> Option<u32> {
+        if self.header.flags & FLAG_ERROR == 0 {
+            return None;
+        }
+        Some(self.payload.as_slice().read_u32::<BigEndian>().unwrap_or(0))
+    }
+
+    /// Writes the frame without flushing.
+    pub fn write_to(&self, writer: &mut impl Write) -> std::io::Result<()> {
+        self.header.write_to(writer)?;
+        writer.write_all(&self.payload)
+    }
+
+    pub fn read_from(reader: &mut impl Read) -> std::io::Result<Self> {
+        let header = FrameHeader::read_from(reader)?;
+        let mut payload = vec![0_u8; header.length as usize];
+        reader.read_exact(&mut payload)?;
+        Ok(Frame { header, payload })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let request = Frame::request(7, 1, vec![1, 2, 3]);
+        let reply = Frame::reply(&request.header, vec![4, 5]);
+        let error = Frame::error_reply(&request.header, STATUS_UNSUPPORTED_RPC);
+
+        let mut buf = Vec::new();
+        for frame in [&request, &reply, &error] {
+            frame.write_to(&mut buf).unwrap();
+        }
+        assert_eq!(buf.len(), 3 * HEADER_SIZE + 3 + 2 + 4);
+
+        let mut reader = buf.as_slice();
+        assert_eq!(Frame::read_from(&mut reader).unwrap(), request);
+        let decoded_reply = Frame::read_from(&mut reader).unwrap();
+        assert_eq!(decoded_reply, reply);
+        assert!(decoded_reply.is_reply());
+        assert_eq!(decoded_reply.error_status(), None);
+        let decoded_error = Frame::read_from(&mut reader).unwrap();
+        assert_eq!(decoded_error.header.request_id, 7);
+        assert_eq!(decoded_error.error_status(), Some(STATUS_UNSUPPORTED_RPC));
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn batch() {
+        let calls = [Frame::request(0, 1, vec![1]), Frame::request(0, 2, Vec::new())];
+        let batch = Frame::batch(9, &calls);
+        assert!(batch.is_batch());
+        assert_eq!(batch.batch_calls().unwrap(), calls);
+
+        let succeeded = Frame::batch_reply(&batch.header, None);
+        assert!(succeeded.is_reply() && succeeded.is_batch());
+        assert_eq!(succeeded.batch_failure().unwrap(), None);
+
+        let error = Frame::error_reply(&calls[1].header, STATUS_MALFORMED_REQUEST);
+        let failed = Frame::batch_reply(&batch.header, Some((1, &error)));
+        assert_eq!(failed.header.request_id, 9);
+        assert_eq!(failed.batch_failure().unwrap(), Some((1, error)));
+    }
+
+    #[test]
+    fn truncated_and_oversized() {
+        let mut buf = Vec::new();
+        Frame::request(1, 1, vec![1, 2, 3]).write_to(&mut buf).unwrap();
+        buf.pop();
+        let e = Frame::read_from(&mut buf.as_slice()).unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::UnexpectedEof);
+
+        let mut buf = Vec::new();
+        FrameHeader { length: MAX_PAYLOAD_LENGTH + 1, request_id: 1, rpc_id: 1, flags: 0 }.write_to(&mut buf).unwrap();
+        let e = Frame::read_from(&mut buf.as_slice()).unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,219 @@
+// Generated by parser_wip from parser_wip/functions.yaml, do not edit.
+
+#![allow(non_camel_case_types, non_snake_case)]
+use crate::wire::Wire;
+use std::io;
+#[repr(i32)]
+#[derive(PartialEq, Debug, Clone, Copy, FromPrimitive)]
+pub enum RPC {
+    cuDriverGetVersion = 1,
+    cuInit = 2,
+    cuCtxSynchronize = 3,
+    cuDeviceGetCount = 4,
+    cuDeviceGet = 5,
+    cuDeviceGetName = 6,
+    cuDeviceGetUuid = 7,
+    cuDeviceGetProperties = 8,
+    cuCtxCreate_v2 = 9,
+    cuCtxDestroy_v2 = 10,
+    cuCtxSetCurrent = 11,
+    cuCtxGetCurrent = 12,
+    cuCtxGetDevice = 13,
+    cuDevicePrimaryCtxRetain = 14,
+    cuDevicePrimaryCtxRelease_v2 = 15,
+    cuStreamCreate = 16,
+    cuStreamDestroy_v2 = 17,
+    cuStreamSynchronize = 18,
+    cuEventCreate = 19,
+    cuEventDestroy_v2 = 20,
+    cuEventRecord = 21,
+    cuEventSynchronize = 22,
+    cuMemGetInfo_v2 = 23,
+    cuMemAlloc_v2 = 24,
+    cuMemFree_v2 = 25,
+    cuMemAllocPitch_v2 = 26,
+    cuMemsetD8_v2 = 27,
+    cuMemsetD32_v2 = 28,
+    cuMemcpyHtoD_v2 = 29,
+    cuMemcpyDtoH_v2 = 30,
+    cuModuleLoadData = 31,
+    cuModuleLoadDataEx = 32,
+    cuModuleLoadFatBinary = 33,
+    cuModuleGetFunction = 34,
+    cuModuleUnload = 35,
+    cuFuncGetParamInfo = 36,
+    cuLaunchKernel = 37,
+    cuStreamWaitEvent = 38,
+    cuMemcpy2D_v2 = 39,
+}
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CUuuid_st {
+    pub bytes: [i8; 16],
+}
+const _: () = {
+    let size = size_of::<CUuuid_st>();
+    assert!(size == 16);
+    assert!(std::mem::offset_of!(CUuuid_st, bytes) == 0);
+};
+impl Default for CUuuid_st {
+    fn default() -> Self {
+        unsafe { std::mem::zeroed() }
+    }
+}
+impl Wire for CUuuid_st {
+    fn write_to(&self, writer: &mut Vec<u8>) {
+        self.bytes.write_to(writer);
+    }
+    fn read_from(reader: &mut &[u8]) -> io::Result<Self> {
+        Ok(CUuuid_st {
+            bytes: Wire::read_from(reader)?,
+        })
+    }
+}
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CUdevprop_st {
+    pub maxThreadsPerBlock: i32,
+    pub maxThreadsDim: [i32; 3],
+    pub maxGridSize: [i32; 3],
+    pub sharedMemPerBlock: i32,
+    pub totalConstantMemory: i32,
+    pub SIMDWidth: i32,
+    pub memPitch: i32,
+    pub regsPerBlock: i32,
+    pub clockRate: i32,
+    pub textureAlign: i32,
+}
+const _: () = {
+    let size = size_of::<CUdevprop_st>();
+    assert!(size == 56);
+    assert!(std::mem::offset_of!(CUdevprop_st, maxThreadsPerBlock) == 0);
+    assert!(std::mem::offset_of!(CUdevprop_st, maxThreadsDim) == 4);
+    assert!(std::mem::offset_of!(CUdevprop_st, maxGridSize) == 16);
+    assert!(std::mem::offset_of!(CUdevprop_st, sharedMemPerBlock) == 28);
+    assert!(std::mem::offset_of!(CUdevprop_st, totalConstantMemory) == 32);
+    assert!(std::mem::offset_of!(CUdevprop_st, SIMDWidth) == 36);
+    assert!(std::mem::offset_of!(CUdevprop_st, memPitch) == 40);
+    assert!(std::mem::offset_of!(CUdevprop_st, regsPerBlock) == 44);
+    assert!(std::mem::offset_of!(CUdevprop_st, clockRate) == 48);
+    assert!(std::mem::offset_of!(CUdevprop_st, textureAlign) == 52);
+};
+impl Default for CUdevprop_st {
+    fn default() -> Self {
+        unsafe { std::mem::zeroed() }
+    }
+}
+impl Wire for CUdevprop_st {
+    fn write_to(&self, writer: &mut Vec<u8>) {
+        self.maxThreadsPerBlock.write_to(writer);
+        self.maxThreadsDim.write_to(writer);
+        self.maxGridSize.write_to(writer);
+        self.sharedMemPerBlock.write_to(writer);
+        self.totalConstantMemory.write_to(writer);
+        self.SIMDWidth.write_to(writer);
+        self.memPitch.write_to(writer);
+        self.regsPerBlock.write_to(writer);
+        self.clockRate.write_to(writer);
+        self.textureAlign.write_to(writer);
+    }
+    fn read_from(reader: &mut &[u8]) -> io::Result<Self> {
+        Ok(CUdevprop_st {
+            maxThreadsPerBlock: Wire::read_from(reader)?,
+            maxThreadsDim: Wire::read_from(reader)?,
+            maxGridSize: Wire::read_from(reader)?,
+            sharedMemPerBlock: Wire::read_from(reader)?,
+            totalConstantMemory: Wire::read_from(reader)?,
+            SIMDWidth: Wire::read_from(reader)?,
+            memPitch: Wire::read_from(reader)?,
+            regsPerBlock: Wire::read_from(reader)?,
+            clockRate: Wire::read_from(reader)?,
+            textureAlign: Wire::read_from(reader)?,
+        })
+    }
+}
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CUDA_MEMCPY2D_st {
+    pub srcXInBytes: u64,
+    pub srcY: u64,
+    pub srcMemoryType: i32,
+    pub srcHost: *const std::ffi::c_void,
+    pub srcDevice: u64,
+    pub srcArray: *mut std::ffi::c_void,
+    pub srcPitch: u64,
+    pub dstXInBytes: u64,
+    pub dstY: u64,
+    pub dstMemoryType: i32,
+    pub dstHost: *mut std::ffi::c_void,
+    pub dstDevice: u64,
+    pub dstArray: *mut std::ffi::c_void,
+    pub dstPitch: u64,
+    pub WidthInBytes: u64,
+    pub Height: u64,
+}
+const _: () = {
+    let size = size_of::<CUDA_MEMCPY2D_st>();
+    assert!(size == 128);
+    assert!(std::mem::offset_of!(CUDA_MEMCPY2D_st, srcXInBytes) == 0);
+    assert!(std::mem::offset_of!(CUDA_MEMCPY2D_st, srcY) == 8);
+    assert!(std::mem::offset_of!(CUDA_MEMCPY2D_st, srcMemoryType) == 16);
+    assert!(std::mem::offset_of!(CUDA_MEMCPY2D_st, srcHost) == 24);
+    assert!(std::mem::offset_of!(CUDA_MEMCPY2D_st, srcDevice) == 32);
+    assert!(std::mem::offset_of!(CUDA_MEMCPY2D_st, srcArray) == 40);
+    assert!(std::mem::offset_of!(CUDA_MEMCPY2D_st, srcPitch) == 48);
+    assert!(std::mem::offset_of!(CUDA_MEMCPY2D_st, dstXInBytes) == 56);
+    assert!(std::mem::offset_of!(CUDA_MEMCPY2D_st, dstY) == 64);
+    assert!(std::mem::offset_of!(CUDA_MEMCPY2D_st, dstMemoryType) == 72);
+    assert!(std::mem::offset_of!(CUDA_MEMCPY2D_st, dstHost) == 80);
+    assert!(std::mem::offset_of!(CUDA_MEMCPY2D_st, dstDevice) == 88);
+    assert!(std::mem::offset_of!(CUDA_MEMCPY2D_st, dstArray) == 96);
+    assert!(std::mem::offset_of!(CUDA_MEMCPY2D_st, dstPitch) == 104);
+    assert!(std::mem::offset_of!(CUDA_MEMCPY2D_st, WidthInBytes) == 112);
+    assert!(std::mem::offset_of!(CUDA_MEMCPY2D_st, Height) == 120);
+};
+impl Default for CUDA_MEMCPY2D_st {
+    fn default() -> Self {
+        unsafe { std::mem::zeroed() }
+    }
+}
+impl Wire for CUDA_MEMCPY2D_st {
+    fn write_to(&self, writer: &mut Vec<u8>) {
+        self.srcXInBytes.write_to(writer);
+        self.srcY.write_to(writer);
+        self.srcMemoryType.write_to(writer);
+        self.srcHost.write_to(writer);
+        self.srcDevice.write_to(writer);
+        self.srcArray.write_to(writer);
+        self.srcPitch.write_to(writer);
+        self.dstXInBytes.write_to(writer);
+        self.dstY.write_to(writer);
+        self.dstMemoryType.write_to(writer);
+        self.dstHost.write_to(writer);
+        self.dstDevice.write_to(writer);
+        self.dstArray.write_to(writer);
+        self.dstPitch.write_to(writer);
+        self.WidthInBytes.write_to(writer);
+        self.Height.write_to(writer);
+    }
+    fn read_from(reader: &mut &[u8]) -> io::Result<Self> {
+        Ok(CUDA_MEMCPY2D_st {
+            srcXInBytes: Wire::read_from(reader)?,
+            srcY: Wire::read_from(reader)?,
+            srcMemoryType: Wire::read_from(reader)?,
+            srcHost: Wire::read_from(reader)?,
+            srcDevice: Wire::read_from(reader)?,
+            srcArray: Wire::read_from(reader)?,
+            srcPitch: Wire::read_from(reader)?,
+            dstXInBytes: Wire::read_from(reader)?,
+            dstY: Wire::read_from(reader)?,
+            dstMemoryType: Wire::read_from(reader)?,
+            dstHost: Wire::read_from(reader)?,
+            dstDevice: Wire::read_from(reader)?,
+            dstArray: Wire::read_from(reader)?,
+            dstPitch: Wire::read_from(reader)?,
+            WidthInBytes: Wire::read_from(reader)?,
+            Height: Wire::read_from(reader)?,
+        })
+    }
+}
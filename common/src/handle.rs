@@ -0,0 +1,34 @@
+//! Driver handles as they cross the wire.
+//!
+//! The server does not send the driver's handles to the clients, but IDs it translates back on every call.
+//! An ID is sent as a `u64`, whatever the representation of the handle type in C.
+
+use std::ffi::c_void;
+
+/// The representation of a handle type in C: a pointer to an opaque struct, e.g. `CUcontext`,
+/// or an integer, e.g. `CUdeviceptr`.
+pub trait Handle: Copy {
+    fn from_u64(value: u64) -> Self;
+
+    fn to_u64(self) -> u64;
+}
+
+impl Handle for *mut c_void {
+    fn from_u64(value: u64) -> Self {
+        value as usize as *mut c_void
+    }
+
+    fn to_u64(self) -> u64 {
+        self as usize as u64
+    }
+}
+
+impl Handle for u64 {
+    fn from_u64(value: u64) -> Self {
+        value
+    }
+
+    fn to_u64(self) -> u64 {
+        self
+    }
+}
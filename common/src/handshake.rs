@@ -0,0 +1,226 @@
+//! Handshake exchanged right after the connection is established, before any RPC.
+//!
+//! The client sends a [`ClientHello`], the server answers with a [`ServerHello`].
+//! All integers are big endian.
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::fmt::{Display, Formatter};
+use std::io::{Read, Write};
+
+/// "CUIP"
+pub const MAGIC: u32 = 0x43_55_49_50;
+
+/// Version of the wire protocol. Client and server must agree on it exactly.
+pub const PROTOCOL_VERSION: u16 = 6;
+
+/// Bit set of optional protocol features, see the `CAPABILITY_*` constants.
+pub type Capabilities = u32;
+
+/// Identifies a server-side session, which outlives a single connection.
+/// Issued by the server on connect, presented by the client to resume the session after reconnecting.
+pub type SessionToken = u128;
+
+/// The peer handles [batches](crate::frame::Frame::batch) of requests.
+pub const CAPABILITY_BATCH: Capabilities = 1;
+
+const STATUS_ACCEPTED: u8 = 0;
+const STATUS_REJECTED: u8 = 1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClientHello {
+    pub protocol_version: u16,
+    /// The CUDA API version the client exposes to the application, e.g. 12000 for 12.0.
+    pub cuda_api_version: i32,
+    pub capabilities: Capabilities,
+    /// The session to resume or join, `None` to start a new one.
+    pub session_token: Option<SessionToken>,
+    /// Distinguishes the connections a client opens within one session.
+    /// A reconnecting connection presents the slot of the connection it replaces.
+    pub connection_slot: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServerHello {
+    Accepted {
+        protocol_version: u16,
+        /// The capabilities both sides support.
+        capabilities: Capabilities,
+        session_token: SessionToken,
+    },
+    Rejected {
+        reason: String,
+    },
+}
+
+#[derive(Debug)]
+pub enum HandshakeError {
+    Io(std::io::Error),
+    BadMagic(u32),
+    BadStatus(u8),
+    Rejected(String),
+}
+
+impl Display for HandshakeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HandshakeError::Io(e) => write!(f, "I/O error during handshake: {}", e),
+            HandshakeError::BadMagic(magic) =>
+                write!(f, "peer is not a CUDA-over-IP endpoint (magic {:#010x})", magic),
+            HandshakeError::BadStatus(status) => write!(f, "invalid handshake status {}", status),
+            HandshakeError::Rejected(reason) => write!(f, "rejected by server: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for HandshakeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            HandshakeError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for HandshakeError {
+    fn from(e: std::io::Error) -> Self {
+        HandshakeError::Io(e)
+    }
+}
+
+impl ClientHello {
+    pub fn write_to(&self, writer: &mut impl Write) -> std::io::Result<()> {
+        writer.write_u32::<BigEndian>(MAGIC)?;
+        writer.write_u16::<BigEndian>(self.protocol_version)?;
+        writer.write_i32::<BigEndian>(self.cuda_api_version)?;
+        writer.write_u32::<BigEndian>(self.capabilities)?;
+        match self.session_token {
+            Some(token) => {
+                writer.write_u8(1)?;
+                writer.write_u128::<BigEndian>(token)?;
+            }
+            None => writer.write_u8(0)?,
+        }
+        writer.write_u32::<BigEndian>(self.connection_slot)?;
+        writer.flush()
+    }
+
+    pub fn read_from(reader: &mut impl Read) -> Result<Self, HandshakeError> {
+        read_magic(reader)?;
+        Ok(ClientHello {
+            protocol_version: reader.read_u16::<BigEndian>()?,
+            cuda_api_version: reader.read_i32::<BigEndian>()?,
+            capabilities: reader.read_u32::<BigEndian>()?,
+            session_token: match reader.read_u8()? {
+                0 => None,
+                _ => Some(reader.read_u128::<BigEndian>()?),
+            },
+            connection_slot: reader.read_u32::<BigEndian>()?,
+        })
+    }
+}
+
+impl ServerHello {
+    /// Decides whether a server speaking `PROTOCOL_VERSION` with the given driver version and
+    /// capabilities can serve the client. Returns the negotiated capabilities or the reason of rejection.
+    pub fn negotiate(client: &ClientHello, driver_version: i32, server_capabilities: Capabilities) -> Result<Capabilities, String> {
+        if client.protocol_version != PROTOCOL_VERSION {
+            return Err(format!("protocol version {} is not supported, server speaks version {}",
+                               client.protocol_version, PROTOCOL_VERSION));
+        }
+        if client.cuda_api_version > driver_version {
+            return Err(format!("client requires CUDA API version {}, server driver provides {}",
+                               client.cuda_api_version, driver_version));
+        }
+        Ok(client.capabilities & server_capabilities)
+    }
+
+    pub fn write_to(&self, writer: &mut impl Write) -> std::io::Result<()> {
+        writer.write_u32::<BigEndian>(MAGIC)?;
+        match self {
+            ServerHello::Accepted { protocol_version, capabilities, session_token } => {
+                writer.write_u8(STATUS_ACCEPTED)?;
+                writer.write_u16::<BigEndian>(*protocol_version)?;
+                writer.write_u32::<BigEndian>(*capabilities)?;
+                writer.write_u128::<BigEndian>(*session_token)?;
+            }
+            ServerHello::Rejected { reason } => {
+                let reason = &reason.as_bytes()[..reason.len().min(u16::MAX as usize)];
+                writer.write_u8(STATUS_REJECTED)?;
+                writer.write_u16::<BigEndian>(reason.len() as u16)?;
+                writer.write_all(reason)?;
+            }
+        }
+        writer.flush()
+    }
+
+    pub fn read_from(reader: &mut impl Read) -> Result<Self, HandshakeError> {
+        read_magic(reader)?;
+        match reader.read_u8()? {
+            STATUS_ACCEPTED => Ok(ServerHello::Accepted {
+                protocol_version: reader.read_u16::<BigEndian>()?,
+                capabilities: reader.read_u32::<BigEndian>()?,
+                session_token: reader.read_u128::<BigEndian>()?,
+            }),
+            STATUS_REJECTED => {
+                let mut reason = vec![0_u8; reader.read_u16::<BigEndian>()? as usize];
+                reader.read_exact(&mut reason)?;
+                Ok(ServerHello::Rejected { reason: String::from_utf8_lossy(&reason).into_owned() })
+            }
+            status => Err(HandshakeError::BadStatus(status)),
+        }
+    }
+
+    /// Converts a rejection into an error, returning the negotiated capabilities and the session otherwise.
+    pub fn into_result(self) -> Result<(Capabilities, SessionToken), HandshakeError> {
+        match self {
+            ServerHello::Accepted { capabilities, session_token, .. } => Ok((capabilities, session_token)),
+            ServerHello::Rejected { reason } => Err(HandshakeError::Rejected(reason)),
+        }
+    }
+}
+
+fn read_magic(reader: &mut impl Read) -> Result<(), HandshakeError> {
+    match reader.read_u32::<BigEndian>()? {
+        MAGIC => Ok(()),
+        magic => Err(HandshakeError::BadMagic(magic)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hello(protocol_version: u16, cuda_api_version: i32) -> ClientHello {
+        ClientHello { protocol_version, cuda_api_version, capabilities: 0b11, session_token: Some(42), connection_slot: 3 }
+    }
+
+    #[test]
+    fn round_trip() {
+        for client in [hello(PROTOCOL_VERSION, 12000), ClientHello { session_token: None, ..hello(PROTOCOL_VERSION, 12000) }] {
+            let mut buf = Vec::new();
+            client.write_to(&mut buf).unwrap();
+            assert_eq!(ClientHello::read_from(&mut buf.as_slice()).unwrap(), client);
+        }
+
+        let accepted = ServerHello::Accepted { protocol_version: PROTOCOL_VERSION, capabilities: 0b10, session_token: u128::MAX };
+        let rejected = ServerHello::Rejected { reason: "no".to_string() };
+        for server in [accepted, rejected] {
+            let mut buf = Vec::new();
+            server.write_to(&mut buf).unwrap();
+            assert_eq!(ServerHello::read_from(&mut buf.as_slice()).unwrap(), server);
+        }
+    }
+
+    #[test]
+    fn negotiate() {
+        assert_eq!(ServerHello::negotiate(&hello(PROTOCOL_VERSION, 12000), 12040, 0b10), Ok(0b10));
+        assert!(ServerHello::negotiate(&hello(PROTOCOL_VERSION + 1, 12000), 12040, 0).is_err());
+        assert!(ServerHello::negotiate(&hello(PROTOCOL_VERSION, 12000), 11080, 0).is_err());
+    }
+
+    #[test]
+    fn bad_magic() {
+        let bytes = [0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        assert!(matches!(ClientHello::read_from(&mut bytes.as_slice()), Err(HandshakeError::BadMagic(1))));
+    }
+}
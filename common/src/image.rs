@@ -0,0 +1,24 @@
+//! How module images are sent to the server, which caches them by their hash.
+//!
+//! A request to load a module has the hash of the image, or the image itself once the server said it does not have
+//! it. The reply starts with [`IMAGE_FOUND`], followed by the reply of the function, or with [`IMAGE_MISSING`] alone.
+
+use sha2::{Digest, Sha256};
+
+/// The SHA-256 of an image. The server hashes the images it receives itself, so a client can't have another one load
+/// an image of its choosing.
+pub type ImageHash = [u8; 32];
+
+/// The request has the hash of the image.
+pub const IMAGE_BY_HASH: u8 = 0;
+/// The request has the length of the image and its bytes.
+pub const IMAGE_BYTES: u8 = 1;
+
+/// The server does not have the image of the hash, the client sends the request again with the image.
+pub const IMAGE_MISSING: u8 = 0;
+/// The server had the image, or got it with the request, and called the function.
+pub const IMAGE_FOUND: u8 = 1;
+
+pub fn image_hash(image: &[u8]) -> ImageHash {
+    Sha256::digest(image).into()
+}
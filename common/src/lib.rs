@@ -1,29 +1,49 @@
 #[macro_use]
 extern crate num_derive;
 
-#[allow(non_camel_case_types)]
-#[repr(i32)]
-#[derive(PartialEq, Debug, FromPrimitive)]
-pub enum RPC {
-    cuDriverGetVersion = 1
+use std::fmt::{Display, Formatter};
+
+pub mod cuda;
+pub mod frame;
+mod generated;
+pub mod handle;
+pub mod handshake;
+pub mod image;
+pub mod wire;
+
+pub use generated::*;
+
+/// An RPC ID this build does not know, e.g. sent by a newer peer.
+#[derive(Debug, PartialEq)]
+pub struct UnknownRpc(pub i32);
+
+impl Display for UnknownRpc {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "unknown RPC {}", self.0)
+    }
 }
 
-impl RPC {
-    pub fn parse(value: i32) -> Self {
+impl std::error::Error for UnknownRpc {}
+
+impl TryFrom<i32> for RPC {
+    type Error = UnknownRpc;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
         use num_traits::FromPrimitive;
-        FromPrimitive::from_i32(value).expect(&format!("Invalid RPC value: {}", value))
+        FromPrimitive::from_i32(value).ok_or(UnknownRpc(value))
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::RPC;
+    use crate::{UnknownRpc, RPC};
 
     #[test]
     fn conversion() {
         let original = RPC::cuDriverGetVersion;
         let v: i32 = RPC::cuDriverGetVersion as i32;
-        let rpc = RPC::parse(v);
-        assert_eq!(rpc, original);
+        let rpc = RPC::try_from(v);
+        assert_eq!(rpc, Ok(original));
+        assert_eq!(RPC::try_from(-1), Err(UnknownRpc(-1)));
     }
 }
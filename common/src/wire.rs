@@ -0,0 +1,96 @@
+//! Encoding of the C structs passed to the driver.
+//!
+//! Structs are sent field by field, each number in native byte order like the other arguments, so the padding between
+//! fields does not cross the wire. Their layout in memory is the one of the C compiler on either side.
+
+use std::io::{self, Read};
+
+/// A value that can cross the wire as a field of a struct.
+pub trait Wire: Sized {
+    fn write_to(&self, writer: &mut Vec<u8>);
+
+    fn read_from(reader: &mut &[u8]) -> io::Result<Self>;
+}
+
+macro_rules! impl_wire {
+    ($($t:ty),*) => {
+        $(impl Wire for $t {
+            fn write_to(&self, writer: &mut Vec<u8>) {
+                writer.extend_from_slice(&self.to_ne_bytes());
+            }
+
+            fn read_from(reader: &mut &[u8]) -> io::Result<Self> {
+                let mut bytes = [0; size_of::<$t>()];
+                reader.read_exact(&mut bytes)?;
+                Ok(<$t>::from_ne_bytes(bytes))
+            }
+        })*
+    };
+}
+
+impl_wire!(i8, u8, i16, u16, i32, u32, i64, u64, f32, f64);
+
+/// Pointers are sent as their address, which means nothing on the other side: the server clears them.
+impl<T> Wire for *const T {
+    fn write_to(&self, writer: &mut Vec<u8>) {
+        (*self as usize as u64).write_to(writer);
+    }
+
+    fn read_from(reader: &mut &[u8]) -> io::Result<Self> {
+        Ok(u64::read_from(reader)? as usize as *const T)
+    }
+}
+
+impl<T> Wire for *mut T {
+    fn write_to(&self, writer: &mut Vec<u8>) {
+        (*self as usize as u64).write_to(writer);
+    }
+
+    fn read_from(reader: &mut &[u8]) -> io::Result<Self> {
+        Ok(u64::read_from(reader)? as usize as *mut T)
+    }
+}
+
+impl<T: Wire, const N: usize> Wire for [T; N] {
+    fn write_to(&self, writer: &mut Vec<u8>) {
+        self.iter().for_each(|element| element.write_to(writer));
+    }
+
+    fn read_from(reader: &mut &[u8]) -> io::Result<Self> {
+        let elements = (0..N).map(|_| T::read_from(reader)).collect::<io::Result<Vec<T>>>()?;
+        Ok(elements.try_into().unwrap_or_else(|_| unreachable!()))
+    }
+}
+
+/// The encoding of `value`.
+pub fn to_wire<T: Wire>(value: &T) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    value.write_to(&mut bytes);
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::wire::{to_wire, Wire};
+
+    #[test]
+    fn round_trip() {
+        let bytes = to_wire(&0x0102_i16);
+        assert_eq!(bytes, 0x0102_i16.to_ne_bytes());
+        assert_eq!(i16::read_from(&mut bytes.as_slice()).unwrap(), 0x0102);
+
+        let value = [[1.5_f32, -2.0], [0.0, f32::MAX]];
+        let bytes = to_wire(&value);
+        assert_eq!(bytes.len(), 16);
+        let mut reader = bytes.as_slice();
+        assert_eq!(<[[f32; 2]; 2]>::read_from(&mut reader).unwrap(), value);
+        assert!(reader.is_empty());
+
+        assert!(<[u32; 2]>::read_from(&mut &bytes[..7]).is_err());
+
+        let pointer = 0x1234 as *mut std::ffi::c_void;
+        let bytes = to_wire(&pointer);
+        assert_eq!(bytes, 0x1234_u64.to_ne_bytes());
+        assert_eq!(<*mut std::ffi::c_void>::read_from(&mut bytes.as_slice()).unwrap(), pointer);
+    }
+}
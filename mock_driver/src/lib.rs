@@ -0,0 +1,967 @@
+//! Mock of the CUDA driver library, for testing the client and the server on machines without a GPU.
+//!
+//! Implements a subset of the driver API deterministically: [`DEVICE_COUNT`] identical devices,
+//! device memory backed by host memory, streams and events that are always complete, and kernels that do nothing
+//! but for `fill`, see [`cuLaunchKernel`].
+//! Asynchronous functions complete before returning.
+//!
+//! The server loads it in place of `libcuda.so.1` with `--driver-library`, e.g.
+//! `--driver-library target/debug/libcuda_over_ip_mock_driver.so`.
+
+// The exported functions keep the names and the contracts of the driver API.
+#![allow(non_snake_case, clippy::missing_safety_doc)]
+
+mod memory;
+mod module;
+
+use crate::memory::DeviceMemory;
+use crate::module::Module;
+use cuda_over_ip_common::cuda::*;
+use std::cell::Cell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::{c_char, c_int, c_uint, c_void, CStr};
+use std::sync::{Mutex, MutexGuard};
+
+pub type CUdevice = i32;
+pub type CUdeviceptr = u64;
+pub type CUcontext = *mut c_void;
+pub type CUmodule = *mut c_void;
+pub type CUfunction = *mut c_void;
+pub type CUstream = *mut c_void;
+pub type CUevent = *mut c_void;
+pub type CUarray = *mut c_void;
+
+#[repr(C)]
+pub struct CUuuid {
+    pub bytes: [c_char; 16],
+}
+
+#[repr(C)]
+pub struct CUdevprop {
+    pub maxThreadsPerBlock: i32,
+    pub maxThreadsDim: [i32; 3],
+    pub maxGridSize: [i32; 3],
+    pub sharedMemPerBlock: i32,
+    pub totalConstantMemory: i32,
+    pub SIMDWidth: i32,
+    pub memPitch: i32,
+    pub regsPerBlock: i32,
+    pub clockRate: i32,
+    pub textureAlign: i32,
+}
+
+#[repr(C)]
+pub struct CUDA_MEMCPY2D {
+    pub srcXInBytes: usize,
+    pub srcY: usize,
+    pub srcMemoryType: c_int,
+    pub srcHost: *const c_void,
+    pub srcDevice: CUdeviceptr,
+    pub srcArray: CUarray,
+    pub srcPitch: usize,
+    pub dstXInBytes: usize,
+    pub dstY: usize,
+    pub dstMemoryType: c_int,
+    pub dstHost: *mut c_void,
+    pub dstDevice: CUdeviceptr,
+    pub dstArray: CUarray,
+    pub dstPitch: usize,
+    pub WidthInBytes: usize,
+    pub Height: usize,
+}
+
+/// The driver version reported by `cuDriverGetVersion`.
+pub const DRIVER_VERSION: i32 = 12040;
+pub const DEVICE_COUNT: i32 = 2;
+/// Memory of every device, in bytes.
+pub const DEVICE_MEMORY: u64 = 16 * 1024 * 1024 * 1024;
+pub const MAX_THREADS_PER_BLOCK: u32 = 1024;
+/// What `cuMemAllocPitch` rounds the width of rows up to.
+pub const PITCH_ALIGNMENT: usize = 512;
+/// Like the driver's handles, the mock's look like addresses rather than null or the special stream handles.
+const FIRST_HANDLE: usize = 0x7f00_0000_0000;
+
+/// The UUID reported by `cuDeviceGetUuid`, `mock` followed by zeros and the device ordinal.
+pub fn device_uuid(dev: CUdevice) -> [u8; 16] {
+    let mut uuid = [0; 16];
+    uuid[..4].copy_from_slice(b"mock");
+    uuid[15] = dev as u8;
+    uuid
+}
+
+const CU_DEVICE_ATTRIBUTE_MAX_THREADS_PER_BLOCK: i32 = 1;
+const CU_DEVICE_ATTRIBUTE_WARP_SIZE: i32 = 10;
+const CU_DEVICE_ATTRIBUTE_MULTIPROCESSOR_COUNT: i32 = 16;
+const CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MAJOR: i32 = 75;
+const CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MINOR: i32 = 76;
+
+static DRIVER: Mutex<Driver> = Mutex::new(Driver::new());
+
+thread_local! {
+    /// Handle of the current context of the thread, 0 if none.
+    static CURRENT_CONTEXT: Cell<usize> = const { Cell::new(0) };
+}
+
+struct Context {
+    device: CUdevice,
+}
+
+struct Function {
+    module: usize,
+    name: String,
+}
+
+/// State of the driver. Objects are identified by handles counting up from [`FIRST_HANDLE`], which are cast to the CUDA handle types.
+struct Driver {
+    initialized: bool,
+    contexts: BTreeMap<usize, Context>,
+    /// Primary context handle and reference count of every device that has one.
+    primary_contexts: BTreeMap<CUdevice, (usize, u32)>,
+    streams: BTreeSet<usize>,
+    events: BTreeSet<usize>,
+    modules: BTreeMap<usize, Module>,
+    functions: BTreeMap<usize, Function>,
+    memory: DeviceMemory,
+    next_handle: usize,
+}
+
+impl Driver {
+    const fn new() -> Self {
+        Driver {
+            initialized: false,
+            contexts: BTreeMap::new(),
+            primary_contexts: BTreeMap::new(),
+            streams: BTreeSet::new(),
+            events: BTreeSet::new(),
+            modules: BTreeMap::new(),
+            functions: BTreeMap::new(),
+            memory: DeviceMemory::new(),
+            next_handle: FIRST_HANDLE,
+        }
+    }
+
+    fn handle(&mut self) -> usize {
+        self.next_handle += 1;
+        self.next_handle - 1
+    }
+
+    fn create_context(&mut self, device: CUdevice) -> Result<usize, CUresult> {
+        check_device(device)?;
+        let handle = self.handle();
+        self.contexts.insert(handle, Context { device });
+        Ok(handle)
+    }
+
+    /// The device of the current context of the thread.
+    fn current_device(&self) -> Result<CUdevice, CUresult> {
+        self.current_context().map(|(_, device)| device)
+    }
+
+    /// The current context of the thread and its device.
+    fn current_context(&self) -> Result<(usize, CUdevice), CUresult> {
+        let context = CURRENT_CONTEXT.with(Cell::get);
+        self.contexts.get(&context).map(|c| (context, c.device)).ok_or(CUDA_ERROR_INVALID_CONTEXT)
+    }
+
+    /// Checks a stream handle, 0 being the default stream.
+    fn check_stream(&self, stream: CUstream) -> Result<(), CUresult> {
+        match stream as usize {
+            0 => Ok(()),
+            handle if self.streams.contains(&handle) => Ok(()),
+            _ => Err(CUDA_ERROR_INVALID_HANDLE),
+        }
+    }
+
+    fn check_event(&self, event: CUevent) -> Result<(), CUresult> {
+        match self.events.contains(&(event as usize)) {
+            true => Ok(()),
+            false => Err(CUDA_ERROR_INVALID_HANDLE),
+        }
+    }
+}
+
+/// Runs `f` on the driver state, which must have been initialized with `cuInit`.
+fn run(f: impl FnOnce(&mut Driver) -> Result<(), CUresult>) -> CUresult {
+    let mut driver = driver();
+    if !driver.initialized {
+        return CUDA_ERROR_NOT_INITIALIZED;
+    }
+    match f(&mut driver) {
+        Ok(()) => CUDA_SUCCESS,
+        Err(code) => code,
+    }
+}
+
+fn driver() -> MutexGuard<'static, Driver> {
+    DRIVER.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// The target of an output parameter.
+unsafe fn out<'a, T>(p: *mut T) -> Result<&'a mut T, CUresult> {
+    p.as_mut().ok_or(CUDA_ERROR_INVALID_VALUE)
+}
+
+fn check_device(device: CUdevice) -> Result<(), CUresult> {
+    match (0..DEVICE_COUNT).contains(&device) {
+        true => Ok(()),
+        false => Err(CUDA_ERROR_INVALID_DEVICE),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuInit(flags: c_uint) -> CUresult {
+    if flags != 0 {
+        return CUDA_ERROR_INVALID_VALUE;
+    }
+    driver().initialized = true;
+    CUDA_SUCCESS
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuDriverGetVersion(driver_version: *mut i32) -> CUresult {
+    match out(driver_version) {
+        Ok(driver_version) => {
+            *driver_version = DRIVER_VERSION;
+            CUDA_SUCCESS
+        }
+        Err(code) => code,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuGetErrorName(error: CUresult, p_str: *mut *const c_char) -> CUresult {
+    let name: &CStr = match error {
+        CUDA_SUCCESS => c"CUDA_SUCCESS",
+        CUDA_ERROR_INVALID_VALUE => c"CUDA_ERROR_INVALID_VALUE",
+        CUDA_ERROR_OUT_OF_MEMORY => c"CUDA_ERROR_OUT_OF_MEMORY",
+        CUDA_ERROR_NOT_INITIALIZED => c"CUDA_ERROR_NOT_INITIALIZED",
+        CUDA_ERROR_INVALID_DEVICE => c"CUDA_ERROR_INVALID_DEVICE",
+        CUDA_ERROR_INVALID_IMAGE => c"CUDA_ERROR_INVALID_IMAGE",
+        CUDA_ERROR_INVALID_CONTEXT => c"CUDA_ERROR_INVALID_CONTEXT",
+        CUDA_ERROR_FILE_NOT_FOUND => c"CUDA_ERROR_FILE_NOT_FOUND",
+        CUDA_ERROR_INVALID_HANDLE => c"CUDA_ERROR_INVALID_HANDLE",
+        CUDA_ERROR_NOT_FOUND => c"CUDA_ERROR_NOT_FOUND",
+        CUDA_ERROR_ILLEGAL_ADDRESS => c"CUDA_ERROR_ILLEGAL_ADDRESS",
+        CUDA_ERROR_NOT_SUPPORTED => c"CUDA_ERROR_NOT_SUPPORTED",
+        CUDA_ERROR_UNKNOWN => c"CUDA_ERROR_UNKNOWN",
+        _ => return CUDA_ERROR_INVALID_VALUE,
+    };
+    match out(p_str) {
+        Ok(p_str) => {
+            *p_str = name.as_ptr();
+            CUDA_SUCCESS
+        }
+        Err(code) => code,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuDeviceGetCount(count: *mut i32) -> CUresult {
+    run(|_| {
+        *out(count)? = DEVICE_COUNT;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuDeviceGet(device: *mut CUdevice, ordinal: i32) -> CUresult {
+    run(|_| {
+        check_device(ordinal)?;
+        *out(device)? = ordinal;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuDeviceGetName(name: *mut c_char, len: i32, dev: CUdevice) -> CUresult {
+    run(|_| {
+        check_device(dev)?;
+        if name.is_null() || len <= 0 {
+            return Err(CUDA_ERROR_INVALID_VALUE);
+        }
+        let device_name = format!("Mock CUDA Device {}", dev);
+        let copied = device_name.len().min(len as usize - 1);
+        std::ptr::copy_nonoverlapping(device_name.as_ptr().cast(), name, copied);
+        *name.add(copied) = 0;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuDeviceGetUuid(uuid: *mut CUuuid, dev: CUdevice) -> CUresult {
+    run(|_| {
+        check_device(dev)?;
+        out(uuid)?.bytes = device_uuid(dev).map(|byte| byte as c_char);
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuDeviceGetProperties(prop: *mut CUdevprop, dev: CUdevice) -> CUresult {
+    run(|_| {
+        check_device(dev)?;
+        *out(prop)? = CUdevprop {
+            maxThreadsPerBlock: MAX_THREADS_PER_BLOCK as i32,
+            maxThreadsDim: [1024, 1024, 64],
+            maxGridSize: [i32::MAX, 65535, 65535],
+            sharedMemPerBlock: 48 * 1024,
+            totalConstantMemory: 64 * 1024,
+            SIMDWidth: 32,
+            memPitch: i32::MAX,
+            regsPerBlock: 64 * 1024,
+            clockRate: 1_500_000,
+            textureAlign: 512,
+        };
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuDeviceTotalMem_v2(bytes: *mut usize, dev: CUdevice) -> CUresult {
+    run(|_| {
+        check_device(dev)?;
+        *out(bytes)? = DEVICE_MEMORY as usize;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuDeviceGetAttribute(pi: *mut i32, attrib: i32, dev: CUdevice) -> CUresult {
+    run(|_| {
+        check_device(dev)?;
+        *out(pi)? = match attrib {
+            CU_DEVICE_ATTRIBUTE_MAX_THREADS_PER_BLOCK => MAX_THREADS_PER_BLOCK as i32,
+            CU_DEVICE_ATTRIBUTE_WARP_SIZE => 32,
+            CU_DEVICE_ATTRIBUTE_MULTIPROCESSOR_COUNT => 16,
+            CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MAJOR => 8,
+            CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MINOR => 0,
+            _ => return Err(CUDA_ERROR_INVALID_VALUE),
+        };
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuCtxCreate_v2(pctx: *mut CUcontext, _flags: c_uint, dev: CUdevice) -> CUresult {
+    run(|driver| {
+        let pctx = out(pctx)?;
+        let context = driver.create_context(dev)?;
+        CURRENT_CONTEXT.with(|current| current.set(context));
+        *pctx = context as CUcontext;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuCtxDestroy_v2(ctx: CUcontext) -> CUresult {
+    run(|driver| {
+        driver.contexts.remove(&(ctx as usize)).ok_or(CUDA_ERROR_INVALID_CONTEXT)?;
+        CURRENT_CONTEXT.with(|current| if current.get() == ctx as usize {
+            current.set(0);
+        });
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuCtxSetCurrent(ctx: CUcontext) -> CUresult {
+    run(|driver| {
+        if !ctx.is_null() && !driver.contexts.contains_key(&(ctx as usize)) {
+            return Err(CUDA_ERROR_INVALID_CONTEXT);
+        }
+        CURRENT_CONTEXT.with(|current| current.set(ctx as usize));
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuCtxGetCurrent(pctx: *mut CUcontext) -> CUresult {
+    run(|_| {
+        *out(pctx)? = CURRENT_CONTEXT.with(Cell::get) as CUcontext;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuCtxGetDevice(device: *mut CUdevice) -> CUresult {
+    run(|driver| {
+        *out(device)? = driver.current_device()?;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuCtxSynchronize() -> CUresult {
+    run(|driver| driver.current_device().map(|_| ()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuDevicePrimaryCtxRetain(pctx: *mut CUcontext, dev: CUdevice) -> CUresult {
+    run(|driver| {
+        let pctx = out(pctx)?;
+        let context = match driver.primary_contexts.get_mut(&dev) {
+            Some((context, references)) => {
+                *references += 1;
+                *context
+            }
+            None => {
+                let context = driver.create_context(dev)?;
+                driver.primary_contexts.insert(dev, (context, 1));
+                context
+            }
+        };
+        *pctx = context as CUcontext;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuDevicePrimaryCtxRelease_v2(dev: CUdevice) -> CUresult {
+    run(|driver| {
+        check_device(dev)?;
+        let (context, references) = driver.primary_contexts.get_mut(&dev).ok_or(CUDA_ERROR_INVALID_CONTEXT)?;
+        *references -= 1;
+        if *references == 0 {
+            let context = *context;
+            driver.primary_contexts.remove(&dev);
+            driver.contexts.remove(&context);
+        }
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuMemGetInfo_v2(free: *mut usize, total: *mut usize) -> CUresult {
+    run(|driver| {
+        let device = driver.current_device()?;
+        *out(free)? = (DEVICE_MEMORY - driver.memory.used(device)) as usize;
+        *out(total)? = DEVICE_MEMORY as usize;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuMemAlloc_v2(dptr: *mut CUdeviceptr, bytesize: usize) -> CUresult {
+    run(|driver| {
+        let dptr = out(dptr)?;
+        let (context, device) = driver.current_context()?;
+        *dptr = driver.memory.alloc(device, context, bytesize, DEVICE_MEMORY)?;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuMemAllocPitch_v2(dptr: *mut CUdeviceptr,
+                                            p_pitch: *mut usize,
+                                            width_in_bytes: usize,
+                                            height: usize,
+                                            element_size_bytes: c_uint) -> CUresult {
+    run(|driver| {
+        let (dptr, p_pitch) = (out(dptr)?, out(p_pitch)?);
+        if ![4, 8, 16].contains(&element_size_bytes) {
+            return Err(CUDA_ERROR_INVALID_VALUE);
+        }
+        let (context, device) = driver.current_context()?;
+        let pitch = width_in_bytes.next_multiple_of(PITCH_ALIGNMENT);
+        let bytesize = pitch.checked_mul(height).ok_or(CUDA_ERROR_INVALID_VALUE)?;
+        *dptr = driver.memory.alloc(device, context, bytesize, DEVICE_MEMORY)?;
+        *p_pitch = pitch;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuMemFree_v2(dptr: CUdeviceptr) -> CUresult {
+    run(|driver| {
+        driver.current_device()?;
+        driver.memory.free(dptr)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuPointerGetAttribute(data: *mut c_void, attribute: i32, ptr: CUdeviceptr) -> CUresult {
+    run(|driver| match attribute {
+        CU_POINTER_ATTRIBUTE_CONTEXT => {
+            *out(data.cast::<CUcontext>())? = driver.memory.context(ptr)? as CUcontext;
+            Ok(())
+        }
+        _ => Err(CUDA_ERROR_INVALID_VALUE),
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuMemcpyHtoD_v2(dst_device: CUdeviceptr, src_host: *const c_void, byte_count: usize) -> CUresult {
+    run(|driver| memcpy_htod(driver, dst_device, src_host, byte_count))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuMemcpyDtoH_v2(dst_host: *mut c_void, src_device: CUdeviceptr, byte_count: usize) -> CUresult {
+    run(|driver| memcpy_dtoh(driver, dst_host, src_device, byte_count))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuMemcpyDtoD_v2(dst_device: CUdeviceptr, src_device: CUdeviceptr, byte_count: usize) -> CUresult {
+    run(|driver| memcpy_dtod(driver, dst_device, src_device, byte_count))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuMemcpyHtoDAsync_v2(dst_device: CUdeviceptr,
+                                              src_host: *const c_void,
+                                              byte_count: usize,
+                                              stream: CUstream) -> CUresult {
+    run(|driver| {
+        driver.check_stream(stream)?;
+        memcpy_htod(driver, dst_device, src_host, byte_count)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuMemcpyDtoHAsync_v2(dst_host: *mut c_void,
+                                              src_device: CUdeviceptr,
+                                              byte_count: usize,
+                                              stream: CUstream) -> CUresult {
+    run(|driver| {
+        driver.check_stream(stream)?;
+        memcpy_dtoh(driver, dst_host, src_device, byte_count)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuMemsetD8_v2(dst_device: CUdeviceptr, uc: u8, n: usize) -> CUresult {
+    run(|driver| {
+        driver.current_device()?;
+        driver.memory.get_mut(dst_device, n)?.fill(uc);
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuMemsetD32_v2(dst_device: CUdeviceptr, ui: c_uint, n: usize) -> CUresult {
+    run(|driver| {
+        driver.current_device()?;
+        let bytes = n.checked_mul(size_of::<c_uint>()).ok_or(CUDA_ERROR_INVALID_VALUE)?;
+        for value in driver.memory.get_mut(dst_device, bytes)?.chunks_exact_mut(size_of::<c_uint>()) {
+            value.copy_from_slice(&ui.to_ne_bytes());
+        }
+        Ok(())
+    })
+}
+
+/// Copies row by row between host and device memory. There are no arrays.
+#[no_mangle]
+pub unsafe extern "C" fn cuMemcpy2D_v2(p_copy: *const CUDA_MEMCPY2D) -> CUresult {
+    run(|driver| {
+        driver.current_device()?;
+        let copy = p_copy.as_ref().ok_or(CUDA_ERROR_INVALID_VALUE)?;
+        let width = copy.WidthInBytes;
+        if copy.Height > 1 && (copy.srcPitch < width || copy.dstPitch < width) {
+            return Err(CUDA_ERROR_INVALID_VALUE);
+        }
+        for row in 0..copy.Height {
+            let src = row_address(copy.srcMemoryType, copy.srcHost, copy.srcDevice, copy.srcXInBytes,
+                                  copy.srcY.checked_add(row), copy.srcPitch)?;
+            let dst = row_address(copy.dstMemoryType, copy.dstHost, copy.dstDevice, copy.dstXInBytes,
+                                  copy.dstY.checked_add(row), copy.dstPitch)?;
+            let bytes = match copy.srcMemoryType {
+                CU_MEMORYTYPE_HOST => std::slice::from_raw_parts(src as *const u8, width).to_vec(),
+                _ => driver.memory.get(src, width)?.to_vec(),
+            };
+            match copy.dstMemoryType {
+                CU_MEMORYTYPE_HOST => std::slice::from_raw_parts_mut(dst as *mut u8, width).copy_from_slice(&bytes),
+                _ => driver.memory.get_mut(dst, width)?.copy_from_slice(&bytes),
+            }
+        }
+        Ok(())
+    })
+}
+
+/// The address of row `y` of a 2D copy, in host or device memory.
+fn row_address(memory_type: c_int,
+               host: *const c_void,
+               device: CUdeviceptr,
+               x: usize,
+               y: Option<usize>,
+               pitch: usize) -> Result<u64, CUresult> {
+    let start = match memory_type {
+        CU_MEMORYTYPE_HOST if !host.is_null() => host as u64,
+        CU_MEMORYTYPE_DEVICE | CU_MEMORYTYPE_UNIFIED => device,
+        _ => return Err(CUDA_ERROR_INVALID_VALUE),
+    };
+    y.and_then(|y| y.checked_mul(pitch))
+        .and_then(|offset| offset.checked_add(x))
+        .and_then(|offset| start.checked_add(offset as u64))
+        .ok_or(CUDA_ERROR_INVALID_VALUE)
+}
+
+unsafe fn memcpy_htod(driver: &mut Driver, dst_device: CUdeviceptr, src_host: *const c_void, byte_count: usize) -> Result<(), CUresult> {
+    driver.current_device()?;
+    if byte_count == 0 {
+        return Ok(());
+    }
+    if src_host.is_null() {
+        return Err(CUDA_ERROR_INVALID_VALUE);
+    }
+    let src = std::slice::from_raw_parts(src_host.cast::<u8>(), byte_count);
+    driver.memory.get_mut(dst_device, byte_count)?.copy_from_slice(src);
+    Ok(())
+}
+
+unsafe fn memcpy_dtoh(driver: &mut Driver, dst_host: *mut c_void, src_device: CUdeviceptr, byte_count: usize) -> Result<(), CUresult> {
+    driver.current_device()?;
+    if byte_count == 0 {
+        return Ok(());
+    }
+    if dst_host.is_null() {
+        return Err(CUDA_ERROR_INVALID_VALUE);
+    }
+    let dst = std::slice::from_raw_parts_mut(dst_host.cast::<u8>(), byte_count);
+    dst.copy_from_slice(driver.memory.get(src_device, byte_count)?);
+    Ok(())
+}
+
+fn memcpy_dtod(driver: &mut Driver, dst_device: CUdeviceptr, src_device: CUdeviceptr, byte_count: usize) -> Result<(), CUresult> {
+    driver.current_device()?;
+    if byte_count == 0 {
+        return Ok(());
+    }
+    let src = driver.memory.get(src_device, byte_count)?.to_vec();
+    driver.memory.get_mut(dst_device, byte_count)?.copy_from_slice(&src);
+    Ok(())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuStreamCreate(ph_stream: *mut CUstream, _flags: c_uint) -> CUresult {
+    run(|driver| {
+        let ph_stream = out(ph_stream)?;
+        driver.current_device()?;
+        let stream = driver.handle();
+        driver.streams.insert(stream);
+        *ph_stream = stream as CUstream;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuStreamDestroy_v2(h_stream: CUstream) -> CUresult {
+    run(|driver| match driver.streams.remove(&(h_stream as usize)) {
+        true => Ok(()),
+        false => Err(CUDA_ERROR_INVALID_HANDLE),
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuStreamSynchronize(h_stream: CUstream) -> CUresult {
+    run(|driver| driver.check_stream(h_stream))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuStreamQuery(h_stream: CUstream) -> CUresult {
+    run(|driver| driver.check_stream(h_stream))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuStreamWaitEvent(h_stream: CUstream, h_event: CUevent, _flags: c_uint) -> CUresult {
+    run(|driver| {
+        driver.check_stream(h_stream)?;
+        driver.check_event(h_event)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuEventCreate(ph_event: *mut CUevent, _flags: c_uint) -> CUresult {
+    run(|driver| {
+        let ph_event = out(ph_event)?;
+        driver.current_device()?;
+        let event = driver.handle();
+        driver.events.insert(event);
+        *ph_event = event as CUevent;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuEventDestroy_v2(h_event: CUevent) -> CUresult {
+    run(|driver| match driver.events.remove(&(h_event as usize)) {
+        true => Ok(()),
+        false => Err(CUDA_ERROR_INVALID_HANDLE),
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuEventRecord(h_event: CUevent, h_stream: CUstream) -> CUresult {
+    run(|driver| {
+        driver.check_event(h_event)?;
+        driver.check_stream(h_stream)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuEventSynchronize(h_event: CUevent) -> CUresult {
+    run(|driver| driver.check_event(h_event))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuEventQuery(h_event: CUevent) -> CUresult {
+    run(|driver| driver.check_event(h_event))
+}
+
+/// Kernels take no time.
+#[no_mangle]
+pub unsafe extern "C" fn cuEventElapsedTime(p_milliseconds: *mut f32, h_start: CUevent, h_end: CUevent) -> CUresult {
+    run(|driver| {
+        driver.check_event(h_start)?;
+        driver.check_event(h_end)?;
+        *out(p_milliseconds)? = 0.0;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuModuleLoadData(module: *mut CUmodule, image: *const c_void) -> CUresult {
+    run(|driver| {
+        let module = out(module)?;
+        driver.current_device()?;
+        if image.is_null() {
+            return Err(CUDA_ERROR_INVALID_VALUE);
+        }
+        *module = load_module(driver, Module::from_image(image.cast())?);
+        Ok(())
+    })
+}
+
+/// Of the JIT options, only writes the error log, whose size option must come first.
+#[no_mangle]
+pub unsafe extern "C" fn cuModuleLoadDataEx(module: *mut CUmodule,
+                                            image: *const c_void,
+                                            num_options: c_uint,
+                                            options: *mut c_int,
+                                            option_values: *mut *mut c_void) -> CUresult {
+    let result = cuModuleLoadData(module, image);
+    if result != CUDA_SUCCESS && num_options > 0 {
+        let options = std::slice::from_raw_parts(options, num_options as usize);
+        let option_values = std::slice::from_raw_parts_mut(option_values, num_options as usize);
+        let mut log_size = None;
+        for index in 0..options.len() {
+            match (options[index], log_size) {
+                (CU_JIT_ERROR_LOG_BUFFER_SIZE_BYTES, _) => log_size = Some(index),
+                (CU_JIT_ERROR_LOG_BUFFER, Some(size_index)) => {
+                    let log = format!("error {}\0", result);
+                    let length = log.len().min(option_values[size_index] as usize);
+                    std::ptr::copy_nonoverlapping(log.as_ptr(), option_values[index].cast::<u8>(), length);
+                    // The driver sets the size to the length of the log.
+                    option_values[size_index] = length as *mut c_void;
+                }
+                _ => {}
+            }
+        }
+    }
+    result
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuModuleLoadFatBinary(module: *mut CUmodule, fat_cubin: *const c_void) -> CUresult {
+    cuModuleLoadData(module, fat_cubin)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuModuleLoad(module: *mut CUmodule, fname: *const c_char) -> CUresult {
+    run(|driver| {
+        let module = out(module)?;
+        driver.current_device()?;
+        if fname.is_null() {
+            return Err(CUDA_ERROR_INVALID_VALUE);
+        }
+        let path = CStr::from_ptr(fname).to_str().map_err(|_| CUDA_ERROR_INVALID_VALUE)?;
+        let image = std::fs::read(path).map_err(|_| CUDA_ERROR_FILE_NOT_FOUND)?;
+        *module = load_module(driver, Module::from_bytes(&image)?);
+        Ok(())
+    })
+}
+
+fn load_module(driver: &mut Driver, module: Module) -> CUmodule {
+    let handle = driver.handle();
+    driver.modules.insert(handle, module);
+    handle as CUmodule
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuModuleUnload(hmod: CUmodule) -> CUresult {
+    run(|driver| {
+        let module = hmod as usize;
+        driver.modules.remove(&module).ok_or(CUDA_ERROR_INVALID_HANDLE)?;
+        driver.functions.retain(|_, function| function.module != module);
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cuModuleGetFunction(hfunc: *mut CUfunction, hmod: CUmodule, name: *const c_char) -> CUresult {
+    run(|driver| {
+        let hfunc = out(hfunc)?;
+        if name.is_null() {
+            return Err(CUDA_ERROR_INVALID_VALUE);
+        }
+        let module = driver.modules.get(&(hmod as usize)).ok_or(CUDA_ERROR_INVALID_HANDLE)?;
+        let name = CStr::from_ptr(name).to_str().map_err(|_| CUDA_ERROR_NOT_FOUND)?;
+        if !module.has_kernel(name) {
+            return Err(CUDA_ERROR_NOT_FOUND);
+        }
+        let function = driver.handle();
+        driver.functions.insert(function, Function { module: hmod as usize, name: name.to_string() });
+        *hfunc = function as CUfunction;
+        Ok(())
+    })
+}
+
+/// Only for kernels of PTX modules.
+#[no_mangle]
+pub unsafe extern "C" fn cuFuncGetParamInfo(func: CUfunction,
+                                            param_index: usize,
+                                            param_offset: *mut usize,
+                                            param_size: *mut usize) -> CUresult {
+    run(|driver| {
+        let params = function_params(driver, func)?.ok_or(CUDA_ERROR_NOT_SUPPORTED)?;
+        let (offset, size) = params.get(param_index).ok_or(CUDA_ERROR_INVALID_VALUE)?;
+        *out(param_offset)? = *offset;
+        *out(param_size)? = *size;
+        Ok(())
+    })
+}
+
+fn function_params(driver: &Driver, func: CUfunction) -> Result<Option<&[(usize, usize)]>, CUresult> {
+    let function = driver.functions.get(&(func as usize)).ok_or(CUDA_ERROR_INVALID_HANDLE)?;
+    Ok(driver.modules[&function.module].params(&function.name))
+}
+
+/// Checks the launch configuration. A kernel of PTX named `fill` with the parameters `(.u64 dst, .u32 value,
+/// .u32 count)` sets `count` 32-bit words at `dst` to `value`, other kernels do nothing.
+#[no_mangle]
+pub unsafe extern "C" fn cuLaunchKernel(f: CUfunction,
+                                        grid_dim_x: c_uint,
+                                        grid_dim_y: c_uint,
+                                        grid_dim_z: c_uint,
+                                        block_dim_x: c_uint,
+                                        block_dim_y: c_uint,
+                                        block_dim_z: c_uint,
+                                        _shared_mem_bytes: c_uint,
+                                        h_stream: CUstream,
+                                        kernel_params: *mut *mut c_void,
+                                        extra: *mut *mut c_void) -> CUresult {
+    run(|driver| {
+        driver.current_device()?;
+        let params = function_params(driver, f)?;
+        driver.check_stream(h_stream)?;
+        let threads_per_block = block_dim_x as u64 * block_dim_y as u64 * block_dim_z as u64;
+        if grid_dim_x == 0 || grid_dim_y == 0 || grid_dim_z == 0
+            || threads_per_block == 0 || threads_per_block > MAX_THREADS_PER_BLOCK as u64 {
+            return Err(CUDA_ERROR_INVALID_VALUE);
+        }
+        if !kernel_params.is_null() && !extra.is_null() {
+            return Err(CUDA_ERROR_INVALID_VALUE);
+        }
+        match (driver.functions[&(f as usize)].name.as_str(), params) {
+            ("fill", Some(params @ [_, _, _])) => {
+                let arguments = kernel_arguments(params, kernel_params, extra)?;
+                let dst = CUdeviceptr::from_ne_bytes(arguments[0].try_into().map_err(|_| CUDA_ERROR_INVALID_VALUE)?);
+                let value = u32::from_ne_bytes(arguments[1].try_into().map_err(|_| CUDA_ERROR_INVALID_VALUE)?);
+                let count = u32::from_ne_bytes(arguments[2].try_into().map_err(|_| CUDA_ERROR_INVALID_VALUE)?);
+                let words = driver.memory.get_mut(dst, count as usize * size_of::<u32>())
+                    .map_err(|_| CUDA_ERROR_ILLEGAL_ADDRESS)?;
+                for word in words.chunks_exact_mut(size_of::<u32>()) {
+                    word.copy_from_slice(&value.to_ne_bytes());
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    })
+}
+
+/// The bytes of every parameter, passed either as `kernel_params` or in the buffer of `extra`.
+unsafe fn kernel_arguments<'a>(params: &[(usize, usize)],
+                               kernel_params: *mut *mut c_void,
+                               extra: *mut *mut c_void) -> Result<Vec<&'a [u8]>, CUresult> {
+    if !kernel_params.is_null() {
+        return Ok(params.iter().enumerate()
+            .map(|(index, (_, size))| std::slice::from_raw_parts((*kernel_params.add(index)).cast::<u8>(), *size))
+            .collect());
+    }
+    let (mut buffer, mut buffer_size) = (None, None);
+    let mut entry = extra;
+    while !entry.is_null() && *entry as usize != CU_LAUNCH_PARAM_END {
+        match *entry as usize {
+            CU_LAUNCH_PARAM_BUFFER_POINTER => buffer = Some((*entry.add(1)).cast::<u8>()),
+            CU_LAUNCH_PARAM_BUFFER_SIZE => buffer_size = Some(*(*entry.add(1)).cast::<usize>()),
+            _ => return Err(CUDA_ERROR_INVALID_VALUE),
+        }
+        entry = entry.add(2);
+    }
+    let (Some(buffer), Some(buffer_size)) = (buffer, buffer_size) else {
+        return Err(CUDA_ERROR_INVALID_VALUE);
+    };
+    params.iter()
+        .map(|(offset, size)| match offset + size <= buffer_size {
+            true => Ok(std::slice::from_raw_parts(buffer.add(*offset), *size)),
+            false => Err(CUDA_ERROR_INVALID_VALUE),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr::null_mut;
+
+    #[test]
+    fn allocate_copy_launch() {
+        unsafe {
+            assert_eq!(cuInit(0), CUDA_SUCCESS);
+            let mut context = null_mut();
+            assert_eq!(cuCtxCreate_v2(&mut context, 0, DEVICE_COUNT), CUDA_ERROR_INVALID_DEVICE);
+            assert_eq!(cuCtxCreate_v2(&mut context, 0, 1), CUDA_SUCCESS);
+
+            let mut dptr = 0;
+            assert_eq!(cuMemAlloc_v2(&mut dptr, 8), CUDA_SUCCESS);
+            let data = [1_u8, 2, 3, 4, 5, 6, 7, 8];
+            assert_eq!(cuMemcpyHtoD_v2(dptr, data.as_ptr().cast(), 8), CUDA_SUCCESS);
+            assert_eq!(cuMemsetD8_v2(dptr + 4, 0, 4), CUDA_SUCCESS);
+            let mut read = [0_u8; 8];
+            assert_eq!(cuMemcpyDtoH_v2(read.as_mut_ptr().cast(), dptr, 8), CUDA_SUCCESS);
+            assert_eq!(read, [1, 2, 3, 4, 0, 0, 0, 0]);
+            assert_eq!(cuMemcpyDtoH_v2(read.as_mut_ptr().cast(), dptr + 1, 8), CUDA_ERROR_INVALID_VALUE);
+
+            let mut module = null_mut();
+            let ptx = c".version 8.0\n.visible .entry kernel()\n{\nret;\n}\n";
+            assert_eq!(cuModuleLoadData(&mut module, ptx.as_ptr().cast()), CUDA_SUCCESS);
+            let mut function = null_mut();
+            assert_eq!(cuModuleGetFunction(&mut function, module, c"other".as_ptr()), CUDA_ERROR_NOT_FOUND);
+            assert_eq!(cuModuleGetFunction(&mut function, module, c"kernel".as_ptr()), CUDA_SUCCESS);
+            let launch = |block_dim_x| cuLaunchKernel(function, 1, 1, 1, block_dim_x, 1, 1, 0, null_mut(), null_mut(), null_mut());
+            assert_eq!(launch(MAX_THREADS_PER_BLOCK), CUDA_SUCCESS);
+            assert_eq!(launch(MAX_THREADS_PER_BLOCK + 1), CUDA_ERROR_INVALID_VALUE);
+            assert_eq!(cuModuleUnload(module), CUDA_SUCCESS);
+            assert_eq!(launch(1), CUDA_ERROR_INVALID_HANDLE);
+
+            let ptx = c".version 8.0\n.visible .entry fill(.param .u64 dst, .param .u32 value, .param .u32 count)\n{\nret;\n}\n";
+            assert_eq!(cuModuleLoadData(&mut module, ptx.as_ptr().cast()), CUDA_SUCCESS);
+            assert_eq!(cuModuleGetFunction(&mut function, module, c"fill".as_ptr()), CUDA_SUCCESS);
+            let (mut offset, mut size) = (0, 0);
+            assert_eq!(cuFuncGetParamInfo(function, 2, &mut offset, &mut size), CUDA_SUCCESS);
+            assert_eq!((offset, size), (12, 4));
+            assert_eq!(cuFuncGetParamInfo(function, 3, &mut offset, &mut size), CUDA_ERROR_INVALID_VALUE);
+            let (mut dst, mut value, mut count) = (dptr + 4, 0x0101_0101_u32, 1_u32);
+            let mut params = [&mut dst as *mut u64 as *mut c_void, &mut value as *mut u32 as *mut c_void,
+                              &mut count as *mut u32 as *mut c_void];
+            assert_eq!(cuLaunchKernel(function, 1, 1, 1, 1, 1, 1, 0, null_mut(), params.as_mut_ptr(), null_mut()), CUDA_SUCCESS);
+            assert_eq!(cuMemcpyDtoH_v2(read.as_mut_ptr().cast(), dptr, 8), CUDA_SUCCESS);
+            assert_eq!(read, [1, 2, 3, 4, 1, 1, 1, 1]);
+            *params[2].cast::<u32>() = 2;
+            assert_eq!(cuLaunchKernel(function, 1, 1, 1, 1, 1, 1, 0, null_mut(), params.as_mut_ptr(), null_mut()),
+                       CUDA_ERROR_ILLEGAL_ADDRESS);
+
+            assert_eq!(cuMemFree_v2(dptr), CUDA_SUCCESS);
+            assert_eq!(cuCtxDestroy_v2(context), CUDA_SUCCESS);
+            assert_eq!(cuMemAlloc_v2(&mut dptr, 8), CUDA_ERROR_INVALID_CONTEXT);
+        }
+    }
+}
@@ -0,0 +1,115 @@
+//! Device memory, backed by host memory.
+
+use crate::{CUdevice, CUdeviceptr};
+use cuda_over_ip_common::cuda::{CUresult, CUDA_ERROR_INVALID_VALUE, CUDA_ERROR_OUT_OF_MEMORY};
+use std::collections::BTreeMap;
+
+/// Address of the first allocation, keeps device pointers apart from small integers.
+const BASE_ADDRESS: CUdeviceptr = 0x7_0000_0000;
+
+/// Alignment of allocations, as `cuMemAlloc` guarantees.
+const ALIGNMENT: u64 = 256;
+
+struct Allocation {
+    device: CUdevice,
+    /// The context the allocation was made in.
+    context: usize,
+    bytes: Vec<u8>,
+}
+
+/// Allocations of all devices in one address space. Addresses are never reused, so they are
+/// the same from run to run and a stale pointer is detected.
+pub(crate) struct DeviceMemory {
+    allocations: BTreeMap<CUdeviceptr, Allocation>,
+    next_address: CUdeviceptr,
+}
+
+impl DeviceMemory {
+    pub(crate) const fn new() -> Self {
+        DeviceMemory { allocations: BTreeMap::new(), next_address: BASE_ADDRESS }
+    }
+
+    /// Allocates zeroed memory in `context` on `device`, which has `capacity` bytes in total.
+    pub(crate) fn alloc(&mut self, device: CUdevice, context: usize, size: usize, capacity: u64) -> Result<CUdeviceptr, CUresult> {
+        if size == 0 {
+            return Err(CUDA_ERROR_INVALID_VALUE);
+        }
+        if self.used(device) + size as u64 > capacity {
+            return Err(CUDA_ERROR_OUT_OF_MEMORY);
+        }
+        let address = self.next_address;
+        self.next_address += (size as u64).next_multiple_of(ALIGNMENT);
+        self.allocations.insert(address, Allocation { device, context, bytes: vec![0; size] });
+        Ok(address)
+    }
+
+    /// Frees an allocation given its start address.
+    pub(crate) fn free(&mut self, address: CUdeviceptr) -> Result<(), CUresult> {
+        self.allocations.remove(&address).map(|_| ()).ok_or(CUDA_ERROR_INVALID_VALUE)
+    }
+
+    /// Bytes allocated on `device`.
+    pub(crate) fn used(&self, device: CUdevice) -> u64 {
+        self.allocations.values()
+            .filter(|allocation| allocation.device == device)
+            .map(|allocation| allocation.bytes.len() as u64)
+            .sum()
+    }
+
+    /// The context of the allocation containing `address`.
+    pub(crate) fn context(&self, address: CUdeviceptr) -> Result<usize, CUresult> {
+        let (base, _) = self.locate(address, 1)?;
+        Ok(self.allocations[&base].context)
+    }
+
+    /// The `len` bytes at `address`, which must lie within one allocation.
+    pub(crate) fn get(&self, address: CUdeviceptr, len: usize) -> Result<&[u8], CUresult> {
+        let (base, offset) = self.locate(address, len)?;
+        Ok(&self.allocations[&base].bytes[offset..offset + len])
+    }
+
+    pub(crate) fn get_mut(&mut self, address: CUdeviceptr, len: usize) -> Result<&mut [u8], CUresult> {
+        let (base, offset) = self.locate(address, len)?;
+        Ok(&mut self.allocations.get_mut(&base).unwrap().bytes[offset..offset + len])
+    }
+
+    /// The allocation containing the range and the offset of the range in it.
+    fn locate(&self, address: CUdeviceptr, len: usize) -> Result<(CUdeviceptr, usize), CUresult> {
+        let (&base, allocation) = self.allocations.range(..=address).next_back().ok_or(CUDA_ERROR_INVALID_VALUE)?;
+        let offset = (address - base) as usize;
+        match offset.checked_add(len) {
+            Some(end) if end <= allocation.bytes.len() => Ok((base, offset)),
+            _ => Err(CUDA_ERROR_INVALID_VALUE),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alloc_copy_free() {
+        let mut memory = DeviceMemory::new();
+        let a = memory.alloc(0, 5, 100, 1000).unwrap();
+        let b = memory.alloc(0, 6, 10, 1000).unwrap();
+        assert_eq!(a % ALIGNMENT, 0);
+        assert_eq!(b, a + ALIGNMENT);
+        assert_eq!(memory.used(0), 110);
+        assert_eq!(memory.used(1), 0);
+        assert_eq!(memory.context(b + 9), Ok(6));
+
+        memory.get_mut(a + 98, 2).unwrap().copy_from_slice(&[1, 2]);
+        assert_eq!(memory.get(a + 97, 3).unwrap(), &[0, 1, 2]);
+        assert_eq!(memory.get(a + 98, 3), Err(CUDA_ERROR_INVALID_VALUE));
+        assert_eq!(memory.get(a + 100, 1), Err(CUDA_ERROR_INVALID_VALUE));
+        assert_eq!(memory.get(a - 1, 1), Err(CUDA_ERROR_INVALID_VALUE));
+
+        assert_eq!(memory.alloc(0, 5, 891, 1000), Err(CUDA_ERROR_OUT_OF_MEMORY));
+        assert_eq!(memory.alloc(0, 5, 0, 1000), Err(CUDA_ERROR_INVALID_VALUE));
+        assert_eq!(memory.free(a + 1), Err(CUDA_ERROR_INVALID_VALUE));
+        memory.free(a).unwrap();
+        assert_eq!(memory.get(a, 1), Err(CUDA_ERROR_INVALID_VALUE));
+        assert_eq!(memory.used(0), 10);
+    }
+}
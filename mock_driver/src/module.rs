@@ -0,0 +1,113 @@
+//! Module images. The kernels of a PTX module and the layout of their parameters are taken from its `.entry`
+//! directives, binary images (cubin, fatbin) are not parsed and provide a kernel of any name.
+
+use cuda_over_ip_common::cuda::{CUresult, CUDA_ERROR_INVALID_IMAGE};
+
+const ELF_MAGIC: &[u8] = b"\x7fELF";
+const FATBIN_MAGIC: &[u8] = &0xBA55_ED50_u32.to_le_bytes();
+
+pub(crate) enum Module {
+    Ptx { kernels: Vec<Kernel> },
+    Binary,
+}
+
+pub(crate) struct Kernel {
+    name: String,
+    /// The offset and the size of every parameter.
+    params: Vec<(usize, usize)>,
+}
+
+impl Module {
+    /// Reads an image as passed to `cuModuleLoadData`, NUL-terminated if it is PTX.
+    ///
+    /// # Safety
+    /// `image` must point to a NUL-terminated PTX or to a cubin or fatbin.
+    pub(crate) unsafe fn from_image(image: *const u8) -> Result<Self, CUresult> {
+        // Binary images have a NUL within the first bytes of their header.
+        Self::from_bytes(std::ffi::CStr::from_ptr(image.cast()).to_bytes())
+    }
+
+    pub(crate) fn from_bytes(image: &[u8]) -> Result<Self, CUresult> {
+        if image.starts_with(ELF_MAGIC) || image.starts_with(FATBIN_MAGIC) {
+            return Ok(Module::Binary);
+        }
+
+        let ptx = std::str::from_utf8(image).map_err(|_| CUDA_ERROR_INVALID_IMAGE)?;
+        if !ptx.contains(".version") {
+            return Err(CUDA_ERROR_INVALID_IMAGE);
+        }
+        let kernels = ptx.split(".entry").skip(1).map(parse_entry).collect::<Result<_, _>>()?;
+        Ok(Module::Ptx { kernels })
+    }
+
+    pub(crate) fn has_kernel(&self, name: &str) -> bool {
+        match self {
+            Module::Ptx { kernels } => kernels.iter().any(|kernel| kernel.name == name),
+            Module::Binary => true,
+        }
+    }
+
+    /// The offsets and the sizes of the parameters of the kernel, unknown for binary images.
+    pub(crate) fn params(&self, name: &str) -> Option<&[(usize, usize)]> {
+        match self {
+            Module::Ptx { kernels } => kernels.iter().find(|kernel| kernel.name == name).map(|kernel| kernel.params.as_slice()),
+            Module::Binary => None,
+        }
+    }
+}
+
+/// Parses what follows `.entry`: the name, then the parameters in parentheses, e.g. `.param .u64 .ptr .align 8 p`
+/// or `.param .align 8 .b8 s[16]`.
+fn parse_entry(entry: &str) -> Result<Kernel, CUresult> {
+    let entry = entry.trim_start();
+    let name_end = entry.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '$')).unwrap_or(entry.len());
+    let rest = entry[name_end..].trim_start();
+    let mut params = Vec::new();
+    if let Some(rest) = rest.strip_prefix('(') {
+        let declarations = &rest[..rest.find(')').ok_or(CUDA_ERROR_INVALID_IMAGE)?];
+        let mut offset = 0;
+        for declaration in declarations.split(',').filter(|declaration| !declaration.trim().is_empty()) {
+            let tokens: Vec<&str> = declaration.split_whitespace().collect();
+            // A type like `.u64`, `.f32` or `.b8`.
+            let element_size = tokens.iter()
+                .filter_map(|token| token.strip_prefix(".")?.strip_prefix(['u', 's', 'b', 'f']))
+                .find_map(|bits| bits.parse::<usize>().ok())
+                .map(|bits| bits / 8)
+                .ok_or(CUDA_ERROR_INVALID_IMAGE)?;
+            let alignment = match tokens.iter().position(|token| *token == ".align") {
+                Some(index) => tokens.get(index + 1).and_then(|token| token.parse().ok()).ok_or(CUDA_ERROR_INVALID_IMAGE)?,
+                None => element_size,
+            };
+            let count = match tokens.last().and_then(|name| name.split_once('[')) {
+                Some((_, count)) => count.trim_end_matches(']').parse().map_err(|_| CUDA_ERROR_INVALID_IMAGE)?,
+                None => 1,
+            };
+            offset = usize::next_multiple_of(offset, alignment);
+            params.push((offset, element_size * count));
+            offset += element_size * count;
+        }
+    }
+    Ok(Kernel { name: entry[..name_end].to_string(), params })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ptx_kernels() {
+        let ptx = b".version 8.0\n.target sm_80\n.visible .entry add_one(\n.param .u64 p)\n{\nret;\n}\n.entry $scale (\n)\n\
+            .entry mixed(.param .u8 a, .param .u64 .ptr .global .align 8 b, .param .f32 c, .param .align 8 .b8 d[12])";
+        let module = Module::from_bytes(ptx).unwrap();
+        assert!(module.has_kernel("add_one"));
+        assert!(module.has_kernel("$scale"));
+        assert!(!module.has_kernel("add"));
+        assert_eq!(module.params("add_one"), Some(&[(0, 8)][..]));
+        assert_eq!(module.params("$scale"), Some(&[][..]));
+        assert_eq!(module.params("mixed"), Some(&[(0, 1), (8, 8), (16, 4), (24, 12)][..]));
+
+        assert!(Module::from_bytes(b"\x7fELF\x02\x01").unwrap().has_kernel("anything"));
+        assert_eq!(Module::from_bytes(b"\x7fELF\x02\x01").unwrap().params("anything"), None);
+        assert!(matches!(Module::from_bytes(b"not ptx"), Err(CUDA_ERROR_INVALID_IMAGE)));
+    }
+}
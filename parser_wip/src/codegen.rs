@@ -0,0 +1,627 @@
+use crate::model::{FieldType, Function, Length, Lifetime, Param, ParamType, Struct};
+use proc_macro2::{Literal, TokenStream};
+use quote::{quote, ToTokens};
+use std::fs::File;
+use std::io::Write;
+use syn::{parse2, Attribute, Item};
+use syn::parse::Parser;
+
+const HEADER: &str = "// Generated by parser_wip from parser_wip/functions.yaml, do not edit.\n\n";
+
+/// Generates the `RPC` enum and the structs shared by the client and the server.
+pub fn generate_common(output_path: &str, functions: &[Function], structs: &[Struct]) {
+    let import_toks = vec![
+        quote! {use crate::wire::Wire;},
+        quote! {use std::io;},
+    ];
+
+    let variant_toks: Vec<TokenStream> = functions.iter().map(|function| {
+        let name_tok: TokenStream = function.name.parse().unwrap();
+        let id_tok: TokenStream = function.id.to_string().parse().unwrap();
+        quote! { #name_tok = #id_tok }
+    }).collect();
+
+    let rpc_tok = quote! {
+        #[repr(i32)]
+        #[derive(PartialEq, Debug, Clone, Copy, FromPrimitive)]
+        pub enum RPC {
+            #(#variant_toks),*
+        }
+    };
+
+    let struct_toks = structs.iter().flat_map(generate_struct);
+    let items = import_toks.into_iter()
+        .chain(vec![rpc_tok])
+        .chain(struct_toks)
+        .collect();
+    write_rust_file(output_path, vec![quote! {#![allow(non_camel_case_types, non_snake_case)]}], items);
+}
+
+/// Generates the struct with the layout of the C struct, checked at compile time, and its encoding.
+fn generate_struct(struct_: &Struct) -> Vec<TokenStream> {
+    let name_tok: TokenStream = struct_.name.parse().unwrap();
+    let field_name_toks: Vec<TokenStream> = struct_.fields.iter().map(|field| field.name.parse().unwrap()).collect();
+    let field_type_toks: Vec<TokenStream> = struct_.fields.iter().map(|field| field_type(&field.type_)).collect();
+    let size_tok = Literal::usize_unsuffixed(struct_.size);
+    let offset_toks: Vec<Literal> = struct_.fields.iter().map(|field| Literal::usize_unsuffixed(field.offset)).collect();
+
+    let mut toks = vec![
+        quote! {
+            #[repr(C)]
+            #[derive(Clone, Copy, Debug, PartialEq)]
+            pub struct #name_tok {
+                #(pub #field_name_toks: #field_type_toks),*
+            }
+        },
+        quote! {
+            const _: () = {
+                let size = size_of::<#name_tok>();
+                assert!(size == #size_tok);
+                #(assert!(std::mem::offset_of!(#name_tok, #field_name_toks) == #offset_toks);)*
+            };
+        },
+    ];
+    toks.push(quote! {
+        impl Default for #name_tok {
+            fn default() -> Self {
+                unsafe { std::mem::zeroed() }
+            }
+        }
+    });
+    toks.push(quote! {
+        impl Wire for #name_tok {
+            fn write_to(&self, writer: &mut Vec<u8>) {
+                #(self.#field_name_toks.write_to(writer);)*
+            }
+
+            fn read_from(reader: &mut &[u8]) -> io::Result<Self> {
+                Ok(#name_tok {
+                    #(#field_name_toks: Wire::read_from(reader)?),*
+                })
+            }
+        }
+    });
+    toks
+}
+
+fn field_type(type_: &FieldType) -> TokenStream {
+    match type_ {
+        FieldType::Scalar(rust_type) | FieldType::Pointer(rust_type) | FieldType::Struct(rust_type) =>
+            rust_type.parse().unwrap(),
+        FieldType::Handle(handle_type) => handle_type.rust_type.parse().unwrap(),
+        FieldType::Array(element, length) => {
+            let element_tok = field_type(element);
+            let length_tok = Literal::usize_unsuffixed(*length);
+            quote! { [#element_tok; #length_tok] }
+        }
+    }
+}
+
+/// Generates the functions the client library exports, which forward the calls to the server.
+pub fn generate_client(output_path: &str, functions: &[Function]) {
+    let import_toks = vec![
+        quote! {use byteorder::{BigEndian, NativeEndian, ReadBytesExt};},
+        quote! {use std::io::Read;},
+        quote! {use cuda_over_ip_common::RPC;},
+        quote! {use cuda_over_ip_common::cuda::{CUresult, CUDA_ERROR_INVALID_VALUE, CUDA_SUCCESS};},
+        quote! {use cuda_over_ip_common::handle::Handle;},
+        quote! {use cuda_over_ip_common::wire::{to_wire, Wire};},
+        quote! {use crate::non_generated::{self, array_as_u8_slice, as_u8_slice, ptr_as_u8_slice};},
+    ];
+
+    let function_toks: Vec<TokenStream> = functions.iter()
+        .filter(|function| !function.hand_written)
+        .map(generate_client_function)
+        .collect();
+
+    write_rust_file(output_path, vec![quote! {#![allow(non_snake_case, unused_imports)]}], import_toks.into_iter().chain(function_toks).collect());
+}
+
+fn generate_client_function(function: &Function) -> TokenStream {
+    let name_tok: TokenStream = function.name.parse().unwrap();
+    let params_tok: Vec<TokenStream> = function.params.iter().map(|param| {
+        let name_tok: TokenStream = param.name.parse().unwrap();
+        let type_tok = c_param_type(param);
+        quote! { #name_tok: #type_tok }
+    }).collect();
+
+    // Like the driver, reject negative lengths and null pointers before making the call.
+    let length_toks: Vec<TokenStream> = function.params.iter().filter_map(|param| match &param.type_ {
+        ParamType::Array { length, .. } => {
+            let length_name_tok = length_name(param);
+            Some(match length {
+                Length::Constant(length) => {
+                    let length_tok = Literal::usize_unsuffixed(*length);
+                    quote! { let #length_name_tok: usize = #length_tok; }
+                }
+                Length::Param(length_param) => {
+                    let length_param_tok: TokenStream = length_param.parse().unwrap();
+                    quote! {
+                        let Ok(#length_name_tok) = usize::try_from(#length_param_tok) else {
+                            return CUDA_ERROR_INVALID_VALUE;
+                        };
+                    }
+                }
+            })
+        }
+        _ => None,
+    }).collect();
+    let pointer_name_toks: Vec<TokenStream> = function.params.iter()
+        .filter(|param| !is_by_value(param))
+        .map(|param| param.name.parse().unwrap())
+        .collect();
+    let null_check_tok = match pointer_name_toks.is_empty() {
+        true => quote! {},
+        false => quote! {
+            if #(#pointer_name_toks.is_null())||* {
+                return CUDA_ERROR_INVALID_VALUE;
+            }
+        },
+    };
+
+    // The values come first, so that the server knows the lengths of the arrays when reading them.
+    let in_slice_toks: Vec<TokenStream> = payload_order(function).filter(|param| param.is_in()).map(|param| {
+        let name_tok: TokenStream = param.name.parse().unwrap();
+        match &param.type_ {
+            ParamType::Value(_) => quote! { as_u8_slice(&#name_tok) },
+            ParamType::Pointer(_) => quote! { as_u8_slice(&*#name_tok) },
+            ParamType::Array { .. } => {
+                let length_name_tok = length_name(param);
+                quote! { array_as_u8_slice(#name_tok, #length_name_tok) }
+            }
+            ParamType::Struct(_) => quote! { to_wire(&*#name_tok).as_slice() },
+            ParamType::Handle(_) => quote! { as_u8_slice(&#name_tok.to_u64()) },
+            ParamType::HandlePointer(_) => quote! { as_u8_slice(&(*#name_tok).to_u64()) },
+        }
+    }).collect();
+    let payload_tok = match in_slice_toks.is_empty() {
+        true => quote! { Vec::new() },
+        false => quote! { [#(#in_slice_toks),*].concat() },
+    };
+
+    let read_out_toks: Vec<TokenStream> = function.params.iter().filter(|param| param.is_out()).map(|param| {
+        let name_tok: TokenStream = param.name.parse().unwrap();
+        match &param.type_ {
+            ParamType::Array { .. } => {
+                let length_name_tok = length_name(param);
+                quote! { reply.read_exact(array_as_u8_slice(#name_tok, #length_name_tok))?; }
+            }
+            ParamType::Struct(_) => quote! { *#name_tok = Wire::read_from(reply)?; },
+            ParamType::HandlePointer(_) => quote! { *#name_tok = Handle::from_u64(reply.read_u64::<NativeEndian>()?); },
+            _ => quote! { reply.read_exact(ptr_as_u8_slice(#name_tok))?; },
+        }
+    }).collect();
+    let read_reply_tok = match read_out_toks.is_empty() {
+        true => quote! { |reply| reply.read_i32::<BigEndian>() },
+        false => quote! {
+            |reply| {
+                let result = reply.read_i32::<BigEndian>()?;
+                if result == CUDA_SUCCESS {
+                    #(#read_out_toks)*
+                }
+                Ok(result)
+            }
+        },
+    };
+
+    let call_tok = match function.asynchronous {
+        true => quote! { non_generated::call_async(RPC::#name_tok, #payload_tok) },
+        false => quote! { non_generated::call(RPC::#name_tok, #payload_tok, #read_reply_tok) },
+    };
+
+    quote! {
+        #[no_mangle]
+        pub unsafe extern "C" fn #name_tok(#(#params_tok),*) -> CUresult {
+            #(#length_toks)*
+            #null_check_tok
+            #call_tok
+        }
+    }
+}
+
+/// Generates the dispatch of calls to their handlers, which call the driver, and the translation of the structs.
+pub fn generate_server(output_path: &str, functions: &[Function], structs: &[Struct]) {
+    let import_toks = vec![
+        quote! {use byteorder::{BigEndian, WriteBytesExt};},
+        quote! {use cuda_over_ip_common::RPC;},
+        quote! {use cuda_over_ip_common::cuda::{CUresult, CUDA_SUCCESS};},
+        quote! {use cuda_over_ip_common::handle::Handle;},
+        quote! {use cuda_over_ip_common::wire::Wire;},
+        quote! {use libloading::Library;},
+        quote! {use crate::handles::{HandleTable, Translate};},
+        quote! {use crate::marshal::{slice_as_bytes, Arguments, Plain};},
+        quote! {use crate::resources::Resource;},
+        quote! {use crate::session::Session;},
+    ];
+
+    let match_branch_toks: Vec<TokenStream> = functions.iter().map(|function| {
+        let name_tok: TokenStream = function.name.parse().unwrap();
+        let handle_function_name_tok: TokenStream = handle_function_name(function).parse().unwrap();
+        match function.hand_written {
+            true => quote! { RPC::#name_tok => crate::#handle_function_name_tok(payload, libcuda, session) },
+            false => quote! { RPC::#name_tok => #handle_function_name_tok(payload, libcuda, session) },
+        }
+    }).collect();
+    let handle_call_tok = quote! {
+        pub(crate) fn handle_call(rpc: RPC, payload: &[u8], libcuda: &Library, session: &Session) -> anyhow::Result<Vec<u8>> {
+            match rpc {
+                #(#match_branch_toks),*
+            }
+        }
+    };
+
+    let function_toks: Vec<TokenStream> = functions.iter()
+        .filter(|function| !function.hand_written)
+        .map(|function| generate_handle_function(function, structs))
+        .collect();
+    let translate_toks: Vec<TokenStream> = structs.iter()
+        .filter(|struct_| is_translated(&FieldType::Struct(struct_.name.clone()), structs))
+        .map(|struct_| generate_translate(struct_, structs))
+        .collect();
+
+    let items = import_toks.into_iter()
+        .chain(vec![handle_call_tok])
+        .chain(function_toks)
+        .chain(translate_toks)
+        .collect();
+    write_rust_file(output_path, vec![quote! {#![allow(non_snake_case, unused_imports)]}], items);
+}
+
+fn generate_handle_function(function: &Function, structs: &[Struct]) -> TokenStream {
+    let handle_function_name_tok: TokenStream = handle_function_name(function).parse().unwrap();
+    let rpc_name_tok: TokenStream = function.name.parse().unwrap();
+    let c_func_name_bytes_tok: TokenStream = format!("b\"{}\"", function.name).parse().unwrap();
+
+    let symbol_param_toks: Vec<TokenStream> = function.params.iter().map(c_param_type).collect();
+    let symbol_tok = quote! {
+        libloading::Symbol<unsafe extern "C" fn(#(#symbol_param_toks),*) -> CUresult>
+    };
+
+    // The pointers passed to the driver point to these locals.
+    let read_argument_toks: Vec<TokenStream> = payload_order(function).map(|param| {
+        let name_tok: TokenStream = param.name.parse().unwrap();
+        let type_tok = rust_type(&param.type_);
+        let mut_tok = match param.is_out() || is_translated_struct(param, structs) {
+            true => quote! { mut },
+            false => quote! {},
+        };
+        match &param.type_ {
+            ParamType::Value(_) | ParamType::Pointer(_) => match param.is_in() {
+                true => quote! { let #mut_tok #name_tok: #type_tok = arguments.value()?; },
+                false => quote! { let mut #name_tok: #type_tok = Default::default(); },
+            },
+            ParamType::Struct(_) => match param.is_in() {
+                true => quote! { let #mut_tok #name_tok: #type_tok = arguments.wire()?; },
+                false => quote! { let mut #name_tok: #type_tok = Default::default(); },
+            },
+            // Translated once all arguments are read.
+            ParamType::Handle(_) | ParamType::HandlePointer(_) => match param.is_in() {
+                true => quote! { let #name_tok: u64 = arguments.value()?; },
+                false => quote! { let mut #name_tok: #type_tok = Handle::from_u64(0); },
+            },
+            ParamType::Array { length, .. } => {
+                let length_name_tok = length_name(param);
+                let length_tok = match length {
+                    Length::Constant(length) => {
+                        let length_tok = Literal::usize_unsuffixed(*length);
+                        quote! { let #length_name_tok: usize = #length_tok; }
+                    }
+                    Length::Param(length_param) => {
+                        let length_param_tok: TokenStream = length_param.parse().unwrap();
+                        quote! { let #length_name_tok = arguments.length::<#type_tok>(#length_param_tok)?; }
+                    }
+                };
+                let array_tok = match param.is_in() {
+                    true => quote! { let #mut_tok #name_tok: Vec<#type_tok> = arguments.array(#length_name_tok)?; },
+                    false => quote! { let mut #name_tok: Vec<#type_tok> = vec![Default::default(); #length_name_tok]; },
+                };
+                quote! { #length_tok #array_tok }
+            }
+        }
+    }).collect();
+    let translate_handle_toks: Vec<TokenStream> = function.params.iter()
+        .filter(|param| matches!(param.type_, ParamType::Handle(_) | ParamType::HandlePointer(_)) && param.is_in())
+        .map(|param| {
+            let name_tok: TokenStream = param.name.parse().unwrap();
+            let type_tok = rust_type(&param.type_);
+            let mut_tok = match param.is_out() {
+                true => quote! { mut },
+                false => quote! {},
+            };
+            match &param.size {
+                Some(factors) => {
+                    let size_tok = size_product(factors);
+                    quote! { let #mut_tok #name_tok: #type_tok = session.state().handles.raw_range(#name_tok, #size_tok)?; }
+                }
+                None => quote! { let #mut_tok #name_tok: #type_tok = session.state().handles.raw(#name_tok)?; },
+            }
+        })
+        .chain(function.params.iter()
+            .filter(|param| is_translated_struct(param, structs) && param.is_in())
+            .map(|param| {
+                let name_tok: TokenStream = param.name.parse().unwrap();
+                quote! { #name_tok.translate_in(&session.state().handles)?; }
+            }))
+        .collect();
+    let arguments_tok = match function.params.iter().any(|param| param.is_in()) {
+        true => quote! { let mut arguments = Arguments::new(RPC::#rpc_name_tok, payload); },
+        false => quote! { let arguments = Arguments::new(RPC::#rpc_name_tok, payload); },
+    };
+    let call_param_toks: Vec<TokenStream> = function.params.iter().map(|param| {
+        let name_tok: TokenStream = param.name.parse().unwrap();
+        match (&param.type_, param.is_out()) {
+            (ParamType::Value(_) | ParamType::Handle(_), _) => name_tok,
+            (ParamType::Pointer(_) | ParamType::Struct(_) | ParamType::HandlePointer(_), false) => quote! { &#name_tok },
+            (ParamType::Pointer(_) | ParamType::Struct(_) | ParamType::HandlePointer(_), true) => quote! { &mut #name_tok },
+            (ParamType::Array { .. }, false) => quote! { #name_tok.as_ptr() },
+            (ParamType::Array { .. }, true) => quote! { #name_tok.as_mut_ptr() },
+        }
+    }).collect();
+
+    let write_out_toks: Vec<TokenStream> = function.params.iter().filter(|param| param.is_out()).map(|param| {
+        let name_tok: TokenStream = param.name.parse().unwrap();
+        match &param.type_ {
+            ParamType::Array { .. } => quote! { reply.extend_from_slice(slice_as_bytes(&#name_tok)); },
+            ParamType::Struct(name) => {
+                let translate_toks = translate_fields(name_tok.clone(), name, false, structs);
+                let handles_tok = match translate_toks.is_empty() {
+                    true => quote! {},
+                    false => quote! { let handles = &mut session.state().handles; },
+                };
+                quote! {
+                    #handles_tok
+                    #(#translate_toks)*
+                    #name_tok.write_to(&mut reply);
+                }
+            }
+            ParamType::HandlePointer(_) => match &param.size {
+                Some(factors) => {
+                    let size_tok = size_product(factors);
+                    quote! {
+                        reply.extend_from_slice(session.state().handles.id_with_size(#name_tok, #size_tok).as_bytes());
+                    }
+                }
+                None => quote! { reply.extend_from_slice(session.state().handles.id(#name_tok).as_bytes()); },
+            },
+            _ => quote! { reply.extend_from_slice(#name_tok.as_bytes()); },
+        }
+    }).collect();
+    let track_resource_toks: Vec<TokenStream> = function.params.iter().filter_map(|param| {
+        let name_tok: TokenStream = param.name.parse().unwrap();
+        let value_tok = match &param.type_ {
+            ParamType::Value(_) => quote! { #name_tok as u64 },
+            _ => quote! { #name_tok.to_u64() },
+        };
+        match param.lifetime.as_ref()? {
+            Lifetime::Creates(resource) => {
+                let resource_tok = resource_variant(resource);
+                Some(quote! { session.state().resources.created(Resource::#resource_tok, #value_tok); })
+            }
+            // The client cannot use the ID of a destroyed handle anymore.
+            Lifetime::Destroys(resource) => {
+                let resource_tok = resource_variant(resource);
+                match &param.type_ {
+                    ParamType::Handle(_) => Some(quote! { session.state().destroyed(Resource::#resource_tok, #value_tok); }),
+                    _ => Some(quote! { session.state().resources.destroyed(Resource::#resource_tok, #value_tok); }),
+                }
+            }
+        }
+    }).collect();
+    let on_success_toks: Vec<TokenStream> = write_out_toks.into_iter().chain(track_resource_toks).collect();
+    let write_outs_tok = match on_success_toks.is_empty() {
+        true => quote! {},
+        false => quote! {
+            if result == CUDA_SUCCESS {
+                #(#on_success_toks)*
+            }
+        },
+    };
+
+    let uses_session = function.params.iter().any(|param| {
+        matches!(param.type_, ParamType::Handle(_) | ParamType::HandlePointer(_))
+            || is_translated_struct(param, structs)
+            || param.lifetime.is_some()
+    });
+    let session_tok = match uses_session {
+        true => quote! { session },
+        false => quote! { _session },
+    };
+
+    quote! {
+        fn #handle_function_name_tok(payload: &[u8], libcuda: &Library, #session_tok: &Session) -> anyhow::Result<Vec<u8>> {
+            #arguments_tok
+            #(#read_argument_toks)*
+            arguments.finish()?;
+            #(#translate_handle_toks)*
+
+            let driver_func: #symbol_tok = unsafe {
+                libcuda.get(#c_func_name_bytes_tok)?
+            };
+            let result = unsafe { driver_func(#(#call_param_toks),*) };
+
+            let mut reply = Vec::new();
+            reply.write_i32::<BigEndian>(result)?;
+            #write_outs_tok
+            Ok(reply)
+        }
+    }
+}
+
+/// Generates the translation of the struct from the client to the driver, see `Translate` in `server/src/handles.rs`.
+fn generate_translate(struct_: &Struct, structs: &[Struct]) -> TokenStream {
+    let name_tok: TokenStream = struct_.name.parse().unwrap();
+    let translate_toks = translate_fields(quote! { self }, &struct_.name, true, structs);
+    let handles_tok = match is_translated_with(&FieldType::Struct(struct_.name.clone()), structs, true) {
+        true => quote! { handles },
+        false => quote! { _handles },
+    };
+    quote! {
+        impl Translate for cuda_over_ip_common::#name_tok {
+            fn translate_in(&mut self, #handles_tok: &HandleTable) -> anyhow::Result<()> {
+                #(#translate_toks)*
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The statements translating the fields of the struct at `place`, see `translate_field`.
+fn translate_fields(place: TokenStream, name: &str, in_: bool, structs: &[Struct]) -> Vec<TokenStream> {
+    struct_by_name(name, structs).fields.iter()
+        .filter_map(|field| {
+            let field_name_tok: TokenStream = field.name.parse().unwrap();
+            translate_field(quote! { #place.#field_name_tok }, &field.type_, in_, structs)
+        })
+        .collect()
+}
+
+/// The statements translating the field at `place` from the client to the driver if `in_`, else back with `handles`,
+/// `None` if the field is not translated. Pointers are cleared both ways.
+fn translate_field(place: TokenStream, type_: &FieldType, in_: bool, structs: &[Struct]) -> Option<TokenStream> {
+    match type_ {
+        FieldType::Scalar(_) => None,
+        FieldType::Pointer(rust_type) => match rust_type.starts_with("*const") {
+            true => Some(quote! { #place = std::ptr::null(); }),
+            false => Some(quote! { #place = std::ptr::null_mut(); }),
+        },
+        FieldType::Handle(_) => match in_ {
+            true => Some(quote! { #place = handles.raw(Handle::to_u64(#place))?; }),
+            false => Some(quote! { #place = Handle::from_u64(handles.id(#place)); }),
+        },
+        FieldType::Struct(name) => {
+            let field_toks = translate_fields(place, name, in_, structs);
+            match field_toks.is_empty() {
+                true => None,
+                false => Some(quote! { #(#field_toks)* }),
+            }
+        }
+        FieldType::Array(element, _) => {
+            // The fields of a struct are reached through the reference.
+            let element_place_tok = match element.as_ref() {
+                FieldType::Struct(_) => quote! { element },
+                _ => quote! { *element },
+            };
+            let element_tok = translate_field(element_place_tok, element, in_, structs)?;
+            Some(quote! {
+                for element in &mut #place {
+                    #element_tok
+                }
+            })
+        }
+    }
+}
+
+/// Whether the field is or contains a handle or a pointer, which the server translates.
+fn is_translated(type_: &FieldType, structs: &[Struct]) -> bool {
+    is_translated_with(type_, structs, false)
+}
+
+/// Whether the field is or contains a handle, or a pointer too unless `handles_only`.
+fn is_translated_with(type_: &FieldType, structs: &[Struct], handles_only: bool) -> bool {
+    match type_ {
+        FieldType::Scalar(_) => false,
+        FieldType::Pointer(_) => !handles_only,
+        FieldType::Handle(_) => true,
+        FieldType::Struct(name) => struct_by_name(name, structs).fields.iter()
+            .any(|field| is_translated_with(&field.type_, structs, handles_only)),
+        FieldType::Array(element, _) => is_translated_with(element, structs, handles_only),
+    }
+}
+
+fn struct_by_name<'a>(name: &str, structs: &'a [Struct]) -> &'a Struct {
+    structs.iter().find(|struct_| struct_.name == name).unwrap()
+}
+
+/// Whether the parameter points to a struct with handles or pointers.
+fn is_translated_struct(param: &Param, structs: &[Struct]) -> bool {
+    match &param.type_ {
+        ParamType::Struct(name) => is_translated(&FieldType::Struct(name.clone()), structs),
+        _ => false,
+    }
+}
+
+/// The parameters in the order of the request payload: the values, then the pointees and arrays.
+fn payload_order(function: &Function) -> impl Iterator<Item = &Param> {
+    let values = function.params.iter().filter(|param| is_by_value(param));
+    let pointers = function.params.iter().filter(|param| !is_by_value(param));
+    values.chain(pointers)
+}
+
+fn is_by_value(param: &Param) -> bool {
+    matches!(param.type_, ParamType::Value(_) | ParamType::Handle(_))
+}
+
+/// The name of the local holding the number of elements of an array parameter.
+fn length_name(param: &Param) -> TokenStream {
+    format!("{}_length", param.name).parse().unwrap()
+}
+
+/// The variant of the server's `Resource` enum for a kind of resource in snake case.
+fn resource_variant(resource: &str) -> TokenStream {
+    let variant: String = resource.split('_')
+        .map(|word| word[..1].to_uppercase() + &word[1..])
+        .collect();
+    variant.parse().unwrap()
+}
+
+/// The product of the size factors as a `u64`. Saturates, so a client can't wrap a size around to pass a range check.
+fn size_product(factors: &[String]) -> TokenStream {
+    let factor_toks: Vec<TokenStream> = factors.iter()
+        .map(|factor| match factor.parse::<u64>() {
+            Ok(constant) => Literal::u64_suffixed(constant).into_token_stream(),
+            Err(_) => {
+                let factor_tok: TokenStream = factor.parse().unwrap();
+                quote! { #factor_tok as u64 }
+            }
+        })
+        .collect();
+    match factor_toks.split_first().unwrap() {
+        (first_tok, []) => first_tok.clone(),
+        (first_tok, rest_toks) => quote! { (#first_tok)#(.saturating_mul(#rest_toks))* },
+    }
+}
+
+fn handle_function_name(function: &Function) -> String {
+    format!("handle_{}", function.name)
+}
+
+/// The Rust type of the value, of the pointee, of the array elements, of the struct or of the handle.
+fn rust_type(type_: &ParamType) -> TokenStream {
+    match type_ {
+        ParamType::Value(rust_type) | ParamType::Pointer(rust_type) => rust_type.parse().unwrap(),
+        ParamType::Array { element, .. } => element.parse().unwrap(),
+        ParamType::Struct(name) => {
+            let name_tok: TokenStream = name.parse().unwrap();
+            quote! { cuda_over_ip_common::#name_tok }
+        }
+        ParamType::Handle(handle_type) | ParamType::HandlePointer(handle_type) => handle_type.rust_type.parse().unwrap(),
+    }
+}
+
+/// The Rust type of the C parameter.
+fn c_param_type(param: &Param) -> TokenStream {
+    let type_tok = rust_type(&param.type_);
+    match (is_by_value(param), param.is_out()) {
+        (true, _) => type_tok,
+        (_, false) => quote! { *const #type_tok },
+        (_, true) => quote! { *mut #type_tok },
+    }
+}
+
+fn write_rust_file(output_path: &str, inner_attr_toks: Vec<TokenStream>, item_toks: Vec<TokenStream>) {
+    let attrs: Vec<Attribute> = inner_attr_toks.into_iter()
+        .flat_map(|t| Attribute::parse_inner.parse2(t).unwrap())
+        .collect();
+    let items: Vec<Item> = item_toks.into_iter()
+        .map(|t| parse2::<Item>(t).unwrap()).collect();
+    let file = syn::File {
+        shebang: None,
+        attrs,
+        items,
+    };
+    let text = prettyplease::unparse(&file);
+
+    let mut output_file = File::create(output_path).unwrap();
+    output_file.write_all(HEADER.as_bytes()).unwrap();
+    output_file.write_all(text.as_bytes()).unwrap();
+}
@@ -0,0 +1,262 @@
+use serde::Deserialize;
+use std::collections::HashSet;
+
+#[derive(Debug, Deserialize)]
+#[derive(PartialEq)]
+pub enum ParameterDirection {
+    #[serde(rename(deserialize = "in"))]
+    In,
+    #[serde(rename(deserialize = "out"))]
+    Out,
+    #[serde(rename(deserialize = "inout"))]
+    InOut,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FunctionParameter {
+    pub name: String,
+    pub direction: ParameterDirection,
+    /// Makes a pointer parameter an array of this many elements.
+    #[serde(default)]
+    pub length: Option<Length>,
+    /// The kind of resource a successful call creates, identified by this parameter. The server releases the
+    /// resources a client did not when its session ends.
+    #[serde(default)]
+    pub creates: Option<String>,
+    /// The kind of resource a successful call destroys, identified by this parameter.
+    #[serde(default)]
+    pub destroys: Option<String>,
+    /// The number of addresses starting at the device pointer this out parameter returns, the product of the
+    /// integer parameters and constants named, e.g. `pPitch * Height`. The client can do pointer arithmetic within them.
+    /// On an in device pointer, the number of addresses the call accesses, which must be within one allocation.
+    #[serde(default)]
+    pub size: Option<String>,
+}
+
+/// The number of elements of an array parameter.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum Length {
+    Constant(usize),
+    /// The name of an integer parameter passed by value.
+    Param(String),
+}
+
+/// The contents of `functions.yaml`.
+#[derive(Debug, Deserialize)]
+pub struct Description {
+    /// The typedefs of the driver's handles, which the server translates to IDs for the clients.
+    pub handles: Vec<String>,
+    pub functions: Vec<FunctionDescription>,
+}
+
+/// A function as described in `functions.yaml`.
+#[derive(Debug, Deserialize)]
+pub struct FunctionDescription {
+    pub name: String,
+    pub id: u32,
+    /// Only the RPC ID is generated, the client stub and the server handler are written by hand. The parameters may
+    /// still be described, for the structs they point to to be generated.
+    #[serde(default)]
+    pub hand_written: bool,
+    /// CUDA may return before the call completes, so the client does not wait for the reply either, and reports an
+    /// error with a later call. The function has no out parameters.
+    #[serde(default, rename(deserialize = "async"))]
+    pub asynchronous: bool,
+    pub params: Vec<FunctionParameter>,
+}
+
+pub fn check_id_uniqueness(function_descriptions: &[FunctionDescription]) {
+    let mut seen_ids: HashSet<u32> = HashSet::new();
+    for fd in function_descriptions {
+        if !seen_ids.insert(fd.id) {
+            panic!("Duplicate function ID {}", fd.id);
+        }
+    }
+}
+
+/// The Rust types a length parameter can have.
+const INTEGER_TYPES: &[&str] = &["i8", "u8", "i16", "u16", "i32", "u32", "i64", "u64", "usize"];
+
+/// How a parameter is passed, as far as marshalling is concerned.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParamType {
+    /// Passed by value, holds the Rust type, e.g. `u32` for `unsigned int` or `i32` for `CUdevice`.
+    Value(String),
+    /// A pointer to a single value of the Rust type. The pointee is sent to the server if the parameter is `in`,
+    /// and copied back to the client if it is `out`.
+    Pointer(String),
+    /// A pointer to the first of `length` elements of the Rust type, sent and copied back like a pointee.
+    Array { element: String, length: Length },
+    /// A pointer to the C struct of this name, sent and copied back field by field.
+    Struct(String),
+    /// A handle passed by value, sent as its ID.
+    Handle(HandleType),
+    /// A pointer to a single handle.
+    HandlePointer(HandleType),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HandleType {
+    /// The name of the typedef, e.g. `CUcontext`.
+    pub name: String,
+    /// The Rust type with the representation of the typedef, e.g. `*mut std::ffi::c_void` or `u64`.
+    pub rust_type: String,
+}
+
+#[derive(Debug)]
+pub struct Param {
+    pub name: String,
+    pub direction: ParameterDirection,
+    pub type_: ParamType,
+    pub lifetime: Option<Lifetime>,
+    /// The names of the parameters and the constants whose product is the size of the range of a returned device
+    /// pointer, or of the addresses a call accesses from an in device pointer.
+    pub size: Option<Vec<String>>,
+}
+
+/// What a successful call does to the resource identified by a parameter, holds the kind of resource in snake case,
+/// e.g. `primary_context`.
+#[derive(Debug)]
+pub enum Lifetime {
+    Creates(String),
+    Destroys(String),
+}
+
+impl Param {
+    /// Whether the client sends the pointee or value to the server.
+    pub fn is_in(&self) -> bool {
+        self.direction != ParameterDirection::Out
+    }
+
+    /// Whether the server sends the pointee back to the client.
+    pub fn is_out(&self) -> bool {
+        self.direction != ParameterDirection::In
+    }
+}
+
+/// A function to generate code for, its description combined with the C declaration.
+#[derive(Debug)]
+pub struct Function {
+    pub name: String,
+    pub id: u32,
+    pub hand_written: bool,
+    pub asynchronous: bool,
+    /// In the order of the C declaration.
+    pub params: Vec<Param>,
+}
+
+impl Function {
+    /// Combines the description with the types of the C parameters, given in declaration order.
+    pub fn new(mut description: FunctionDescription, c_params: Vec<(String, ParamType)>) -> Self {
+        if description.hand_written {
+            // Only the RPC ID is generated, the parameters don't matter.
+            return Function {
+                name: description.name,
+                id: description.id,
+                hand_written: true,
+                asynchronous: description.asynchronous,
+                params: Vec::new(),
+            };
+        }
+        let params: Vec<Param> = c_params.into_iter().map(|(name, type_)| {
+            let index = description.params.iter().position(|p| p.name == name)
+                .unwrap_or_else(|| panic!("Parameter {} of {} is not described", name, description.name));
+            let param = description.params.remove(index);
+            if matches!(type_, ParamType::Value(_) | ParamType::Handle(_)) && param.direction != ParameterDirection::In {
+                panic!("Parameter {} of {} is passed by value and can only be in", name, description.name);
+            }
+            let type_ = match (type_, param.length) {
+                (type_, None) => type_,
+                (ParamType::Pointer(element), Some(length)) => ParamType::Array { element, length },
+                (ParamType::Struct(_) | ParamType::HandlePointer(_), Some(_)) =>
+                    panic!("Parameter {} of {} is an array of structs or handles, which is not supported", name, description.name),
+                (_, Some(_)) => panic!("Parameter {} of {} has a length but is not a pointer", name, description.name),
+            };
+            let lifetime = match (param.creates, param.destroys) {
+                (None, None) => None,
+                (Some(resource), None) if matches!(type_, ParamType::Value(_))
+                    || (matches!(type_, ParamType::HandlePointer(_)) && param.direction == ParameterDirection::Out) =>
+                    Some(Lifetime::Creates(resource)),
+                (None, Some(resource)) if matches!(type_, ParamType::Value(_) | ParamType::Handle(_)) =>
+                    Some(Lifetime::Destroys(resource)),
+                _ => panic!("Parameter {} of {} cannot identify the resource it creates or destroys", name, description.name),
+            };
+            let is_handle = matches!(type_, ParamType::HandlePointer(_))
+                || (matches!(type_, ParamType::Handle(_)) && param.direction == ParameterDirection::In);
+            if param.size.is_some() && !is_handle {
+                panic!("Parameter {} of {} has a size but is not a handle", name, description.name);
+            }
+            let size = param.size.map(|size| size.split('*').map(|factor| factor.trim().to_string()).collect());
+            Param { name, direction: param.direction, type_, lifetime, size }
+        }).collect();
+        if let Some(param) = description.params.first() {
+            panic!("Described parameter {} is not a parameter of {}", param.name, description.name);
+        }
+        for param in &params {
+            if let ParamType::Array { length: Length::Param(length_name), .. } = &param.type_ {
+                let is_integer_value = params.iter().any(|p| &p.name == length_name
+                    && matches!(&p.type_, ParamType::Value(t) if INTEGER_TYPES.contains(&t.as_str())));
+                if !is_integer_value {
+                    panic!("Length {} of parameter {} of {} is not an integer parameter passed by value",
+                           length_name, param.name, description.name);
+                }
+            }
+        }
+        for param in &params {
+            for factor in param.size.iter().flatten().filter(|factor| factor.parse::<u64>().is_err()) {
+                let is_integer = params.iter().any(|p| &p.name == factor
+                    && matches!(&p.type_, ParamType::Value(t) | ParamType::Pointer(t) if INTEGER_TYPES.contains(&t.as_str())));
+                if !is_integer {
+                    panic!("Size factor {} of parameter {} of {} is not an integer parameter",
+                           factor, param.name, description.name);
+                }
+            }
+        }
+        if description.asynchronous {
+            if let Some(param) = params.iter().find(|param| param.is_out()) {
+                panic!("Parameter {} of asynchronous {} is out, but the client does not wait for the reply",
+                       param.name, description.name);
+            }
+        }
+        Function {
+            name: description.name,
+            id: description.id,
+            hand_written: description.hand_written,
+            asynchronous: description.asynchronous,
+            params,
+        }
+    }
+}
+
+/// A C struct, with the layout the C compiler gives it.
+#[derive(Debug)]
+pub struct Struct {
+    pub name: String,
+    /// In bytes.
+    pub size: usize,
+    pub fields: Vec<Field>,
+}
+
+#[derive(Debug)]
+pub struct Field {
+    pub name: String,
+    /// In bytes from the start of the struct.
+    pub offset: usize,
+    pub type_: FieldType,
+}
+
+#[derive(Debug)]
+pub enum FieldType {
+    /// Holds the Rust type.
+    Scalar(String),
+    /// A pointer, holds the Rust pointer type, e.g. `*const std::ffi::c_void`. Only the address crosses the wire, which
+    /// means nothing on the other side, so the server clears it.
+    Pointer(String),
+    /// A handle, sent as its ID.
+    Handle(HandleType),
+    /// Holds the name of the struct.
+    Struct(String),
+    /// A fixed size array of this many elements.
+    Array(Box<FieldType>, usize),
+}
@@ -0,0 +1,199 @@
+use anyhow::Context;
+use clap::{Parser, ValueEnum};
+use cuda_over_ip_common::handshake::SessionToken;
+use log::LevelFilter;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const DEFAULT_BIND_ADDRESS: &str = "127.0.0.1";
+const DEFAULT_PORT: u16 = 19999;
+const DEFAULT_DRIVER_LIBRARY: &str = "libcuda.so.1";
+const DEFAULT_LOG_LEVEL: LevelFilter = LevelFilter::Info;
+const DEFAULT_SESSION_GRACE_PERIOD_SECS: u64 = 60;
+const DEFAULT_MODULE_CACHE_SIZE_MIB: u64 = 1024;
+
+/// CUDA-over-IP server.
+///
+/// Settings given on the command line override the ones from the config file.
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Args {
+    /// TOML config file.
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+
+    /// Address to listen on [default: 127.0.0.1].
+    #[arg(short, long)]
+    bind: Option<String>,
+
+    /// Port to listen on [default: 19999].
+    #[arg(short, long)]
+    port: Option<u16>,
+
+    /// Path or name of the CUDA driver library to load [default: libcuda.so.1].
+    #[arg(long)]
+    driver_library: Option<PathBuf>,
+
+    /// One of off, error, warn, info, debug, trace [default: info].
+    #[arg(long)]
+    log_level: Option<LevelFilter>,
+
+    /// Maximum number of clients, counting their sessions rather than their connections, also while a client
+    /// reconnects [default: unlimited].
+    #[arg(long)]
+    max_clients: Option<usize>,
+
+    /// Seconds to keep the state of a disconnected client for it to reconnect [default: 60].
+    #[arg(long)]
+    session_grace_period: Option<u64>,
+
+    /// Whether clients are served by threads of the server or by worker processes of their own [default: thread].
+    #[arg(long)]
+    isolation: Option<Isolation>,
+
+    /// Mebibytes of module images to keep for clients loading them again, 0 disables the cache. With
+    /// `--isolation process` every worker has a cache of this size, which only its client loads from [default: 1024].
+    #[arg(long)]
+    module_cache_size: Option<u64>,
+
+    /// Seconds after which a cached module image no client loaded is evicted [default: never].
+    #[arg(long)]
+    module_cache_expiry: Option<u64>,
+
+    /// Serve the session with this token, in hex, as a worker process of a server. Set by the server.
+    #[arg(long, hide = true)]
+    worker_session: Option<String>,
+}
+
+/// How clients are separated from each other.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Isolation {
+    /// All clients share the server process and its instance of the driver.
+    Thread,
+    /// Every session is served by a worker process, so a crash or a driver error only affects its client.
+    Process,
+}
+
+/// Contents of the server config file. Every field is optional.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    bind: Option<String>,
+    port: Option<u16>,
+    driver_library: Option<PathBuf>,
+    log_level: Option<String>,
+    max_clients: Option<usize>,
+    session_grace_period: Option<u64>,
+    isolation: Option<Isolation>,
+    module_cache_size: Option<u64>,
+    module_cache_expiry: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct ServerConfig {
+    pub(crate) bind: String,
+    pub(crate) port: u16,
+    pub(crate) driver_library: PathBuf,
+    pub(crate) log_level: LevelFilter,
+    pub(crate) max_clients: Option<usize>,
+    pub(crate) session_grace_period: Duration,
+    pub(crate) isolation: Isolation,
+    /// In bytes. Every worker process has a cache of its own.
+    pub(crate) module_cache_size: usize,
+    pub(crate) module_cache_expiry: Option<Duration>,
+    /// Set when this process is the worker serving the session.
+    pub(crate) worker_session: Option<SessionToken>,
+}
+
+impl ServerConfig {
+    pub(crate) fn from_command_line() -> anyhow::Result<Self> {
+        Self::from_args(Args::parse())
+    }
+
+    fn from_args(args: Args) -> anyhow::Result<Self> {
+        let file = match &args.config {
+            Some(path) => read_config_file(path)?,
+            None => ConfigFile::default(),
+        };
+
+        let log_level = match (args.log_level, file.log_level) {
+            (Some(level), _) => level,
+            (None, Some(level)) => level.parse()
+                .with_context(|| format!("Invalid log_level {:?} in config file", level))?,
+            (None, None) => DEFAULT_LOG_LEVEL,
+        };
+
+        let max_clients = args.max_clients.or(file.max_clients);
+        if max_clients == Some(0) {
+            anyhow::bail!("max_clients must be positive");
+        }
+
+        let module_cache_size = args.module_cache_size.or(file.module_cache_size).unwrap_or(DEFAULT_MODULE_CACHE_SIZE_MIB);
+        let module_cache_size = module_cache_size.checked_mul(1024 * 1024)
+            .and_then(|size| usize::try_from(size).ok())
+            .with_context(|| format!("module_cache_size {} is too large", module_cache_size))?;
+
+        let worker_session = match &args.worker_session {
+            Some(token) => Some(SessionToken::from_str_radix(token, 16)
+                .with_context(|| format!("Invalid worker session token {:?}", token))?),
+            None => None,
+        };
+
+        Ok(ServerConfig {
+            bind: args.bind.or(file.bind).unwrap_or_else(|| DEFAULT_BIND_ADDRESS.to_string()),
+            port: args.port.or(file.port).unwrap_or(DEFAULT_PORT),
+            driver_library: args.driver_library.or(file.driver_library)
+                .unwrap_or_else(|| PathBuf::from(DEFAULT_DRIVER_LIBRARY)),
+            log_level,
+            max_clients,
+            session_grace_period: Duration::from_secs(args.session_grace_period.or(file.session_grace_period)
+                .unwrap_or(DEFAULT_SESSION_GRACE_PERIOD_SECS)),
+            isolation: args.isolation.or(file.isolation).unwrap_or(Isolation::Thread),
+            module_cache_size,
+            module_cache_expiry: args.module_cache_expiry.or(file.module_cache_expiry).map(Duration::from_secs),
+            worker_session,
+        })
+    }
+}
+
+fn read_config_file(path: &Path) -> anyhow::Result<ConfigFile> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Cannot read config file {}", path.display()))?;
+    toml::from_str(&text)
+        .with_context(|| format!("Cannot parse config file {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn command_line_overrides_config_file() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        writeln!(file, "bind = \"0.0.0.0\"\nport = 2000\nlog_level = \"debug\"\nmax_clients = 4\nsession_grace_period = 5\nisolation = \"process\"\nmodule_cache_expiry = 600").unwrap();
+
+        let args = Args::parse_from(["server", "--config", file.path().to_str().unwrap(), "--port", "3000",
+                                     "--module-cache-size", "16"]);
+        let config = ServerConfig::from_args(args).unwrap();
+        assert_eq!(config.bind, "0.0.0.0");
+        assert_eq!(config.port, 3000);
+        assert_eq!(config.driver_library, PathBuf::from(DEFAULT_DRIVER_LIBRARY));
+        assert_eq!(config.log_level, LevelFilter::Debug);
+        assert_eq!(config.max_clients, Some(4));
+        assert_eq!(config.session_grace_period, Duration::from_secs(5));
+        assert_eq!(config.isolation, Isolation::Process);
+        assert_eq!(config.module_cache_size, 16 * 1024 * 1024);
+        assert_eq!(config.module_cache_expiry, Some(Duration::from_secs(600)));
+        assert_eq!(config.worker_session, None);
+
+        let args = Args::parse_from(["server", "--isolation", "thread", "--worker-session", "ff"]);
+        let config = ServerConfig::from_args(args).unwrap();
+        assert_eq!(config.isolation, Isolation::Thread);
+        assert_eq!(config.module_cache_size, DEFAULT_MODULE_CACHE_SIZE_MIB as usize * 1024 * 1024);
+        assert_eq!(config.module_cache_expiry, None);
+        assert_eq!(config.worker_session, Some(0xff));
+    }
+}
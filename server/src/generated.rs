@@ -1,50 +1,704 @@
+// Generated by parser_wip from parser_wip/functions.yaml, do not edit.
+
+#![allow(non_snake_case, unused_imports)]
+use byteorder::{BigEndian, WriteBytesExt};
+use cuda_over_ip_common::RPC;
+use cuda_over_ip_common::cuda::{CUresult, CUDA_SUCCESS};
+use cuda_over_ip_common::handle::Handle;
+use cuda_over_ip_common::wire::Wire;
 use libloading::Library;
-use cuda_over_ip_protocol::protocol;
-use cuda_over_ip_protocol::protocol::{func_result, func_call, FuncCall, FuncResult};
+use crate::handles::{HandleTable, Translate};
+use crate::marshal::{slice_as_bytes, Arguments, Plain};
+use crate::resources::Resource;
+use crate::session::Session;
 pub(crate) fn handle_call(
-    call: FuncCall,
-    libnvidia: &Library,
-) -> Result<FuncResult, String> {
-    match call.r#type.ok_or("No type provided")? {
-        func_call::Type::NvmlInitWithFlagsFuncCall(c) => {
-            handle_NvmlInitWithFlags(c, &libnvidia)
+    rpc: RPC,
+    payload: &[u8],
+    libcuda: &Library,
+    session: &Session,
+) -> anyhow::Result<Vec<u8>> {
+    match rpc {
+        RPC::cuDriverGetVersion => handle_cuDriverGetVersion(payload, libcuda, session),
+        RPC::cuInit => handle_cuInit(payload, libcuda, session),
+        RPC::cuCtxSynchronize => handle_cuCtxSynchronize(payload, libcuda, session),
+        RPC::cuDeviceGetCount => handle_cuDeviceGetCount(payload, libcuda, session),
+        RPC::cuDeviceGet => handle_cuDeviceGet(payload, libcuda, session),
+        RPC::cuDeviceGetName => handle_cuDeviceGetName(payload, libcuda, session),
+        RPC::cuDeviceGetUuid => handle_cuDeviceGetUuid(payload, libcuda, session),
+        RPC::cuDeviceGetProperties => {
+            handle_cuDeviceGetProperties(payload, libcuda, session)
         }
-        func_call::Type::NvmlShutdownFuncCall(c) => handle_NvmlShutdown(c, &libnvidia),
-    }
-}
-#[allow(non_snake_case)]
-fn handle_NvmlInitWithFlags(
-    call: protocol::NvmlInitWithFlagsFuncCall,
-    libnvidia: &Library,
-) -> Result<FuncResult, String> {
-    let func: libloading::Symbol<unsafe extern fn(u32) -> i32> = unsafe {
-        libnvidia.get(b"nvmlInitWithFlags").unwrap()
-    };
-    println!("{:?}", func);
-    let result = unsafe { func(call.flags) };
-    Ok(FuncResult {
-        r#type: Some(
-            func_result::Type::NvmlInitWithFlagsFuncResult(protocol::NvmlInitWithFlagsFuncResult {
-                r#return: result,
-            }),
-        ),
-    })
-}
-#[allow(non_snake_case)]
-fn handle_NvmlShutdown(
-    call: protocol::NvmlShutdownFuncCall,
-    libnvidia: &Library,
-) -> Result<FuncResult, String> {
-    let func: libloading::Symbol<unsafe extern fn() -> i32> = unsafe {
-        libnvidia.get(b"nvmlShutdown").unwrap()
-    };
-    println!("{:?}", func);
-    let result = unsafe { func() };
-    Ok(FuncResult {
-        r#type: Some(
-            func_result::Type::NvmlShutdownFuncResult(protocol::NvmlShutdownFuncResult {
-                r#return: result,
-            }),
-        ),
-    })
+        RPC::cuCtxCreate_v2 => handle_cuCtxCreate_v2(payload, libcuda, session),
+        RPC::cuCtxDestroy_v2 => handle_cuCtxDestroy_v2(payload, libcuda, session),
+        RPC::cuCtxSetCurrent => handle_cuCtxSetCurrent(payload, libcuda, session),
+        RPC::cuCtxGetCurrent => handle_cuCtxGetCurrent(payload, libcuda, session),
+        RPC::cuCtxGetDevice => handle_cuCtxGetDevice(payload, libcuda, session),
+        RPC::cuDevicePrimaryCtxRetain => {
+            handle_cuDevicePrimaryCtxRetain(payload, libcuda, session)
+        }
+        RPC::cuDevicePrimaryCtxRelease_v2 => {
+            handle_cuDevicePrimaryCtxRelease_v2(payload, libcuda, session)
+        }
+        RPC::cuStreamCreate => handle_cuStreamCreate(payload, libcuda, session),
+        RPC::cuStreamDestroy_v2 => handle_cuStreamDestroy_v2(payload, libcuda, session),
+        RPC::cuStreamSynchronize => handle_cuStreamSynchronize(payload, libcuda, session),
+        RPC::cuEventCreate => handle_cuEventCreate(payload, libcuda, session),
+        RPC::cuEventDestroy_v2 => handle_cuEventDestroy_v2(payload, libcuda, session),
+        RPC::cuEventRecord => handle_cuEventRecord(payload, libcuda, session),
+        RPC::cuEventSynchronize => handle_cuEventSynchronize(payload, libcuda, session),
+        RPC::cuMemGetInfo_v2 => handle_cuMemGetInfo_v2(payload, libcuda, session),
+        RPC::cuMemAlloc_v2 => handle_cuMemAlloc_v2(payload, libcuda, session),
+        RPC::cuMemFree_v2 => handle_cuMemFree_v2(payload, libcuda, session),
+        RPC::cuMemAllocPitch_v2 => handle_cuMemAllocPitch_v2(payload, libcuda, session),
+        RPC::cuMemsetD8_v2 => handle_cuMemsetD8_v2(payload, libcuda, session),
+        RPC::cuMemsetD32_v2 => handle_cuMemsetD32_v2(payload, libcuda, session),
+        RPC::cuMemcpyHtoD_v2 => crate::handle_cuMemcpyHtoD_v2(payload, libcuda, session),
+        RPC::cuMemcpyDtoH_v2 => crate::handle_cuMemcpyDtoH_v2(payload, libcuda, session),
+        RPC::cuModuleLoadData => {
+            crate::handle_cuModuleLoadData(payload, libcuda, session)
+        }
+        RPC::cuModuleLoadDataEx => {
+            crate::handle_cuModuleLoadDataEx(payload, libcuda, session)
+        }
+        RPC::cuModuleLoadFatBinary => {
+            crate::handle_cuModuleLoadFatBinary(payload, libcuda, session)
+        }
+        RPC::cuModuleGetFunction => {
+            crate::handle_cuModuleGetFunction(payload, libcuda, session)
+        }
+        RPC::cuModuleUnload => crate::handle_cuModuleUnload(payload, libcuda, session),
+        RPC::cuFuncGetParamInfo => handle_cuFuncGetParamInfo(payload, libcuda, session),
+        RPC::cuLaunchKernel => crate::handle_cuLaunchKernel(payload, libcuda, session),
+        RPC::cuStreamWaitEvent => handle_cuStreamWaitEvent(payload, libcuda, session),
+        RPC::cuMemcpy2D_v2 => crate::handle_cuMemcpy2D_v2(payload, libcuda, session),
+    }
+}
+fn handle_cuDriverGetVersion(
+    payload: &[u8],
+    libcuda: &Library,
+    _session: &Session,
+) -> anyhow::Result<Vec<u8>> {
+    let arguments = Arguments::new(RPC::cuDriverGetVersion, payload);
+    let mut driverVersion: i32 = Default::default();
+    arguments.finish()?;
+    let driver_func: libloading::Symbol<unsafe extern "C" fn(*mut i32) -> CUresult> = unsafe {
+        libcuda.get(b"cuDriverGetVersion")?
+    };
+    let result = unsafe { driver_func(&mut driverVersion) };
+    let mut reply = Vec::new();
+    reply.write_i32::<BigEndian>(result)?;
+    if result == CUDA_SUCCESS {
+        reply.extend_from_slice(driverVersion.as_bytes());
+    }
+    Ok(reply)
+}
+fn handle_cuInit(
+    payload: &[u8],
+    libcuda: &Library,
+    _session: &Session,
+) -> anyhow::Result<Vec<u8>> {
+    let mut arguments = Arguments::new(RPC::cuInit, payload);
+    let Flags: u32 = arguments.value()?;
+    arguments.finish()?;
+    let driver_func: libloading::Symbol<unsafe extern "C" fn(u32) -> CUresult> = unsafe {
+        libcuda.get(b"cuInit")?
+    };
+    let result = unsafe { driver_func(Flags) };
+    let mut reply = Vec::new();
+    reply.write_i32::<BigEndian>(result)?;
+    Ok(reply)
+}
+fn handle_cuCtxSynchronize(
+    payload: &[u8],
+    libcuda: &Library,
+    _session: &Session,
+) -> anyhow::Result<Vec<u8>> {
+    let arguments = Arguments::new(RPC::cuCtxSynchronize, payload);
+    arguments.finish()?;
+    let driver_func: libloading::Symbol<unsafe extern "C" fn() -> CUresult> = unsafe {
+        libcuda.get(b"cuCtxSynchronize")?
+    };
+    let result = unsafe { driver_func() };
+    let mut reply = Vec::new();
+    reply.write_i32::<BigEndian>(result)?;
+    Ok(reply)
+}
+fn handle_cuDeviceGetCount(
+    payload: &[u8],
+    libcuda: &Library,
+    _session: &Session,
+) -> anyhow::Result<Vec<u8>> {
+    let arguments = Arguments::new(RPC::cuDeviceGetCount, payload);
+    let mut count: i32 = Default::default();
+    arguments.finish()?;
+    let driver_func: libloading::Symbol<unsafe extern "C" fn(*mut i32) -> CUresult> = unsafe {
+        libcuda.get(b"cuDeviceGetCount")?
+    };
+    let result = unsafe { driver_func(&mut count) };
+    let mut reply = Vec::new();
+    reply.write_i32::<BigEndian>(result)?;
+    if result == CUDA_SUCCESS {
+        reply.extend_from_slice(count.as_bytes());
+    }
+    Ok(reply)
+}
+fn handle_cuDeviceGet(
+    payload: &[u8],
+    libcuda: &Library,
+    _session: &Session,
+) -> anyhow::Result<Vec<u8>> {
+    let mut arguments = Arguments::new(RPC::cuDeviceGet, payload);
+    let ordinal: i32 = arguments.value()?;
+    let mut device: i32 = Default::default();
+    arguments.finish()?;
+    let driver_func: libloading::Symbol<
+        unsafe extern "C" fn(*mut i32, i32) -> CUresult,
+    > = unsafe { libcuda.get(b"cuDeviceGet")? };
+    let result = unsafe { driver_func(&mut device, ordinal) };
+    let mut reply = Vec::new();
+    reply.write_i32::<BigEndian>(result)?;
+    if result == CUDA_SUCCESS {
+        reply.extend_from_slice(device.as_bytes());
+    }
+    Ok(reply)
+}
+fn handle_cuDeviceGetName(
+    payload: &[u8],
+    libcuda: &Library,
+    _session: &Session,
+) -> anyhow::Result<Vec<u8>> {
+    let mut arguments = Arguments::new(RPC::cuDeviceGetName, payload);
+    let len: i32 = arguments.value()?;
+    let dev: i32 = arguments.value()?;
+    let name_length = arguments.length::<i8>(len)?;
+    let mut name: Vec<i8> = vec![Default::default(); name_length];
+    arguments.finish()?;
+    let driver_func: libloading::Symbol<
+        unsafe extern "C" fn(*mut i8, i32, i32) -> CUresult,
+    > = unsafe { libcuda.get(b"cuDeviceGetName")? };
+    let result = unsafe { driver_func(name.as_mut_ptr(), len, dev) };
+    let mut reply = Vec::new();
+    reply.write_i32::<BigEndian>(result)?;
+    if result == CUDA_SUCCESS {
+        reply.extend_from_slice(slice_as_bytes(&name));
+    }
+    Ok(reply)
+}
+fn handle_cuDeviceGetUuid(
+    payload: &[u8],
+    libcuda: &Library,
+    _session: &Session,
+) -> anyhow::Result<Vec<u8>> {
+    let mut arguments = Arguments::new(RPC::cuDeviceGetUuid, payload);
+    let dev: i32 = arguments.value()?;
+    let mut uuid: cuda_over_ip_common::CUuuid_st = Default::default();
+    arguments.finish()?;
+    let driver_func: libloading::Symbol<
+        unsafe extern "C" fn(*mut cuda_over_ip_common::CUuuid_st, i32) -> CUresult,
+    > = unsafe { libcuda.get(b"cuDeviceGetUuid")? };
+    let result = unsafe { driver_func(&mut uuid, dev) };
+    let mut reply = Vec::new();
+    reply.write_i32::<BigEndian>(result)?;
+    if result == CUDA_SUCCESS {
+        uuid.write_to(&mut reply);
+    }
+    Ok(reply)
+}
+fn handle_cuDeviceGetProperties(
+    payload: &[u8],
+    libcuda: &Library,
+    _session: &Session,
+) -> anyhow::Result<Vec<u8>> {
+    let mut arguments = Arguments::new(RPC::cuDeviceGetProperties, payload);
+    let dev: i32 = arguments.value()?;
+    let mut prop: cuda_over_ip_common::CUdevprop_st = Default::default();
+    arguments.finish()?;
+    let driver_func: libloading::Symbol<
+        unsafe extern "C" fn(*mut cuda_over_ip_common::CUdevprop_st, i32) -> CUresult,
+    > = unsafe { libcuda.get(b"cuDeviceGetProperties")? };
+    let result = unsafe { driver_func(&mut prop, dev) };
+    let mut reply = Vec::new();
+    reply.write_i32::<BigEndian>(result)?;
+    if result == CUDA_SUCCESS {
+        prop.write_to(&mut reply);
+    }
+    Ok(reply)
+}
+fn handle_cuCtxCreate_v2(
+    payload: &[u8],
+    libcuda: &Library,
+    session: &Session,
+) -> anyhow::Result<Vec<u8>> {
+    let mut arguments = Arguments::new(RPC::cuCtxCreate_v2, payload);
+    let flags: u32 = arguments.value()?;
+    let dev: i32 = arguments.value()?;
+    let mut pctx: *mut std::ffi::c_void = Handle::from_u64(0);
+    arguments.finish()?;
+    let driver_func: libloading::Symbol<
+        unsafe extern "C" fn(*mut *mut std::ffi::c_void, u32, i32) -> CUresult,
+    > = unsafe { libcuda.get(b"cuCtxCreate_v2")? };
+    let result = unsafe { driver_func(&mut pctx, flags, dev) };
+    let mut reply = Vec::new();
+    reply.write_i32::<BigEndian>(result)?;
+    if result == CUDA_SUCCESS {
+        reply.extend_from_slice(session.state().handles.id(pctx).as_bytes());
+        session.state().resources.created(Resource::Context, pctx.to_u64());
+    }
+    Ok(reply)
+}
+fn handle_cuCtxDestroy_v2(
+    payload: &[u8],
+    libcuda: &Library,
+    session: &Session,
+) -> anyhow::Result<Vec<u8>> {
+    let mut arguments = Arguments::new(RPC::cuCtxDestroy_v2, payload);
+    let ctx: u64 = arguments.value()?;
+    arguments.finish()?;
+    let ctx: *mut std::ffi::c_void = session.state().handles.raw(ctx)?;
+    let driver_func: libloading::Symbol<
+        unsafe extern "C" fn(*mut std::ffi::c_void) -> CUresult,
+    > = unsafe { libcuda.get(b"cuCtxDestroy_v2")? };
+    let result = unsafe { driver_func(ctx) };
+    let mut reply = Vec::new();
+    reply.write_i32::<BigEndian>(result)?;
+    if result == CUDA_SUCCESS {
+        session.state().destroyed(Resource::Context, ctx.to_u64());
+    }
+    Ok(reply)
+}
+fn handle_cuCtxSetCurrent(
+    payload: &[u8],
+    libcuda: &Library,
+    session: &Session,
+) -> anyhow::Result<Vec<u8>> {
+    let mut arguments = Arguments::new(RPC::cuCtxSetCurrent, payload);
+    let ctx: u64 = arguments.value()?;
+    arguments.finish()?;
+    let ctx: *mut std::ffi::c_void = session.state().handles.raw(ctx)?;
+    let driver_func: libloading::Symbol<
+        unsafe extern "C" fn(*mut std::ffi::c_void) -> CUresult,
+    > = unsafe { libcuda.get(b"cuCtxSetCurrent")? };
+    let result = unsafe { driver_func(ctx) };
+    let mut reply = Vec::new();
+    reply.write_i32::<BigEndian>(result)?;
+    Ok(reply)
+}
+fn handle_cuCtxGetCurrent(
+    payload: &[u8],
+    libcuda: &Library,
+    session: &Session,
+) -> anyhow::Result<Vec<u8>> {
+    let arguments = Arguments::new(RPC::cuCtxGetCurrent, payload);
+    let mut pctx: *mut std::ffi::c_void = Handle::from_u64(0);
+    arguments.finish()?;
+    let driver_func: libloading::Symbol<
+        unsafe extern "C" fn(*mut *mut std::ffi::c_void) -> CUresult,
+    > = unsafe { libcuda.get(b"cuCtxGetCurrent")? };
+    let result = unsafe { driver_func(&mut pctx) };
+    let mut reply = Vec::new();
+    reply.write_i32::<BigEndian>(result)?;
+    if result == CUDA_SUCCESS {
+        reply.extend_from_slice(session.state().handles.id(pctx).as_bytes());
+    }
+    Ok(reply)
+}
+fn handle_cuCtxGetDevice(
+    payload: &[u8],
+    libcuda: &Library,
+    _session: &Session,
+) -> anyhow::Result<Vec<u8>> {
+    let arguments = Arguments::new(RPC::cuCtxGetDevice, payload);
+    let mut device: i32 = Default::default();
+    arguments.finish()?;
+    let driver_func: libloading::Symbol<unsafe extern "C" fn(*mut i32) -> CUresult> = unsafe {
+        libcuda.get(b"cuCtxGetDevice")?
+    };
+    let result = unsafe { driver_func(&mut device) };
+    let mut reply = Vec::new();
+    reply.write_i32::<BigEndian>(result)?;
+    if result == CUDA_SUCCESS {
+        reply.extend_from_slice(device.as_bytes());
+    }
+    Ok(reply)
+}
+fn handle_cuDevicePrimaryCtxRetain(
+    payload: &[u8],
+    libcuda: &Library,
+    session: &Session,
+) -> anyhow::Result<Vec<u8>> {
+    let mut arguments = Arguments::new(RPC::cuDevicePrimaryCtxRetain, payload);
+    let dev: i32 = arguments.value()?;
+    let mut pctx: *mut std::ffi::c_void = Handle::from_u64(0);
+    arguments.finish()?;
+    let driver_func: libloading::Symbol<
+        unsafe extern "C" fn(*mut *mut std::ffi::c_void, i32) -> CUresult,
+    > = unsafe { libcuda.get(b"cuDevicePrimaryCtxRetain")? };
+    let result = unsafe { driver_func(&mut pctx, dev) };
+    let mut reply = Vec::new();
+    reply.write_i32::<BigEndian>(result)?;
+    if result == CUDA_SUCCESS {
+        reply.extend_from_slice(session.state().handles.id(pctx).as_bytes());
+        session.state().resources.created(Resource::PrimaryContext, dev as u64);
+    }
+    Ok(reply)
+}
+fn handle_cuDevicePrimaryCtxRelease_v2(
+    payload: &[u8],
+    libcuda: &Library,
+    session: &Session,
+) -> anyhow::Result<Vec<u8>> {
+    let mut arguments = Arguments::new(RPC::cuDevicePrimaryCtxRelease_v2, payload);
+    let dev: i32 = arguments.value()?;
+    arguments.finish()?;
+    let driver_func: libloading::Symbol<unsafe extern "C" fn(i32) -> CUresult> = unsafe {
+        libcuda.get(b"cuDevicePrimaryCtxRelease_v2")?
+    };
+    let result = unsafe { driver_func(dev) };
+    let mut reply = Vec::new();
+    reply.write_i32::<BigEndian>(result)?;
+    if result == CUDA_SUCCESS {
+        session.state().resources.destroyed(Resource::PrimaryContext, dev as u64);
+    }
+    Ok(reply)
+}
+fn handle_cuStreamCreate(
+    payload: &[u8],
+    libcuda: &Library,
+    session: &Session,
+) -> anyhow::Result<Vec<u8>> {
+    let mut arguments = Arguments::new(RPC::cuStreamCreate, payload);
+    let Flags: u32 = arguments.value()?;
+    let mut phStream: *mut std::ffi::c_void = Handle::from_u64(0);
+    arguments.finish()?;
+    let driver_func: libloading::Symbol<
+        unsafe extern "C" fn(*mut *mut std::ffi::c_void, u32) -> CUresult,
+    > = unsafe { libcuda.get(b"cuStreamCreate")? };
+    let result = unsafe { driver_func(&mut phStream, Flags) };
+    let mut reply = Vec::new();
+    reply.write_i32::<BigEndian>(result)?;
+    if result == CUDA_SUCCESS {
+        reply.extend_from_slice(session.state().handles.id(phStream).as_bytes());
+        session.state().resources.created(Resource::Stream, phStream.to_u64());
+    }
+    Ok(reply)
+}
+fn handle_cuStreamDestroy_v2(
+    payload: &[u8],
+    libcuda: &Library,
+    session: &Session,
+) -> anyhow::Result<Vec<u8>> {
+    let mut arguments = Arguments::new(RPC::cuStreamDestroy_v2, payload);
+    let hStream: u64 = arguments.value()?;
+    arguments.finish()?;
+    let hStream: *mut std::ffi::c_void = session.state().handles.raw(hStream)?;
+    let driver_func: libloading::Symbol<
+        unsafe extern "C" fn(*mut std::ffi::c_void) -> CUresult,
+    > = unsafe { libcuda.get(b"cuStreamDestroy_v2")? };
+    let result = unsafe { driver_func(hStream) };
+    let mut reply = Vec::new();
+    reply.write_i32::<BigEndian>(result)?;
+    if result == CUDA_SUCCESS {
+        session.state().destroyed(Resource::Stream, hStream.to_u64());
+    }
+    Ok(reply)
+}
+fn handle_cuStreamSynchronize(
+    payload: &[u8],
+    libcuda: &Library,
+    session: &Session,
+) -> anyhow::Result<Vec<u8>> {
+    let mut arguments = Arguments::new(RPC::cuStreamSynchronize, payload);
+    let hStream: u64 = arguments.value()?;
+    arguments.finish()?;
+    let hStream: *mut std::ffi::c_void = session.state().handles.raw(hStream)?;
+    let driver_func: libloading::Symbol<
+        unsafe extern "C" fn(*mut std::ffi::c_void) -> CUresult,
+    > = unsafe { libcuda.get(b"cuStreamSynchronize")? };
+    let result = unsafe { driver_func(hStream) };
+    let mut reply = Vec::new();
+    reply.write_i32::<BigEndian>(result)?;
+    Ok(reply)
+}
+fn handle_cuEventCreate(
+    payload: &[u8],
+    libcuda: &Library,
+    session: &Session,
+) -> anyhow::Result<Vec<u8>> {
+    let mut arguments = Arguments::new(RPC::cuEventCreate, payload);
+    let Flags: u32 = arguments.value()?;
+    let mut phEvent: *mut std::ffi::c_void = Handle::from_u64(0);
+    arguments.finish()?;
+    let driver_func: libloading::Symbol<
+        unsafe extern "C" fn(*mut *mut std::ffi::c_void, u32) -> CUresult,
+    > = unsafe { libcuda.get(b"cuEventCreate")? };
+    let result = unsafe { driver_func(&mut phEvent, Flags) };
+    let mut reply = Vec::new();
+    reply.write_i32::<BigEndian>(result)?;
+    if result == CUDA_SUCCESS {
+        reply.extend_from_slice(session.state().handles.id(phEvent).as_bytes());
+        session.state().resources.created(Resource::Event, phEvent.to_u64());
+    }
+    Ok(reply)
+}
+fn handle_cuEventDestroy_v2(
+    payload: &[u8],
+    libcuda: &Library,
+    session: &Session,
+) -> anyhow::Result<Vec<u8>> {
+    let mut arguments = Arguments::new(RPC::cuEventDestroy_v2, payload);
+    let hEvent: u64 = arguments.value()?;
+    arguments.finish()?;
+    let hEvent: *mut std::ffi::c_void = session.state().handles.raw(hEvent)?;
+    let driver_func: libloading::Symbol<
+        unsafe extern "C" fn(*mut std::ffi::c_void) -> CUresult,
+    > = unsafe { libcuda.get(b"cuEventDestroy_v2")? };
+    let result = unsafe { driver_func(hEvent) };
+    let mut reply = Vec::new();
+    reply.write_i32::<BigEndian>(result)?;
+    if result == CUDA_SUCCESS {
+        session.state().destroyed(Resource::Event, hEvent.to_u64());
+    }
+    Ok(reply)
+}
+fn handle_cuEventRecord(
+    payload: &[u8],
+    libcuda: &Library,
+    session: &Session,
+) -> anyhow::Result<Vec<u8>> {
+    let mut arguments = Arguments::new(RPC::cuEventRecord, payload);
+    let hEvent: u64 = arguments.value()?;
+    let hStream: u64 = arguments.value()?;
+    arguments.finish()?;
+    let hEvent: *mut std::ffi::c_void = session.state().handles.raw(hEvent)?;
+    let hStream: *mut std::ffi::c_void = session.state().handles.raw(hStream)?;
+    let driver_func: libloading::Symbol<
+        unsafe extern "C" fn(*mut std::ffi::c_void, *mut std::ffi::c_void) -> CUresult,
+    > = unsafe { libcuda.get(b"cuEventRecord")? };
+    let result = unsafe { driver_func(hEvent, hStream) };
+    let mut reply = Vec::new();
+    reply.write_i32::<BigEndian>(result)?;
+    Ok(reply)
+}
+fn handle_cuEventSynchronize(
+    payload: &[u8],
+    libcuda: &Library,
+    session: &Session,
+) -> anyhow::Result<Vec<u8>> {
+    let mut arguments = Arguments::new(RPC::cuEventSynchronize, payload);
+    let hEvent: u64 = arguments.value()?;
+    arguments.finish()?;
+    let hEvent: *mut std::ffi::c_void = session.state().handles.raw(hEvent)?;
+    let driver_func: libloading::Symbol<
+        unsafe extern "C" fn(*mut std::ffi::c_void) -> CUresult,
+    > = unsafe { libcuda.get(b"cuEventSynchronize")? };
+    let result = unsafe { driver_func(hEvent) };
+    let mut reply = Vec::new();
+    reply.write_i32::<BigEndian>(result)?;
+    Ok(reply)
+}
+fn handle_cuMemGetInfo_v2(
+    payload: &[u8],
+    libcuda: &Library,
+    _session: &Session,
+) -> anyhow::Result<Vec<u8>> {
+    let arguments = Arguments::new(RPC::cuMemGetInfo_v2, payload);
+    let mut free: usize = Default::default();
+    let mut total: usize = Default::default();
+    arguments.finish()?;
+    let driver_func: libloading::Symbol<
+        unsafe extern "C" fn(*mut usize, *mut usize) -> CUresult,
+    > = unsafe { libcuda.get(b"cuMemGetInfo_v2")? };
+    let result = unsafe { driver_func(&mut free, &mut total) };
+    let mut reply = Vec::new();
+    reply.write_i32::<BigEndian>(result)?;
+    if result == CUDA_SUCCESS {
+        reply.extend_from_slice(free.as_bytes());
+        reply.extend_from_slice(total.as_bytes());
+    }
+    Ok(reply)
+}
+fn handle_cuMemAlloc_v2(
+    payload: &[u8],
+    libcuda: &Library,
+    session: &Session,
+) -> anyhow::Result<Vec<u8>> {
+    let mut arguments = Arguments::new(RPC::cuMemAlloc_v2, payload);
+    let bytesize: usize = arguments.value()?;
+    let mut dptr: u64 = Handle::from_u64(0);
+    arguments.finish()?;
+    let driver_func: libloading::Symbol<
+        unsafe extern "C" fn(*mut u64, usize) -> CUresult,
+    > = unsafe { libcuda.get(b"cuMemAlloc_v2")? };
+    let result = unsafe { driver_func(&mut dptr, bytesize) };
+    let mut reply = Vec::new();
+    reply.write_i32::<BigEndian>(result)?;
+    if result == CUDA_SUCCESS {
+        reply
+            .extend_from_slice(
+                session.state().handles.id_with_size(dptr, bytesize as u64).as_bytes(),
+            );
+        session.state().resources.created(Resource::Memory, dptr.to_u64());
+    }
+    Ok(reply)
+}
+fn handle_cuMemFree_v2(
+    payload: &[u8],
+    libcuda: &Library,
+    session: &Session,
+) -> anyhow::Result<Vec<u8>> {
+    let mut arguments = Arguments::new(RPC::cuMemFree_v2, payload);
+    let dptr: u64 = arguments.value()?;
+    arguments.finish()?;
+    let dptr: u64 = session.state().handles.raw(dptr)?;
+    let driver_func: libloading::Symbol<unsafe extern "C" fn(u64) -> CUresult> = unsafe {
+        libcuda.get(b"cuMemFree_v2")?
+    };
+    let result = unsafe { driver_func(dptr) };
+    let mut reply = Vec::new();
+    reply.write_i32::<BigEndian>(result)?;
+    if result == CUDA_SUCCESS {
+        session.state().destroyed(Resource::Memory, dptr.to_u64());
+    }
+    Ok(reply)
+}
+fn handle_cuMemAllocPitch_v2(
+    payload: &[u8],
+    libcuda: &Library,
+    session: &Session,
+) -> anyhow::Result<Vec<u8>> {
+    let mut arguments = Arguments::new(RPC::cuMemAllocPitch_v2, payload);
+    let WidthInBytes: usize = arguments.value()?;
+    let Height: usize = arguments.value()?;
+    let ElementSizeBytes: u32 = arguments.value()?;
+    let mut dptr: u64 = Handle::from_u64(0);
+    let mut pPitch: usize = Default::default();
+    arguments.finish()?;
+    let driver_func: libloading::Symbol<
+        unsafe extern "C" fn(*mut u64, *mut usize, usize, usize, u32) -> CUresult,
+    > = unsafe { libcuda.get(b"cuMemAllocPitch_v2")? };
+    let result = unsafe {
+        driver_func(&mut dptr, &mut pPitch, WidthInBytes, Height, ElementSizeBytes)
+    };
+    let mut reply = Vec::new();
+    reply.write_i32::<BigEndian>(result)?;
+    if result == CUDA_SUCCESS {
+        reply
+            .extend_from_slice(
+                session
+                    .state()
+                    .handles
+                    .id_with_size(dptr, (pPitch as u64).saturating_mul(Height as u64))
+                    .as_bytes(),
+            );
+        reply.extend_from_slice(pPitch.as_bytes());
+        session.state().resources.created(Resource::Memory, dptr.to_u64());
+    }
+    Ok(reply)
+}
+fn handle_cuMemsetD8_v2(
+    payload: &[u8],
+    libcuda: &Library,
+    session: &Session,
+) -> anyhow::Result<Vec<u8>> {
+    let mut arguments = Arguments::new(RPC::cuMemsetD8_v2, payload);
+    let dstDevice: u64 = arguments.value()?;
+    let uc: u8 = arguments.value()?;
+    let N: usize = arguments.value()?;
+    arguments.finish()?;
+    let dstDevice: u64 = session.state().handles.raw_range(dstDevice, N as u64)?;
+    let driver_func: libloading::Symbol<
+        unsafe extern "C" fn(u64, u8, usize) -> CUresult,
+    > = unsafe { libcuda.get(b"cuMemsetD8_v2")? };
+    let result = unsafe { driver_func(dstDevice, uc, N) };
+    let mut reply = Vec::new();
+    reply.write_i32::<BigEndian>(result)?;
+    Ok(reply)
+}
+fn handle_cuMemsetD32_v2(
+    payload: &[u8],
+    libcuda: &Library,
+    session: &Session,
+) -> anyhow::Result<Vec<u8>> {
+    let mut arguments = Arguments::new(RPC::cuMemsetD32_v2, payload);
+    let dstDevice: u64 = arguments.value()?;
+    let ui: u32 = arguments.value()?;
+    let N: usize = arguments.value()?;
+    arguments.finish()?;
+    let dstDevice: u64 = session
+        .state()
+        .handles
+        .raw_range(dstDevice, (N as u64).saturating_mul(4u64))?;
+    let driver_func: libloading::Symbol<
+        unsafe extern "C" fn(u64, u32, usize) -> CUresult,
+    > = unsafe { libcuda.get(b"cuMemsetD32_v2")? };
+    let result = unsafe { driver_func(dstDevice, ui, N) };
+    let mut reply = Vec::new();
+    reply.write_i32::<BigEndian>(result)?;
+    Ok(reply)
+}
+fn handle_cuFuncGetParamInfo(
+    payload: &[u8],
+    libcuda: &Library,
+    session: &Session,
+) -> anyhow::Result<Vec<u8>> {
+    let mut arguments = Arguments::new(RPC::cuFuncGetParamInfo, payload);
+    let func: u64 = arguments.value()?;
+    let paramIndex: usize = arguments.value()?;
+    let mut paramOffset: usize = Default::default();
+    let mut paramSize: usize = Default::default();
+    arguments.finish()?;
+    let func: *mut std::ffi::c_void = session.state().handles.raw(func)?;
+    let driver_func: libloading::Symbol<
+        unsafe extern "C" fn(
+            *mut std::ffi::c_void,
+            usize,
+            *mut usize,
+            *mut usize,
+        ) -> CUresult,
+    > = unsafe { libcuda.get(b"cuFuncGetParamInfo")? };
+    let result = unsafe {
+        driver_func(func, paramIndex, &mut paramOffset, &mut paramSize)
+    };
+    let mut reply = Vec::new();
+    reply.write_i32::<BigEndian>(result)?;
+    if result == CUDA_SUCCESS {
+        reply.extend_from_slice(paramOffset.as_bytes());
+        reply.extend_from_slice(paramSize.as_bytes());
+    }
+    Ok(reply)
+}
+fn handle_cuStreamWaitEvent(
+    payload: &[u8],
+    libcuda: &Library,
+    session: &Session,
+) -> anyhow::Result<Vec<u8>> {
+    let mut arguments = Arguments::new(RPC::cuStreamWaitEvent, payload);
+    let hStream: u64 = arguments.value()?;
+    let hEvent: u64 = arguments.value()?;
+    let Flags: u32 = arguments.value()?;
+    arguments.finish()?;
+    let hStream: *mut std::ffi::c_void = session.state().handles.raw(hStream)?;
+    let hEvent: *mut std::ffi::c_void = session.state().handles.raw(hEvent)?;
+    let driver_func: libloading::Symbol<
+        unsafe extern "C" fn(
+            *mut std::ffi::c_void,
+            *mut std::ffi::c_void,
+            u32,
+        ) -> CUresult,
+    > = unsafe { libcuda.get(b"cuStreamWaitEvent")? };
+    let result = unsafe { driver_func(hStream, hEvent, Flags) };
+    let mut reply = Vec::new();
+    reply.write_i32::<BigEndian>(result)?;
+    Ok(reply)
+}
+impl Translate for cuda_over_ip_common::CUDA_MEMCPY2D_st {
+    fn translate_in(&mut self, handles: &HandleTable) -> anyhow::Result<()> {
+        self.srcHost = std::ptr::null();
+        self.srcDevice = handles.raw(Handle::to_u64(self.srcDevice))?;
+        self.srcArray = handles.raw(Handle::to_u64(self.srcArray))?;
+        self.dstHost = std::ptr::null_mut();
+        self.dstDevice = handles.raw(Handle::to_u64(self.dstDevice))?;
+        self.dstArray = handles.raw(Handle::to_u64(self.dstArray))?;
+        Ok(())
+    }
 }
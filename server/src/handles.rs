@@ -0,0 +1,235 @@
+//! Translation between the driver's handles and the IDs the clients see.
+
+use anyhow::Error;
+use cuda_over_ip_common::handle::Handle;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+/// IDs start here. Below it only null is the same for the client and the driver; the others, e.g. the special streams
+/// `CU_STREAM_LEGACY` and `CU_STREAM_PER_THREAD`, are invalid, as the driver would take them for addresses where the
+/// type of the handle is not a stream.
+const FIRST_ID: u64 = 0x1000;
+
+/// Device pointers get IDs from here on, like the driver's addresses. The 64-bit arguments of a kernel are translated
+/// if they look like the ID of a device pointer, which integers rarely do.
+const FIRST_DEVICE_POINTER_ID: u64 = 0x7000_0000_0000;
+
+/// IDs keep the offset of the handle within a block of this size, so device pointers keep their alignment.
+const ID_ALIGNMENT: u64 = 0x1000;
+
+/// A struct with handles or pointers among its fields, implemented by the generated code, which translates the structs
+/// the driver returns in the handlers.
+pub(crate) trait Translate {
+    /// Replaces the IDs the client sent with the driver's handles, and clears the pointers, which are addresses of the
+    /// client.
+    fn translate_in(&mut self, handles: &HandleTable) -> anyhow::Result<()>;
+}
+
+/// The handles a session got from the driver, by the IDs its client sees. A client can only use the handles of its
+/// own session, and does not learn the addresses of the server.
+///
+/// Every ID stands for a range of addresses, so the client can do pointer arithmetic on device pointers: the ID of an
+/// allocation plus an offset is the driver's pointer plus the offset. Other handles are ranges of one address.
+pub(crate) struct HandleTable {
+    /// By the first ID of their range.
+    ranges: BTreeMap<u64, Range>,
+    /// The first IDs of the ranges by the first driver's handle.
+    ids: BTreeMap<u64, u64>,
+    next_id: u64,
+    next_device_pointer_id: u64,
+}
+
+struct Range {
+    raw: u64,
+    size: u64,
+}
+
+impl Default for HandleTable {
+    fn default() -> Self {
+        HandleTable {
+            ranges: BTreeMap::new(),
+            ids: BTreeMap::new(),
+            next_id: FIRST_ID,
+            next_device_pointer_id: FIRST_DEVICE_POINTER_ID,
+        }
+    }
+}
+
+impl HandleTable {
+    /// The driver's handle with the ID, or the address at the ID within a range.
+    pub(crate) fn raw<T: Handle>(&self, id: u64) -> anyhow::Result<T> {
+        self.raw_range(id, 0)
+    }
+
+    /// The address at the ID within a range, if the `size` addresses from there on are within the range too, e.g. the
+    /// memory a call writes. Sessions share the driver's contexts, so a call must not reach past the session's ranges.
+    pub(crate) fn raw_range<T: Handle>(&self, id: u64, size: u64) -> anyhow::Result<T> {
+        if id == 0 {
+            return Ok(T::from_u64(0));
+        }
+        match self.ranges.range(..=id).next_back() {
+            Some((first_id, range)) if id - first_id < range.size => match size <= range.size - (id - first_id) {
+                true => Ok(T::from_u64(range.raw + (id - first_id))),
+                false => Err(Error::new(OutOfRange { id, size })),
+            },
+            _ => Err(Error::new(InvalidHandle(id))),
+        }
+    }
+
+    /// The ID of the driver's handle, a new one unless the session got the handle before.
+    pub(crate) fn id<T: Handle>(&mut self, raw: T) -> u64 {
+        let raw = raw.to_u64();
+        match self.known_id(raw, 1) {
+            Some(id) => id,
+            None => self.insert(raw, 1, false),
+        }
+    }
+
+    /// The ID of the first of `size` addresses starting at the driver's device pointer, e.g. of a new allocation.
+    pub(crate) fn id_with_size<T: Handle>(&mut self, raw: T, size: u64) -> u64 {
+        let raw = raw.to_u64();
+        match self.known_id(raw, size) {
+            Some(id) => id,
+            None => self.insert(raw, size, true),
+        }
+    }
+
+    /// The driver's device pointer if the value is the ID of one, e.g. an argument of a kernel.
+    pub(crate) fn device_pointer(&self, value: u64) -> Option<u64> {
+        match value >= FIRST_DEVICE_POINTER_ID {
+            true => self.raw(value).ok(),
+            false => None,
+        }
+    }
+
+    /// The ID of `size` addresses starting at the driver's handle if they are within a range already.
+    fn known_id(&self, raw: u64, size: u64) -> Option<u64> {
+        if raw == 0 {
+            return Some(0);
+        }
+        let (first_raw, first_id) = self.ids.range(..=raw).next_back()?;
+        let range = &self.ranges[first_id];
+        match raw - first_raw < range.size && raw - first_raw + size <= range.size {
+            true => Some(first_id + (raw - first_raw)),
+            false => None,
+        }
+    }
+
+    fn insert(&mut self, raw: u64, size: u64, device_pointer: bool) -> u64 {
+        self.forget(raw);
+        let next_id = match device_pointer {
+            true => &mut self.next_device_pointer_id,
+            false => &mut self.next_id,
+        };
+        let id = next_id.next_multiple_of(ID_ALIGNMENT) + raw % ID_ALIGNMENT;
+        let size = size.max(1);
+        // A pointer one past the end of a range is not in the next one.
+        *next_id = id + size + 1;
+        self.ranges.insert(id, Range { raw, size });
+        self.ids.insert(raw, id);
+        id
+    }
+
+    /// Forgets the range starting at the driver's handle, whose IDs become invalid.
+    pub(crate) fn forget(&mut self, raw: u64) {
+        if let Some(id) = self.ids.remove(&raw) {
+            self.ranges.remove(&id);
+        }
+    }
+}
+
+/// The client passed a handle its session did not get from the driver.
+#[derive(Debug)]
+pub(crate) struct InvalidHandle(pub(crate) u64);
+
+impl Display for InvalidHandle {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid handle {:#x}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidHandle {}
+
+/// The client passed a range that starts within one of its session but does not end there.
+#[derive(Debug)]
+pub(crate) struct OutOfRange {
+    pub(crate) id: u64,
+    pub(crate) size: u64,
+}
+
+impl Display for OutOfRange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} bytes at {:#x} exceed the range", self.size, self.id)
+    }
+}
+
+impl std::error::Error for OutOfRange {}
+
+#[cfg(test)]
+mod tests {
+    use crate::handles::{HandleTable, InvalidHandle, OutOfRange, FIRST_ID, ID_ALIGNMENT};
+    use std::ffi::c_void;
+
+    #[test]
+    fn translation() {
+        let mut table = HandleTable::default();
+        let context = 0x7f00_1234_5000_u64 as usize as *mut c_void;
+        let id = table.id(context);
+        assert!(id >= FIRST_ID);
+        assert_eq!(table.id(context), id);
+        assert_eq!(table.raw::<*mut c_void>(id).unwrap(), context);
+
+        let other_id = table.id(0x7f00_1234_6000_u64);
+        assert_ne!(other_id, id);
+        assert_eq!(table.raw::<u64>(other_id).unwrap(), 0x7f00_1234_6000);
+
+        // Only null is passed through, the other IDs below the first one are not the client's.
+        assert_eq!(table.id(0_u64), 0);
+        assert_eq!(table.raw::<u64>(0).unwrap(), 0);
+        assert!(table.raw::<u64>(2).unwrap_err().is::<InvalidHandle>());
+        assert!(table.raw_range::<u64>(FIRST_ID - 1, 1).unwrap_err().is::<InvalidHandle>());
+        let low_id = table.id(2_u64);
+        assert!(low_id >= FIRST_ID);
+        assert_eq!(table.raw::<u64>(low_id).unwrap(), 2);
+
+        assert!(table.raw::<u64>(other_id + 1).unwrap_err().is::<InvalidHandle>());
+        assert!(HandleTable::default().raw::<u64>(id).unwrap_err().is::<InvalidHandle>());
+
+        table.forget(context as u64);
+        assert!(table.raw::<u64>(id).unwrap_err().is::<InvalidHandle>());
+        assert_ne!(table.id(context), id);
+    }
+
+    #[test]
+    fn ranges() {
+        let mut table = HandleTable::default();
+        let allocation = 0x7_0000_0100_u64;
+        let id = table.id_with_size(allocation, 1000);
+        assert_eq!(id % ID_ALIGNMENT, allocation % ID_ALIGNMENT);
+        assert_eq!(table.raw::<u64>(id + 999).unwrap(), allocation + 999);
+        assert!(table.raw::<u64>(id + 1000).unwrap_err().is::<InvalidHandle>());
+        assert!(table.raw::<u64>(id - 1).unwrap_err().is::<InvalidHandle>());
+        // Addresses within the allocation the driver returns map into its range.
+        assert_eq!(table.id(allocation + 10), id + 10);
+
+        // Other handles are not mistaken for device pointers.
+        assert_eq!(table.device_pointer(id + 999), Some(allocation + 999));
+        assert_eq!(table.device_pointer(id + 1000), None);
+        let context = table.id(0x7f00_1234_5000_u64);
+        assert_eq!(table.device_pointer(context), None);
+
+        let next_id = table.id_with_size(allocation + 0x1000, 16);
+        assert!(next_id > id + 1000);
+        assert_eq!(table.raw::<u64>(next_id + 15).unwrap(), allocation + 0x100f);
+
+        // Accesses must end within the range they start in.
+        assert_eq!(table.raw_range::<u64>(id + 10, 990).unwrap(), allocation + 10);
+        assert!(table.raw_range::<u64>(id + 10, 991).unwrap_err().is::<OutOfRange>());
+        assert!(table.raw_range::<u64>(id, u64::MAX).unwrap_err().is::<OutOfRange>());
+        assert!(table.raw_range::<u64>(id + 1000, 1).unwrap_err().is::<InvalidHandle>());
+
+        table.forget(allocation);
+        assert!(table.raw::<u64>(id + 10).unwrap_err().is::<InvalidHandle>());
+        assert_eq!(table.raw::<u64>(next_id).unwrap(), allocation + 0x1000);
+    }
+}
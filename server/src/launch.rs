@@ -0,0 +1,185 @@
+//! Launching kernels with the parameters of the client, see `client/src/launch.rs`.
+
+use crate::handles::HandleTable;
+use crate::marshal::{Arguments, MalformedRequest};
+use crate::session::Session;
+use byteorder::{BigEndian, WriteBytesExt};
+use cuda_over_ip_common::RPC;
+use cuda_over_ip_common::cuda::*;
+use libloading::Library;
+use std::ffi::{c_uint, c_void};
+use std::ptr::null_mut;
+
+type CuLaunchKernel = unsafe extern "C" fn(*mut c_void, c_uint, c_uint, c_uint, c_uint, c_uint, c_uint, c_uint,
+                                           *mut c_void, *mut *mut c_void, *mut *mut c_void) -> CUresult;
+
+#[allow(non_snake_case)]
+pub(crate) fn handle_cuLaunchKernel(payload: &[u8], libcuda: &Library, session: &Session) -> anyhow::Result<Vec<u8>> {
+    let mut arguments = Arguments::new(RPC::cuLaunchKernel, payload);
+    let f: u64 = arguments.value()?;
+    let h_stream: u64 = arguments.value()?;
+    let mut dimensions = [0; 7];
+    for dimension in &mut dimensions {
+        *dimension = arguments.value::<c_uint>()?;
+    }
+    let [grid_x, grid_y, grid_z, block_x, block_y, block_z, shared_mem_bytes] = dimensions;
+    let layout_length: u32 = arguments.value()?;
+    let layout = match layout_length {
+        u32::MAX => None,
+        length => {
+            let entries: Vec<usize> = arguments.array(arguments.length::<usize>(length as usize * 2)?)?;
+            Some(entries.chunks_exact(2).map(|entry| (entry[0], entry[1])).collect::<Vec<_>>())
+        }
+    };
+    let buffer_length: usize = arguments.value()?;
+    let buffer_length = arguments.length::<u8>(buffer_length)?;
+    let mut buffer: Vec<u8> = arguments.array(buffer_length)?;
+    arguments.finish()?;
+    if let Some(layout) = &layout {
+        if layout.iter().any(|(offset, size)| offset.checked_add(*size).is_none_or(|end| end > buffer.len())) {
+            return Err(MalformedRequest("cuLaunchKernel has a parameter outside of the buffer".to_string()).into());
+        }
+    }
+
+    let (f, h_stream) = {
+        let state = session.state();
+        (state.handles.raw::<*mut c_void>(f)?, state.handles.raw::<*mut c_void>(h_stream)?)
+    };
+    // Without the layout, the parameters in the buffer can't be told apart from each other.
+    let probed_layout = match &layout {
+        Some(_) => None,
+        None => param_layout(libcuda, f, buffer.len()),
+    };
+    if let Some(layout) = layout.as_deref().or(probed_layout.as_deref()) {
+        translate_device_pointers(&mut buffer, layout, &session.state().handles);
+    }
+    let func: libloading::Symbol<CuLaunchKernel> = unsafe { libcuda.get(b"cuLaunchKernel")? };
+    // Passed as one buffer rather than a pointer to each parameter, so the driver checks the size of the buffer
+    // against the parameters of the kernel instead of reading as many as it has, whatever the layout of the client.
+    let mut buffer_size = buffer.len();
+    let mut extra = [CU_LAUNCH_PARAM_BUFFER_POINTER as *mut c_void, buffer.as_mut_ptr() as *mut c_void,
+                     CU_LAUNCH_PARAM_BUFFER_SIZE as *mut c_void, &mut buffer_size as *mut usize as *mut c_void,
+                     CU_LAUNCH_PARAM_END as *mut c_void];
+    let result = unsafe {
+        func(f, grid_x, grid_y, grid_z, block_x, block_y, block_z, shared_mem_bytes, h_stream, null_mut(),
+             extra.as_mut_ptr())
+    };
+    let mut reply = Vec::new();
+    reply.write_i32::<BigEndian>(result)?;
+    Ok(reply)
+}
+
+/// The layout of the parameters of the function from the driver, for a buffer of `buffer_length` bytes the client
+/// sent without it. `None` if the driver does not know it, e.g. for kernels of cubins before CUDA 12.4.
+fn param_layout(libcuda: &Library, f: *mut c_void, buffer_length: usize) -> Option<Vec<(usize, usize)>> {
+    let func: libloading::Symbol<unsafe extern "C" fn(*mut c_void, usize, *mut usize, *mut usize) -> CUresult> =
+        unsafe { libcuda.get(b"cuFuncGetParamInfo").ok()? };
+    let mut layout = Vec::new();
+    // Every parameter has a byte in the buffer at least.
+    while layout.len() <= buffer_length {
+        let (mut offset, mut size) = (0, 0);
+        match unsafe { func(f, layout.len(), &mut offset, &mut size) } {
+            CUDA_SUCCESS => layout.push((offset, size)),
+            // Past the last parameter.
+            CUDA_ERROR_INVALID_VALUE => return Some(layout),
+            _ => return None,
+        }
+    }
+    None
+}
+
+/// Replaces the IDs of device pointers among the parameters, the 64-bit ones that are aligned and within the buffer.
+fn translate_device_pointers(buffer: &mut [u8], layout: &[(usize, usize)], handles: &HandleTable) {
+    let buffer_length = buffer.len();
+    let words = layout.iter()
+        .filter(|(offset, size)| *size == size_of::<u64>() && offset % size_of::<u64>() == 0)
+        .filter(|(offset, _)| offset.checked_add(size_of::<u64>()).is_some_and(|end| end <= buffer_length));
+    for (offset, _) in words {
+        let word = &mut buffer[*offset..offset + size_of::<u64>()];
+        if let Some(raw) = handles.device_pointer(u64::from_ne_bytes(word.try_into().unwrap())) {
+            word.copy_from_slice(&raw.to_ne_bytes());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::marshal::slice_as_bytes;
+    use crate::module_cache::ModuleCache;
+    use crate::session::SessionRegistry;
+    use std::ffi::c_char;
+    use std::time::Duration;
+
+    #[test]
+    fn only_pointer_parameters_translated() {
+        let mut handles = HandleTable::default();
+        let allocation = 0x7_0000_0000_u64;
+        let id = handles.id_with_size(allocation, 64);
+
+        // `(u64 dst, u32 low, u32 high)`, where the two 32-bit integers make up the ID.
+        let mut buffer = [id.to_ne_bytes(), id.to_ne_bytes()].concat();
+        let layout = [(0, 8), (8, 4), (12, 4)];
+        translate_device_pointers(&mut buffer, &layout, &handles);
+        assert_eq!(buffer, [allocation.to_ne_bytes(), id.to_ne_bytes()].concat());
+
+        // A parameter at an offset past the buffer is left alone.
+        let mut buffer = id.to_ne_bytes().to_vec();
+        translate_device_pointers(&mut buffer, &[(8, 8)], &handles);
+        assert_eq!(buffer, id.to_ne_bytes());
+    }
+
+    #[test]
+    fn driver_without_param_info() {
+        // A library without `cuFuncGetParamInfo`, like a driver older than CUDA 12.4.
+        let libcuda = unsafe { Library::new("libc.so.6") }.unwrap();
+        assert_eq!(param_layout(&libcuda, null_mut(), 16), None);
+    }
+
+    #[test]
+    fn parameters_checked_by_driver() {
+        // The mock driver, which Cargo builds next to the test binary.
+        let deps_dir = std::env::current_exe().unwrap().parent().unwrap().to_path_buf();
+        let libcuda = unsafe { Library::new(deps_dir.join("libcuda_over_ip_mock_driver.so")) }.unwrap();
+        let sessions = SessionRegistry::new(Duration::from_secs(1), None, ModuleCache::new(0, None), |_| {});
+        let attachment = sessions.create().unwrap();
+        let (function, dptr) = unsafe {
+            let init: libloading::Symbol<unsafe extern "C" fn(c_uint) -> CUresult> = libcuda.get(b"cuInit").unwrap();
+            let ctx_create: libloading::Symbol<unsafe extern "C" fn(*mut *mut c_void, c_uint, i32) -> CUresult> =
+                libcuda.get(b"cuCtxCreate_v2").unwrap();
+            let load_data: libloading::Symbol<unsafe extern "C" fn(*mut *mut c_void, *const c_void) -> CUresult> =
+                libcuda.get(b"cuModuleLoadData").unwrap();
+            let get_function: libloading::Symbol<unsafe extern "C" fn(*mut *mut c_void, *mut c_void,
+                                                                      *const c_char) -> CUresult> =
+                libcuda.get(b"cuModuleGetFunction").unwrap();
+            let mem_alloc: libloading::Symbol<unsafe extern "C" fn(*mut u64, usize) -> CUresult> =
+                libcuda.get(b"cuMemAlloc_v2").unwrap();
+            let (mut context, mut module, mut function, mut dptr) = (null_mut(), null_mut(), null_mut(), 0);
+            let ptx = c".version 8.0\n.visible .entry fill(.param .u64 dst, .param .u32 value, .param .u32 count)\n\
+                        {\nret;\n}\n";
+            assert_eq!(init(0), CUDA_SUCCESS);
+            assert_eq!(ctx_create(&mut context, 0, 0), CUDA_SUCCESS);
+            assert_eq!(load_data(&mut module, ptx.as_ptr().cast()), CUDA_SUCCESS);
+            assert_eq!(get_function(&mut function, module, c"fill".as_ptr()), CUDA_SUCCESS);
+            assert_eq!(mem_alloc(&mut dptr, 64), CUDA_SUCCESS);
+            let handles = &mut attachment.session.state().handles;
+            (handles.id(function), handles.id_with_size(dptr, 64))
+        };
+        let launch = |layout: &[(usize, usize)], buffer: &[u8]| {
+            let entries: Vec<usize> = layout.iter().flat_map(|(offset, size)| [*offset, *size]).collect();
+            let payload = [function.to_ne_bytes().as_slice(), &0_u64.to_ne_bytes(),
+                           &[1_u32, 1, 1, 1, 1, 1, 0].map(u32::to_ne_bytes).concat(),
+                           &(layout.len() as u32).to_ne_bytes(), slice_as_bytes(&entries),
+                           &buffer.len().to_ne_bytes(), buffer].concat();
+            let reply = handle_cuLaunchKernel(&payload, &libcuda, &attachment.session).unwrap();
+            i32::from_be_bytes(reply.try_into().unwrap())
+        };
+
+        // `fill(u64 dst, u32 value, u32 count)`.
+        let buffer = [dptr.to_ne_bytes().as_slice(), &7_u32.to_ne_bytes(), &16_u32.to_ne_bytes()].concat();
+        assert_eq!(launch(&[(0, 8), (8, 4), (12, 4)], &buffer), CUDA_SUCCESS);
+        // A layout with fewer parameters than the kernel, whose buffer the driver must not read past.
+        assert_eq!(launch(&[(0, 8)], &buffer[..8]), CUDA_ERROR_INVALID_VALUE);
+        assert_eq!(launch(&[], &[]), CUDA_ERROR_INVALID_VALUE);
+    }
+}
@@ -1,130 +1,359 @@
+mod config;
 mod generated;
+mod handles;
+mod launch;
+mod marshal;
+mod memcpy;
+mod module;
+mod module_cache;
+mod resources;
+mod session;
+mod worker;
 
+use crate::config::{Isolation, ServerConfig};
+use crate::session::{SentReplies, Session, SessionAttachment, SessionRegistry};
 use crate::generated::handle_call;
+use crate::handles::{InvalidHandle, OutOfRange};
+use crate::marshal::MalformedRequest;
+use crate::launch::handle_cuLaunchKernel;
+use crate::memcpy::{handle_cuMemcpy2D_v2, handle_cuMemcpyDtoH_v2, handle_cuMemcpyHtoD_v2};
+use crate::module_cache::ModuleCache;
+use crate::module::{handle_cuModuleGetFunction, handle_cuModuleLoadData, handle_cuModuleLoadDataEx, handle_cuModuleLoadFatBinary, handle_cuModuleUnload};
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
-use cuda_over_ip_protocol::protocol::{FuncCall, FuncResult};
 use libloading::Library;
-use prost::Message;
-use std::io::{BufReader, BufWriter, Read, SeekFrom, Write};
+use log::{error, info, warn};
+use std::ffi::c_void;
+use std::io::{BufReader, BufWriter, Write};
 use std::net::{TcpListener, TcpStream};
+use std::process::exit;
+use std::ptr::null_mut;
+use std::sync::Arc;
 use std::thread;
-use anyhow::Error;
-use cuda_over_ip_common::RPC;
+use anyhow::{Context, Error};
+use cuda_over_ip_common::{UnknownRpc, RPC};
+use cuda_over_ip_common::cuda::{CUresult, CUDA_ERROR_INVALID_HANDLE, CUDA_ERROR_INVALID_VALUE, CUDA_SUCCESS};
+use cuda_over_ip_common::frame::{Frame, STATUS_MALFORMED_REQUEST, STATUS_SERVER_ERROR, STATUS_UNSUPPORTED_RPC};
+use cuda_over_ip_common::handshake::{Capabilities, ClientHello, CAPABILITY_BATCH, HandshakeError, ServerHello, SessionToken, PROTOCOL_VERSION};
+
+/// Optional protocol features this server implements.
+const SERVER_CAPABILITIES: Capabilities = CAPABILITY_BATCH;
 
 fn main() {
-    let listener = TcpListener::bind("127.0.0.1:19999").unwrap();
+    let config = match ServerConfig::from_command_line() {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            exit(2);
+        }
+    };
+    env_logger::Builder::new().filter_level(config.log_level).init();
+
+    if let Err(e) = run(config) {
+        error!("{:#}", e);
+        exit(1);
+    }
+}
+
+fn run(config: ServerConfig) -> anyhow::Result<()> {
+    let libcuda = unsafe { Library::new(&config.driver_library) }
+        .with_context(|| format!("Cannot load driver library {}", config.driver_library.display()))?;
+    let libcuda = Arc::new(libcuda);
+    let driver_version = driver_version(&libcuda)?;
+    info!("Loaded driver library {}, driver version {}", config.driver_library.display(), driver_version);
+
+    if let Some(token) = config.worker_session {
+        return worker::serve_session(&config, token, libcuda, driver_version);
+    }
+
+    let listener = TcpListener::bind((config.bind.as_str(), config.port))
+        .with_context(|| format!("Cannot listen on {}:{}", config.bind, config.port))?;
+    info!("Listening on {}", listener.local_addr()?);
 
+    match config.isolation {
+        Isolation::Thread => serve_clients(&config, listener, libcuda, driver_version),
+        Isolation::Process => worker::supervise(&config, listener),
+    }
+}
+
+/// Serves every client in threads of this process.
+fn serve_clients(config: &ServerConfig, listener: TcpListener, libcuda: Arc<Library>,
+                 driver_version: i32) -> anyhow::Result<()> {
+    let module_cache = ModuleCache::new(config.module_cache_size, config.module_cache_expiry);
+    // A client has a session, which its connections share.
+    let sessions = SessionRegistry::new(config.session_grace_period, config.max_clients, module_cache, {
+        let libcuda = libcuda.clone();
+        move |session| release_resources(session, &libcuda)
+    });
     while let Ok((tcp_stream_read, client)) = listener.accept() {
-        println!("Client {} connected", client);
+        info!("Client {} connected", client);
         tcp_stream_read.set_nodelay(true).expect("set_nodelay call failed");
-        thread::spawn(move || {
-            serve(tcp_stream_read);
-        });
+        let libcuda = libcuda.clone();
+        let sessions = sessions.clone();
+        thread::spawn(move || serve(tcp_stream_read, &libcuda, driver_version, &sessions, None));
     }
+    Ok(())
 }
 
-fn serve(tcp_stream_read: TcpStream) {
-    let libcuda = unsafe { Library::new("libcuda.so.1").unwrap() };
+/// Releases what the client of an ended session did not, in an order the driver accepts.
+fn release_resources(session: &Session, libcuda: &Library) {
+    let mut state = session.state();
+    if !state.resources.is_empty() {
+        let released = state.release_all(libcuda);
+        info!("Released {} resources of session {:032x}", released, session.token);
+    }
+}
+
+fn driver_version(libcuda: &Library) -> anyhow::Result<i32> {
+    let func: libloading::Symbol<unsafe extern "C" fn(*mut i32) -> i32> = unsafe {
+        libcuda.get(b"cuDriverGetVersion")?
+    };
+    let mut version = 0;
+    match unsafe { func(&mut version) } {
+        CUDA_SUCCESS => Ok(version),
+        result => Err(Error::msg(format!("cuDriverGetVersion failed with {}", result))),
+    }
+}
 
+/// Serves a connection. In a worker process, `worker_session` is the session the worker serves, which a client
+/// starting a new session joins.
+fn serve(tcp_stream_read: TcpStream, libcuda: &Library, driver_version: i32, sessions: &Arc<SessionRegistry>,
+         worker_session: Option<SessionToken>) {
     let tcp_stream_write = tcp_stream_read.try_clone().unwrap();
     let mut buf_writer: BufWriter<TcpStream> = BufWriter::new(tcp_stream_write);
     let mut buf_reader: BufReader<TcpStream> = BufReader::new(tcp_stream_read);
 
+    let (attachment, connection_slot) = match handshake(&mut buf_writer, &mut buf_reader, driver_version, sessions, worker_session) {
+        Ok(r) => r,
+        Err(e) => {
+            warn!("Handshake failed: {}", e);
+            return;
+        }
+    };
+
+    restore_current_context(libcuda, &attachment.session, connection_slot);
     loop {
-        let result = serve_iteration(&mut buf_writer, &mut buf_reader, &libcuda);
-        if let Err(e) = &result {
+        if let Err(e) = serve_iteration(&mut buf_writer, &mut buf_reader, libcuda, &attachment.session, connection_slot) {
             match e.root_cause().downcast_ref::<std::io::Error>() {
-                Some(rc) if rc.kind() == std::io::ErrorKind::UnexpectedEof => {
-                    println!("Client disconnected");
-                    break;
-                }
-
-                _ => {
-                    result.unwrap();
-                }
+                Some(rc) if is_disconnect(rc) => info!("Client disconnected: {}", rc),
+                _ => warn!("Closing the connection: {:#}", e),
             }
-        } else {
-            result.unwrap();
+            break;
+        }
+    }
+}
+
+/// Makes the context current that was current for the connection slot before the client reconnected, as the thread
+/// serving the slot now is another one.
+fn restore_current_context(libcuda: &Library, session: &Session, connection_slot: u32) {
+    let Some(context) = session.state().current_contexts.get(&connection_slot).copied() else {
+        return;
+    };
+    let result = unsafe { libcuda.get::<unsafe extern "C" fn(*mut c_void) -> CUresult>(b"cuCtxSetCurrent") }
+        .map(|set_current| unsafe { set_current(context as *mut c_void) });
+    match result {
+        Ok(CUDA_SUCCESS) => info!("Restored the current context of connection slot {}", connection_slot),
+        Ok(result) => warn!("Cannot restore the current context of connection slot {}: {}", connection_slot, result),
+        Err(e) => warn!("Cannot restore the current context of connection slot {}: {}", connection_slot, e),
+    }
+}
+
+/// Records the current context of the thread serving the connection slot after a call that may have changed it.
+fn record_current_context(request: &Frame, libcuda: &Library, session: &Session, connection_slot: u32) {
+    let changes_context = matches!(RPC::try_from(request.header.rpc_id),
+        Ok(RPC::cuCtxCreate_v2 | RPC::cuCtxDestroy_v2 | RPC::cuCtxSetCurrent));
+    if request.is_batch() || !changes_context {
+        return;
+    }
+    let mut context: *mut c_void = null_mut();
+    let result = unsafe { libcuda.get::<unsafe extern "C" fn(*mut *mut c_void) -> CUresult>(b"cuCtxGetCurrent") }
+        .map(|get_current| unsafe { get_current(&mut context) });
+    let mut state = session.state();
+    match result {
+        Ok(CUDA_SUCCESS) if !context.is_null() => {
+            state.current_contexts.insert(connection_slot, context as u64);
+        }
+        _ => {
+            state.current_contexts.remove(&connection_slot);
+        }
+    }
+}
+
+fn is_disconnect(e: &std::io::Error) -> bool {
+    use std::io::ErrorKind::*;
+    matches!(e.kind(), UnexpectedEof | ConnectionReset | ConnectionAborted | BrokenPipe)
+}
+
+/// Negotiates the protocol and attaches the connection to a new or existing session.
+/// Returns the attachment and the connection slot within the session.
+fn handshake(buf_writer: &mut BufWriter<TcpStream>,
+             buf_reader: &mut BufReader<TcpStream>,
+             driver_version: i32,
+             sessions: &Arc<SessionRegistry>,
+             worker_session: Option<SessionToken>) -> Result<(SessionAttachment, u32), HandshakeError> {
+    let client_hello = ClientHello::read_from(buf_reader)?;
+    let result = ServerHello::negotiate(&client_hello, driver_version, SERVER_CAPABILITIES)
+        .and_then(|capabilities| {
+            let attachment = match client_hello.session_token {
+                Some(token) => sessions.resume(token)
+                    .ok_or_else(|| format!("session {:032x} is unknown or expired", token))?,
+                None => match worker_session {
+                    Some(token) => sessions.resume(token).ok_or_else(|| format!("session {:032x} ended", token))?,
+                    None => sessions.create()?,
+                },
+            };
+            Ok((capabilities, attachment))
+        });
+
+    match result {
+        Ok((capabilities, attachment)) => {
+            let server_hello = ServerHello::Accepted {
+                protocol_version: PROTOCOL_VERSION,
+                capabilities,
+                session_token: attachment.session.token,
+            };
+            server_hello.write_to(buf_writer)?;
+            Ok((attachment, client_hello.connection_slot))
+        }
+        Err(reason) => {
+            ServerHello::Rejected { reason: reason.clone() }.write_to(buf_writer)?;
+            Err(HandshakeError::Rejected(reason))
         }
     }
 }
 
 fn serve_iteration(buf_writer: &mut BufWriter<TcpStream>,
                    buf_reader: &mut BufReader<TcpStream>,
-                   libcuda: &Library) -> anyhow::Result<()> {
-    let rpc_id = buf_reader.read_i32::<BigEndian>()?;
-    let rpc = RPC::parse(rpc_id);
-    match rpc {
-        RPC::cuDriverGetVersion => handle_cuDriverGetVersion(buf_writer, buf_reader, &libcuda),
-        _ => Err(Error::msg(format!("Unsupported RPC {:?}", rpc))),
+                   libcuda: &Library,
+                   session: &Session,
+                   connection_slot: u32) -> anyhow::Result<()> {
+    let request = Frame::read_from(buf_reader)?;
+    let request_id = request.header.request_id;
+
+    let executed_again = SentReplies::executed_again(&request);
+    if let Some(sent_replies) = session.state().sent_replies.get(&connection_slot).filter(|_| !executed_again) {
+        if sent_replies.executed(request_id) {
+            match sent_replies.get(request_id) {
+                Some(reply) => {
+                    info!("Resending reply to request {} after reconnect", request_id);
+                    reply.write_to(buf_writer)?;
+                }
+                // Executing it again could repeat what it did.
+                None => {
+                    warn!("Request {} was resent after its reply was discarded", request_id);
+                    Frame::error_reply(&request.header, STATUS_SERVER_ERROR).write_to(buf_writer)?;
+                }
+            }
+            flush_unless_pipelined(buf_writer, buf_reader)?;
+            return Ok(());
+        }
     }
+
+    let reply = match request.is_batch() {
+        true => execute_batch(&request, libcuda, session)?,
+        false => execute(&request, libcuda, session)?,
+    };
+    record_current_context(&request, libcuda, session, connection_slot);
+    let mut state = session.state();
+    let sent_replies = state.sent_replies.entry(connection_slot).or_default();
+    match executed_again {
+        true => sent_replies.push_executed(request_id),
+        false => sent_replies.push(reply.clone()),
+    }
+    drop(state);
+    reply.write_to(buf_writer)?;
+    flush_unless_pipelined(buf_writer, buf_reader)?;
+
+    Ok(())
 }
 
-fn handle_cuDriverGetVersion(buf_writer: &mut BufWriter<TcpStream>,
-                             buf_reader: &mut BufReader<TcpStream>,
-                             libcuda: &Library) -> anyhow::Result<()> {
-    let func: libloading::Symbol<unsafe extern fn(*mut i32) -> i32> = unsafe {
-        libcuda.get(b"cuDriverGetVersion")?
+/// Executes a request, turning requests the server can't handle into error replies.
+fn execute(request: &Frame, libcuda: &Library, session: &Session) -> anyhow::Result<Frame> {
+    let result = match RPC::try_from(request.header.rpc_id) {
+        Ok(rpc) => handle_call(rpc, &request.payload, libcuda, session),
+        Err(e) => Err(Error::new(e)),
     };
 
-    let mut driverVersion_vec = vec![0_u8; size_of::<i32>()];
-    buf_reader.read_exact(&mut driverVersion_vec)?;
-    let driverVersion = driverVersion_vec.as_mut_ptr() as *mut i32;
+    match result {
+        Ok(payload) => Ok(Frame::reply(&request.header, payload)),
+        // Also a function the driver does not have, e.g. one newer than the driver.
+        Err(e) if e.is::<UnknownRpc>() || e.is::<libloading::Error>() => {
+            warn!("{}", e);
+            Ok(Frame::error_reply(&request.header, STATUS_UNSUPPORTED_RPC))
+        }
+        Err(e) if e.is::<MalformedRequest>() => {
+            warn!("{}", e);
+            Ok(Frame::error_reply(&request.header, STATUS_MALFORMED_REQUEST))
+        }
+        // The driver would not know the handle either.
+        Err(e) if e.is::<InvalidHandle>() => {
+            info!("{}", e);
+            let mut payload = Vec::new();
+            payload.write_i32::<BigEndian>(CUDA_ERROR_INVALID_HANDLE)?;
+            Ok(Frame::reply(&request.header, payload))
+        }
+        // Like the driver, which rejects sizes beyond an allocation.
+        Err(e) if e.is::<OutOfRange>() => {
+            info!("{}", e);
+            let mut payload = Vec::new();
+            payload.write_i32::<BigEndian>(CUDA_ERROR_INVALID_VALUE)?;
+            Ok(Frame::reply(&request.header, payload))
+        }
+        Err(e) => {
+            error!("Request {} failed: {:#}", request.header.request_id, e);
+            Ok(Frame::error_reply(&request.header, STATUS_SERVER_ERROR))
+        }
+    }
+}
 
-    let result: i32 = unsafe { func(driverVersion) };
+/// Executes the calls of a batch in order until one fails.
+fn execute_batch(request: &Frame, libcuda: &Library, session: &Session) -> anyhow::Result<Frame> {
+    let calls = match request.batch_calls() {
+        Ok(calls) => calls,
+        Err(e) => {
+            warn!("Malformed batch: {}", e);
+            return Ok(Frame::error_reply(&request.header, STATUS_MALFORMED_REQUEST));
+        }
+    };
 
-    buf_writer.write_i32::<BigEndian>(result)?;
-    buf_writer.write(&driverVersion_vec)?;
-    buf_writer.flush()?;
+    for (index, call) in calls.iter().enumerate() {
+        let reply = execute(call, libcuda, session)?;
+        let failed = reply.error_status().is_some()
+            || reply.payload.as_slice().read_i32::<BigEndian>().map_or(true, |result| result != CUDA_SUCCESS);
+        if failed {
+            info!("Call {} of batch {} failed, skipping {} calls", index, request.header.request_id, calls.len() - index - 1);
+            return Ok(Frame::batch_reply(&request.header, Some((index as u32, &reply))));
+        }
+    }
+    Ok(Frame::batch_reply(&request.header, None))
+}
 
+/// Flushes the replies unless the client has already sent more requests, whose replies then go out together.
+fn flush_unless_pipelined(buf_writer: &mut BufWriter<TcpStream>, buf_reader: &BufReader<TcpStream>) -> std::io::Result<()> {
+    if buf_reader.buffer().is_empty() {
+        buf_writer.flush()?;
+    }
     Ok(())
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::module_cache::ModuleCache;
+    use std::time::Duration;
 
-// fn serve(tcp_stream_read: TcpStream) {
-//     let libnvidia = unsafe { Library::new("libnvidia-ml.so.1").unwrap() };
-//
-//     let tcp_stream_write = tcp_stream_read.try_clone().unwrap();
-//     let mut buf_writer: BufWriter<TcpStream> = BufWriter::new(tcp_stream_write);
-//     let mut buf_reader: BufReader<TcpStream> = BufReader::new(tcp_stream_read);
-//
-//     loop {
-//         let call = match read_call(&mut buf_reader) {
-//             Ok(c) => c,
-//             Err(e) => {
-//                 eprintln!("Error reading call: {}", e);
-//                 break;
-//             }
-//         };
-//         println!("{:?}", call);
-//
-//         let result = match handle_call(call, &libnvidia) {
-//             Ok(r) => r,
-//             Err(e) => {
-//                 eprintln!("Error handling call: {}", e);
-//                 break;
-//             }
-//         };
-//         println!("{:?}", result);
-//
-//         if let Err(e) = send_result(&mut buf_writer, result) {
-//             eprintln!("Error responding: {}", e);
-//             break;
-//         }
-//     }
-// }
-//
-// fn read_call(buf_reader: &mut BufReader<TcpStream>) -> std::io::Result<FuncCall> {
-//     let size = buf_reader.read_u32::<BigEndian>()? as usize;
-//     let mut buf = vec![0_u8; size];
-//     buf_reader.read_exact(&mut buf)?;
-//     Ok(FuncCall::decode(&*buf)?)
-// }
-//
-// fn send_result(buf_writer: &mut BufWriter<TcpStream>, result: FuncResult) -> std::io::Result<()> {
-//     let mut buf = Vec::<u8>::with_capacity(result.encoded_len());
-//     result.encode(&mut buf)?;
-//     buf_writer.write_u32::<BigEndian>(result.encoded_len() as u32)?;
-//     buf_writer.write_all(&buf)?;
-//     buf_writer.flush()
-// }
+    #[test]
+    fn failed_requests_get_error_replies() {
+        // A library without the driver's functions.
+        let libcuda = unsafe { Library::new("libc.so.6") }.unwrap();
+        let sessions = SessionRegistry::new(Duration::from_secs(1), None, ModuleCache::new(0, None), |_| {});
+        let attachment = sessions.create().unwrap();
+
+        let request = Frame::request(1, RPC::cuInit as i32, 0_u32.to_ne_bytes().to_vec());
+        let reply = execute(&request, &libcuda, &attachment.session).unwrap();
+        assert_eq!(reply.error_status(), Some(STATUS_UNSUPPORTED_RPC));
+
+        let request = Frame::request(2, RPC::cuInit as i32, Vec::new());
+        let reply = execute(&request, &libcuda, &attachment.session).unwrap();
+        assert_eq!(reply.error_status(), Some(STATUS_MALFORMED_REQUEST));
+    }
+}
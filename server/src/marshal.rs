@@ -0,0 +1,139 @@
+//! Reading the arguments of a call from the request payload, for the generated handlers.
+
+use anyhow::Error;
+use cuda_over_ip_common::frame::MAX_PAYLOAD_LENGTH;
+use cuda_over_ip_common::wire::Wire;
+use cuda_over_ip_common::RPC;
+use std::fmt::{Display, Formatter};
+
+/// The request payload does not match what the RPC expects.
+#[derive(Debug)]
+pub(crate) struct MalformedRequest(pub(crate) String);
+
+impl Display for MalformedRequest {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Malformed request: {}", self.0)
+    }
+}
+
+impl std::error::Error for MalformedRequest {}
+
+/// A type any bit pattern of its size is a valid value of, so it can be read from the payload as is.
+pub(crate) trait Plain: Copy + Default {
+    /// The bytes of the value in native order, as sent back to the client.
+    fn as_bytes(&self) -> &[u8] {
+        slice_as_bytes(std::slice::from_ref(self))
+    }
+}
+
+/// The bytes of the values in native order.
+pub(crate) fn slice_as_bytes<T: Plain>(values: &[T]) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(values.as_ptr() as *const u8, size_of_val(values)) }
+}
+
+macro_rules! impl_plain {
+    ($($t:ty),*) => { $(impl Plain for $t {})* };
+}
+
+// `usize` is `size_t`, of the same size on the client as the driver only supports 64-bit hosts.
+impl_plain!(i8, u8, i16, u16, i32, u32, i64, u64, usize, f32, f64);
+
+/// Reads the arguments of a call in order. The client writes them in native byte order.
+pub(crate) struct Arguments<'a> {
+    rpc: RPC,
+    payload: &'a [u8],
+}
+
+impl<'a> Arguments<'a> {
+    pub(crate) fn new(rpc: RPC, payload: &'a [u8]) -> Self {
+        Arguments { rpc, payload }
+    }
+
+    /// Reads the next argument passed by value.
+    pub(crate) fn value<T: Plain>(&mut self) -> anyhow::Result<T> {
+        let size = size_of::<T>();
+        if self.payload.len() < size {
+            return Err(self.malformed(format!("expects {} more bytes of arguments, got {}", size, self.payload.len())));
+        }
+        let (bytes, rest) = self.payload.split_at(size);
+        self.payload = rest;
+        Ok(unsafe { std::ptr::read_unaligned(bytes.as_ptr() as *const T) })
+    }
+
+    /// Reads the next argument that is a struct.
+    pub(crate) fn wire<T: Wire>(&mut self) -> anyhow::Result<T> {
+        match T::read_from(&mut self.payload) {
+            Ok(value) => Ok(value),
+            Err(e) => Err(self.malformed(format!("has a truncated struct argument: {}", e))),
+        }
+    }
+
+    /// Checks the value of the length argument of an array of `T`, which must fit in a frame.
+    pub(crate) fn length<T: Plain>(&self, length: impl TryInto<usize>) -> anyhow::Result<usize> {
+        match length.try_into() {
+            Ok(length) if length <= MAX_PAYLOAD_LENGTH as usize / size_of::<T>() => Ok(length),
+            _ => Err(self.malformed("has an array length out of range".to_string())),
+        }
+    }
+
+    /// Reads the next argument that is an array of `length` elements.
+    pub(crate) fn array<T: Plain>(&mut self, length: usize) -> anyhow::Result<Vec<T>> {
+        let size = length * size_of::<T>();
+        if self.payload.len() < size {
+            return Err(self.malformed(format!("expects {} more bytes of arguments, got {}", size, self.payload.len())));
+        }
+        let (bytes, rest) = self.payload.split_at(size);
+        self.payload = rest;
+        Ok(bytes.chunks_exact(size_of::<T>())
+            .map(|element| unsafe { std::ptr::read_unaligned(element.as_ptr() as *const T) })
+            .collect())
+    }
+
+    /// Checks that all arguments were read.
+    pub(crate) fn finish(self) -> anyhow::Result<()> {
+        if !self.payload.is_empty() {
+            return Err(self.malformed(format!("got {} bytes of arguments too many", self.payload.len())));
+        }
+        Ok(())
+    }
+
+    fn malformed(&self, message: String) -> Error {
+        Error::new(MalformedRequest(format!("{:?} {}", self.rpc, message)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::marshal::{slice_as_bytes, Arguments, MalformedRequest, Plain};
+    use cuda_over_ip_common::RPC;
+
+    #[test]
+    fn arguments() {
+        let payload = [7_u32.to_ne_bytes().as_slice(), (-1_i64).to_ne_bytes().as_slice()].concat();
+        let mut arguments = Arguments::new(RPC::cuInit, &payload);
+        assert_eq!(arguments.value::<u32>().unwrap(), 7);
+        assert_eq!(arguments.value::<i64>().unwrap(), -1);
+        arguments.finish().unwrap();
+        assert_eq!(7_u32.as_bytes(), 7_u32.to_ne_bytes());
+
+        let mut arguments = Arguments::new(RPC::cuInit, &payload[..2]);
+        assert!(arguments.value::<u32>().unwrap_err().is::<MalformedRequest>());
+
+        let arguments = Arguments::new(RPC::cuInit, &payload);
+        assert!(arguments.finish().unwrap_err().is::<MalformedRequest>());
+    }
+
+    #[test]
+    fn arrays() {
+        let values = [1_i16, -2, 3];
+        let mut arguments = Arguments::new(RPC::cuDeviceGetName, slice_as_bytes(&values));
+        let length = arguments.length::<i16>(3_i32).unwrap();
+        assert_eq!(arguments.array::<i16>(length).unwrap(), values);
+        arguments.finish().unwrap();
+
+        let mut arguments = Arguments::new(RPC::cuDeviceGetName, slice_as_bytes(&values));
+        assert!(arguments.length::<i16>(-1_i32).unwrap_err().is::<MalformedRequest>());
+        assert!(arguments.length::<i16>(u32::MAX).unwrap_err().is::<MalformedRequest>());
+        assert!(arguments.array::<i16>(4).unwrap_err().is::<MalformedRequest>());
+    }
+}
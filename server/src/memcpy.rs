@@ -0,0 +1,98 @@
+//! Copies between host and device memory, executed per chunk, and 2D copies within the device, see
+//! `client/src/memcpy.rs`.
+
+use crate::handles::Translate;
+use crate::marshal::{Arguments, MalformedRequest};
+use crate::session::Session;
+use byteorder::{BigEndian, WriteBytesExt};
+use cuda_over_ip_common::{CUDA_MEMCPY2D_st, RPC};
+use cuda_over_ip_common::cuda::{CUresult, CUDA_SUCCESS, CU_MEMORYTYPE_DEVICE, CU_MEMORYTYPE_UNIFIED};
+use cuda_over_ip_common::frame::MAX_MEMCPY_CHUNK_SIZE;
+use libloading::Library;
+use std::ffi::c_void;
+
+#[allow(non_snake_case)]
+pub(crate) fn handle_cuMemcpyHtoD_v2(payload: &[u8], libcuda: &Library, session: &Session) -> anyhow::Result<Vec<u8>> {
+    let mut arguments = Arguments::new(RPC::cuMemcpyHtoD_v2, payload);
+    let dst_device: u64 = arguments.value()?;
+    let length = chunk_length(RPC::cuMemcpyHtoD_v2, arguments.value()?)?;
+    let chunk: Vec<u8> = arguments.array(length)?;
+    arguments.finish()?;
+    let dst_device: u64 = session.state().handles.raw_range(dst_device, length as u64)?;
+    let func: libloading::Symbol<unsafe extern "C" fn(u64, *const c_void, usize) -> CUresult> = unsafe {
+        libcuda.get(b"cuMemcpyHtoD_v2")?
+    };
+    let result = unsafe { func(dst_device, chunk.as_ptr() as *const c_void, length) };
+    let mut reply = Vec::new();
+    reply.write_i32::<BigEndian>(result)?;
+    Ok(reply)
+}
+
+#[allow(non_snake_case)]
+pub(crate) fn handle_cuMemcpyDtoH_v2(payload: &[u8], libcuda: &Library, session: &Session) -> anyhow::Result<Vec<u8>> {
+    let mut arguments = Arguments::new(RPC::cuMemcpyDtoH_v2, payload);
+    let src_device: u64 = arguments.value()?;
+    let length = chunk_length(RPC::cuMemcpyDtoH_v2, arguments.value()?)?;
+    arguments.finish()?;
+    let src_device: u64 = session.state().handles.raw_range(src_device, length as u64)?;
+    let func: libloading::Symbol<unsafe extern "C" fn(*mut c_void, u64, usize) -> CUresult> = unsafe {
+        libcuda.get(b"cuMemcpyDtoH_v2")?
+    };
+    // The chunk is copied into the reply, after the result.
+    let mut reply = vec![0; 4 + length];
+    let result = unsafe { func(reply[4..].as_mut_ptr() as *mut c_void, src_device, length) };
+    match result {
+        CUDA_SUCCESS => reply[..4].copy_from_slice(&result.to_be_bytes()),
+        _ => {
+            reply.clear();
+            reply.write_i32::<BigEndian>(result)?;
+        }
+    }
+    Ok(reply)
+}
+
+/// The client only sends 2D copies between device memory and arrays, the host pointers of the struct are cleared.
+#[allow(non_snake_case)]
+pub(crate) fn handle_cuMemcpy2D_v2(payload: &[u8], libcuda: &Library, session: &Session) -> anyhow::Result<Vec<u8>> {
+    let mut arguments = Arguments::new(RPC::cuMemcpy2D_v2, payload);
+    let mut copy: CUDA_MEMCPY2D_st = arguments.wire()?;
+    arguments.finish()?;
+    {
+        let state = session.state();
+        for (memory_type, device, x, y, pitch) in [
+            (copy.srcMemoryType, copy.srcDevice, copy.srcXInBytes, copy.srcY, copy.srcPitch),
+            (copy.dstMemoryType, copy.dstDevice, copy.dstXInBytes, copy.dstY, copy.dstPitch),
+        ] {
+            if matches!(memory_type, CU_MEMORYTYPE_DEVICE | CU_MEMORYTYPE_UNIFIED) {
+                let size = rows_size(x, y, pitch, copy.WidthInBytes, copy.Height);
+                state.handles.raw_range::<u64>(device, size)?;
+            }
+        }
+        copy.translate_in(&state.handles)?;
+    }
+    let func: libloading::Symbol<unsafe extern "C" fn(*const CUDA_MEMCPY2D_st) -> CUresult> = unsafe {
+        libcuda.get(b"cuMemcpy2D_v2")?
+    };
+    let result = unsafe { func(&copy) };
+    let mut reply = Vec::new();
+    reply.write_i32::<BigEndian>(result)?;
+    Ok(reply)
+}
+
+/// The number of bytes from a device pointer to the end of the last row a 2D copy accesses. Saturates, so a client
+/// can't wrap it around to pass a range check.
+fn rows_size(x: u64, y: u64, pitch: u64, width: u64, height: u64) -> u64 {
+    match height.checked_sub(1) {
+        Some(last_row) => y.saturating_add(last_row).saturating_mul(pitch).saturating_add(x).saturating_add(width),
+        None => 0,
+    }
+}
+
+/// Checks the length of a chunk, which the client keeps within `MAX_MEMCPY_CHUNK_SIZE`.
+fn chunk_length(rpc: RPC, length: usize) -> anyhow::Result<usize> {
+    match length <= MAX_MEMCPY_CHUNK_SIZE as usize {
+        true => Ok(length),
+        false => Err(MalformedRequest(format!("{:?} has a chunk of {} bytes, more than {}",
+                                              rpc, length, MAX_MEMCPY_CHUNK_SIZE)).into()),
+    }
+}
@@ -0,0 +1,237 @@
+//! Loading the module images clients send, and getting their functions, see `client/src/module.rs`.
+//!
+//! Images are taken from the module cache if the client sent their hash, see [`cuda_over_ip_common::image`].
+
+use crate::marshal::{slice_as_bytes, Arguments, MalformedRequest, Plain};
+use crate::resources::Resource;
+use crate::session::Session;
+use byteorder::{BigEndian, NativeEndian, WriteBytesExt};
+use cuda_over_ip_common::RPC;
+use cuda_over_ip_common::cuda::*;
+use cuda_over_ip_common::handle::Handle;
+use cuda_over_ip_common::image::{ImageHash, IMAGE_BYTES, IMAGE_BY_HASH, IMAGE_FOUND, IMAGE_MISSING};
+use libloading::Library;
+use std::ffi::{c_char, c_uint, c_void, CString};
+use std::ptr::null_mut;
+use std::sync::Arc;
+
+#[allow(non_snake_case)]
+pub(crate) fn handle_cuModuleLoadData(payload: &[u8], libcuda: &Library, session: &Session) -> anyhow::Result<Vec<u8>> {
+    load_image(RPC::cuModuleLoadData, b"cuModuleLoadData", payload, libcuda, session)
+}
+
+#[allow(non_snake_case)]
+pub(crate) fn handle_cuModuleLoadFatBinary(payload: &[u8], libcuda: &Library, session: &Session) -> anyhow::Result<Vec<u8>> {
+    load_image(RPC::cuModuleLoadFatBinary, b"cuModuleLoadFatBinary", payload, libcuda, session)
+}
+
+fn load_image(rpc: RPC, name: &[u8], payload: &[u8], libcuda: &Library, session: &Session) -> anyhow::Result<Vec<u8>> {
+    let mut arguments = Arguments::new(rpc, payload);
+    let image = read_image(rpc, &mut arguments, session)?;
+    arguments.finish()?;
+    let Some(image) = image else {
+        return Ok(vec![IMAGE_MISSING]);
+    };
+    let func: libloading::Symbol<unsafe extern "C" fn(*mut *mut c_void, *const c_void) -> CUresult> = unsafe {
+        libcuda.get(name)?
+    };
+    let mut module = null_mut();
+    let result = unsafe { func(&mut module, image.bytes().as_ptr() as *const c_void) };
+    let mut reply = vec![IMAGE_FOUND];
+    reply.write_i32::<BigEndian>(result)?;
+    if result == CUDA_SUCCESS {
+        write_module(&mut reply, module, session);
+        image.loaded(session);
+    }
+    Ok(reply)
+}
+
+#[allow(non_snake_case)]
+pub(crate) fn handle_cuModuleLoadDataEx(payload: &[u8], libcuda: &Library, session: &Session) -> anyhow::Result<Vec<u8>> {
+    let mut arguments = Arguments::new(RPC::cuModuleLoadDataEx, payload);
+    let num_options: c_uint = arguments.value()?;
+    let length = arguments.length::<u64>(num_options)?;
+    let options: Vec<i32> = arguments.array(length)?;
+    let mut values: Vec<u64> = arguments.array(length)?;
+    // The driver reads and writes where the values of other options point, which the client must not choose.
+    let forwarded = |option: &i32| {
+        matches!(*option, CU_JIT_INFO_LOG_BUFFER | CU_JIT_ERROR_LOG_BUFFER) || CU_JIT_SCALAR_OPTIONS.contains(option)
+    };
+    if let Some(option) = options.iter().find(|option| !forwarded(option)) {
+        return Err(MalformedRequest(format!("cuModuleLoadDataEx has the JIT option {}, which is not forwarded",
+                                            option)).into());
+    }
+    let Some(image) = read_image(RPC::cuModuleLoadDataEx, &mut arguments, session)? else {
+        arguments.finish()?;
+        return Ok(vec![IMAGE_MISSING]);
+    };
+
+    // The buffers the driver writes the logs to, as large as the client's.
+    let mut logs = Vec::new();
+    for (index, option) in options.iter().enumerate() {
+        let size_option = match *option {
+            CU_JIT_INFO_LOG_BUFFER => CU_JIT_INFO_LOG_BUFFER_SIZE_BYTES,
+            CU_JIT_ERROR_LOG_BUFFER => CU_JIT_ERROR_LOG_BUFFER_SIZE_BYTES,
+            _ => continue,
+        };
+        // Size options hold an `unsigned int`.
+        let size = match options.iter().position(|option| *option == size_option) {
+            Some(size_index) => arguments.length::<u8>(values[size_index] as c_uint)?,
+            None => 0,
+        };
+        let mut log = vec![0_u8; size];
+        values[index] = log.as_mut_ptr() as u64;
+        logs.push((size_option, log));
+    }
+    arguments.finish()?;
+
+    let func: libloading::Symbol<unsafe extern "C" fn(*mut *mut c_void, *const c_void, c_uint, *const i32,
+                                                      *mut u64) -> CUresult> = unsafe {
+        libcuda.get(b"cuModuleLoadDataEx")?
+    };
+    let mut module = null_mut();
+    let result = unsafe {
+        func(&mut module, image.bytes().as_ptr() as *const c_void, num_options, options.as_ptr(), values.as_mut_ptr())
+    };
+
+    let mut reply = vec![IMAGE_FOUND];
+    reply.write_i32::<BigEndian>(result)?;
+    reply.extend_from_slice(slice_as_bytes(&values));
+    for (size_option, log) in &logs {
+        // The driver sets the size to the length of the log.
+        let log_length = match options.iter().position(|option| option == size_option) {
+            Some(index) => (values[index] as usize).min(log.len()),
+            None => 0,
+        };
+        reply.write_u64::<NativeEndian>(log_length as u64)?;
+        reply.extend_from_slice(&log[..log_length]);
+    }
+    if result == CUDA_SUCCESS {
+        write_module(&mut reply, module, session);
+        image.loaded(session);
+    }
+    Ok(reply)
+}
+
+/// An image to load, from the cache or from the request.
+enum Image {
+    Cached(Arc<Vec<u8>>),
+    Sent(Vec<u8>),
+}
+
+impl Image {
+    fn bytes(&self) -> &[u8] {
+        match self {
+            Image::Cached(image) => image,
+            Image::Sent(image) => image,
+        }
+    }
+
+    /// Caches an image the client sent once the driver loaded it, so the cache only has images that load.
+    fn loaded(self, session: &Session) {
+        if let Image::Sent(image) = self {
+            session.module_cache.insert(image);
+        }
+    }
+}
+
+/// Reads the hash of the image and takes the image from the cache, `None` if not cached, or reads the length of the
+/// image and its bytes.
+fn read_image(rpc: RPC, arguments: &mut Arguments, session: &Session) -> anyhow::Result<Option<Image>> {
+    match arguments.value::<u8>()? {
+        IMAGE_BY_HASH => {
+            let hash: Vec<u8> = arguments.array(size_of::<ImageHash>())?;
+            Ok(session.module_cache.get(&hash.try_into().unwrap()).map(Image::Cached))
+        }
+        IMAGE_BYTES => {
+            let length: usize = arguments.value()?;
+            let length = arguments.length::<u8>(length)?;
+            Ok(Some(Image::Sent(arguments.array(length)?)))
+        }
+        kind => Err(MalformedRequest(format!("{:?} has an image of unknown kind {}", rpc, kind)).into()),
+    }
+}
+
+fn write_module(reply: &mut Vec<u8>, module: *mut c_void, session: &Session) {
+    let mut state = session.state();
+    reply.extend_from_slice(state.handles.id(module).as_bytes());
+    state.resources.created(Resource::Module, module.to_u64());
+}
+
+#[allow(non_snake_case)]
+pub(crate) fn handle_cuModuleGetFunction(payload: &[u8], libcuda: &Library, session: &Session) -> anyhow::Result<Vec<u8>> {
+    let mut arguments = Arguments::new(RPC::cuModuleGetFunction, payload);
+    let hmod: u64 = arguments.value()?;
+    let length: usize = arguments.value()?;
+    let length = arguments.length::<u8>(length)?;
+    let name: Vec<u8> = arguments.array(length)?;
+    arguments.finish()?;
+    let hmod: *mut c_void = session.state().handles.raw(hmod)?;
+    // A name with a NUL can't name a function.
+    let Ok(name) = CString::new(name) else {
+        let mut reply = Vec::new();
+        reply.write_i32::<BigEndian>(CUDA_ERROR_NOT_FOUND)?;
+        return Ok(reply);
+    };
+    let func: libloading::Symbol<unsafe extern "C" fn(*mut *mut c_void, *mut c_void, *const c_char) -> CUresult> = unsafe {
+        libcuda.get(b"cuModuleGetFunction")?
+    };
+    let mut function = null_mut();
+    let result = unsafe { func(&mut function, hmod, name.as_ptr()) };
+    let mut reply = Vec::new();
+    reply.write_i32::<BigEndian>(result)?;
+    if result == CUDA_SUCCESS {
+        let mut state = session.state();
+        reply.extend_from_slice(state.handles.id(function).as_bytes());
+        state.module_functions.entry(hmod.to_u64()).or_default().insert(function.to_u64());
+    }
+    Ok(reply)
+}
+
+/// Unloads the module like a generated handler, the client forgets the layouts of its functions, see
+/// `client/src/module.rs`.
+#[allow(non_snake_case)]
+pub(crate) fn handle_cuModuleUnload(payload: &[u8], libcuda: &Library, session: &Session) -> anyhow::Result<Vec<u8>> {
+    let mut arguments = Arguments::new(RPC::cuModuleUnload, payload);
+    let hmod: u64 = arguments.value()?;
+    arguments.finish()?;
+    let hmod: *mut c_void = session.state().handles.raw(hmod)?;
+    let func: libloading::Symbol<unsafe extern "C" fn(*mut c_void) -> CUresult> = unsafe {
+        libcuda.get(b"cuModuleUnload")?
+    };
+    let result = unsafe { func(hmod) };
+    let mut reply = Vec::new();
+    reply.write_i32::<BigEndian>(result)?;
+    if result == CUDA_SUCCESS {
+        session.state().destroyed(Resource::Module, hmod.to_u64());
+    }
+    Ok(reply)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::module_cache::ModuleCache;
+    use crate::session::SessionRegistry;
+    use std::time::Duration;
+
+    #[test]
+    fn pointer_jit_options_rejected() {
+        // A library without the driver's functions.
+        let libcuda = unsafe { Library::new("libc.so.6") }.unwrap();
+        let sessions = SessionRegistry::new(Duration::from_secs(1), None, ModuleCache::new(0, None), |_| {});
+        let attachment = sessions.create().unwrap();
+        let image = b".version 8.0\n\0";
+        let request = |option: i32| {
+            [1_u32.to_ne_bytes().as_slice(), &option.to_ne_bytes(), &0x7f00_0000_1000_u64.to_ne_bytes(),
+             &[IMAGE_BYTES], &image.len().to_ne_bytes(), image].concat()
+        };
+
+        // `CU_JIT_GLOBAL_SYMBOL_ADDRESSES`, an array the driver would write to.
+        let error = handle_cuModuleLoadDataEx(&request(18), &libcuda, &attachment.session).unwrap_err();
+        assert!(error.is::<MalformedRequest>());
+        // `CU_JIT_MAX_REGISTERS` is passed to the driver, which is missing here.
+        let error = handle_cuModuleLoadDataEx(&request(0), &libcuda, &attachment.session).unwrap_err();
+        assert!(error.is::<libloading::Error>());
+    }
+}
@@ -0,0 +1,123 @@
+//! The module images clients sent, by their hash, so other clients load them without uploading them again.
+
+use cuda_over_ip_common::image::{image_hash, ImageHash};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// Shared by all sessions of the server process. Evicts the images loaded least recently to stay within its size,
+/// and the images not loaded for `expiry`.
+pub(crate) struct ModuleCache {
+    size: usize,
+    expiry: Option<Duration>,
+    state: Mutex<CacheState>,
+}
+
+#[derive(Default)]
+struct CacheState {
+    images: HashMap<ImageHash, CachedImage>,
+    bytes: usize,
+}
+
+struct CachedImage {
+    image: Arc<Vec<u8>>,
+    last_loaded: Instant,
+}
+
+impl ModuleCache {
+    /// A cache of `size` bytes, 0 disables it.
+    pub(crate) fn new(size: usize, expiry: Option<Duration>) -> Self {
+        ModuleCache { size, expiry, state: Mutex::new(CacheState::default()) }
+    }
+
+    /// The image with the hash, if cached.
+    pub(crate) fn get(&self, hash: &ImageHash) -> Option<Arc<Vec<u8>>> {
+        let mut state = self.state();
+        self.evict_expired(&mut state);
+        let cached = state.images.get_mut(hash)?;
+        cached.last_loaded = Instant::now();
+        Some(cached.image.clone())
+    }
+
+    /// Caches an image a client sent, unless larger than the cache.
+    pub(crate) fn insert(&self, image: Vec<u8>) {
+        if image.len() > self.size {
+            return;
+        }
+        let hash = image_hash(&image);
+        let mut state = self.state();
+        self.evict_expired(&mut state);
+        Self::evict(&mut state, &hash);
+        while state.bytes + image.len() > self.size {
+            let least_recent = *state.images.iter().min_by_key(|(_, cached)| cached.last_loaded).unwrap().0;
+            Self::evict(&mut state, &least_recent);
+        }
+        state.bytes += image.len();
+        state.images.insert(hash, CachedImage { image: Arc::new(image), last_loaded: Instant::now() });
+    }
+
+    fn evict_expired(&self, state: &mut CacheState) {
+        let Some(expiry) = self.expiry else {
+            return;
+        };
+        let now = Instant::now();
+        let expired: Vec<ImageHash> = state.images.iter()
+            .filter(|(_, cached)| now.duration_since(cached.last_loaded) >= expiry)
+            .map(|(hash, _)| *hash)
+            .collect();
+        expired.iter().for_each(|hash| Self::evict(state, hash));
+    }
+
+    fn evict(state: &mut CacheState, hash: &ImageHash) {
+        if let Some(cached) = state.images.remove(hash) {
+            state.bytes -= cached.image.len();
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, CacheState> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn least_recently_loaded_evicted() {
+        let cache = ModuleCache::new(10, None);
+        cache.insert(vec![1; 4]);
+        cache.insert(vec![2; 4]);
+        assert_eq!(*cache.get(&image_hash(&[1; 4])).unwrap(), [1; 4]);
+        // Evicts the second image, loaded before the first one was loaded again.
+        cache.insert(vec![3; 4]);
+        assert!(cache.get(&image_hash(&[2; 4])).is_none());
+        assert!(cache.get(&image_hash(&[1; 4])).is_some());
+        assert!(cache.get(&image_hash(&[3; 4])).is_some());
+
+        // Inserting an image again does not count it twice.
+        cache.insert(vec![3; 4]);
+        assert!(cache.get(&image_hash(&[1; 4])).is_some());
+
+        // Too large to cache.
+        cache.insert(vec![4; 11]);
+        assert!(cache.get(&image_hash(&[4; 11])).is_none());
+        assert!(cache.get(&image_hash(&[3; 4])).is_some());
+
+        let disabled = ModuleCache::new(0, None);
+        disabled.insert(vec![1]);
+        assert!(disabled.get(&image_hash(&[1])).is_none());
+    }
+
+    #[test]
+    fn expiry() {
+        let cache = ModuleCache::new(10, Some(Duration::from_millis(200)));
+        cache.insert(vec![1; 4]);
+        thread::sleep(Duration::from_millis(120));
+        cache.insert(vec![2; 4]);
+        thread::sleep(Duration::from_millis(120));
+        assert!(cache.get(&image_hash(&[1; 4])).is_none());
+        assert!(cache.get(&image_hash(&[2; 4])).is_some());
+    }
+}
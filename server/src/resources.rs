@@ -0,0 +1,136 @@
+//! The driver objects a session created, released when the session ends.
+
+use cuda_over_ip_common::cuda::{CUresult, CUDA_SUCCESS, CU_POINTER_ATTRIBUTE_CONTEXT};
+use cuda_over_ip_common::handle::Handle;
+use libloading::Library;
+use log::warn;
+use std::collections::BTreeMap;
+use std::ffi::c_void;
+use std::ptr::null_mut;
+
+/// Kinds of resources, in the order they are released: what belongs to a context before the context.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Resource {
+    Stream,
+    Event,
+    /// A loaded module, with its functions.
+    Module,
+    /// Device memory, freed in the context it was allocated in.
+    Memory,
+    Context,
+    /// A reference to the primary context of the device.
+    PrimaryContext,
+}
+
+impl Resource {
+    /// Releases the resource identified by `value`, the handle or the device.
+    fn release(self, value: u64, libcuda: &Library) -> anyhow::Result<CUresult> {
+        unsafe {
+            match self {
+                Resource::Stream => call(libcuda, b"cuStreamDestroy_v2", <*mut c_void>::from_u64(value)),
+                Resource::Event => call(libcuda, b"cuEventDestroy_v2", <*mut c_void>::from_u64(value)),
+                Resource::Module => call(libcuda, b"cuModuleUnload", <*mut c_void>::from_u64(value)),
+                Resource::Memory => free_memory(value, libcuda),
+                Resource::Context => call(libcuda, b"cuCtxDestroy_v2", <*mut c_void>::from_u64(value)),
+                Resource::PrimaryContext => call(libcuda, b"cuDevicePrimaryCtxRelease_v2", value as i32),
+            }
+        }
+    }
+}
+
+/// Frees the allocation in its context, as the thread releasing resources has none current.
+unsafe fn free_memory(dptr: u64, libcuda: &Library) -> anyhow::Result<CUresult> {
+    let get_attribute: libloading::Symbol<unsafe extern "C" fn(*mut c_void, i32, u64) -> CUresult> =
+        libcuda.get(b"cuPointerGetAttribute")?;
+    let mut context: *mut c_void = null_mut();
+    match get_attribute(&mut context as *mut _ as *mut c_void, CU_POINTER_ATTRIBUTE_CONTEXT, dptr) {
+        CUDA_SUCCESS => {}
+        result => return Ok(result),
+    }
+    match call(libcuda, b"cuCtxSetCurrent", context)? {
+        CUDA_SUCCESS => {}
+        result => return Ok(result),
+    }
+    let result = call(libcuda, b"cuMemFree_v2", dptr);
+    call(libcuda, b"cuCtxSetCurrent", null_mut::<c_void>())?;
+    result
+}
+
+unsafe fn call<T>(libcuda: &Library, name: &[u8], argument: T) -> anyhow::Result<CUresult> {
+    let func: libloading::Symbol<unsafe extern "C" fn(T) -> CUresult> = libcuda.get(name)?;
+    Ok(func(argument))
+}
+
+/// The resources a session holds, with how many times it holds them.
+#[derive(Default)]
+pub(crate) struct Resources {
+    held: BTreeMap<(Resource, u64), u32>,
+}
+
+impl Resources {
+    pub(crate) fn created(&mut self, resource: Resource, value: u64) {
+        *self.held.entry((resource, value)).or_default() += 1;
+    }
+
+    pub(crate) fn destroyed(&mut self, resource: Resource, value: u64) {
+        if let Some(count) = self.held.get_mut(&(resource, value)) {
+            *count -= 1;
+            if *count == 0 {
+                self.held.remove(&(resource, value));
+            }
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.held.is_empty()
+    }
+
+    /// Releases all resources in order, returns how many were released.
+    pub(crate) fn release_all(&mut self, libcuda: &Library) -> usize {
+        let mut released = 0;
+        for ((resource, value), count) in std::mem::take(&mut self.held) {
+            for _ in 0..count {
+                match resource.release(value, libcuda) {
+                    Ok(CUDA_SUCCESS) => released += 1,
+                    Ok(result) => warn!("Releasing {:?} {:#x} failed with {}", resource, value, result),
+                    Err(e) => warn!("Cannot release {:?} {:#x}: {}", resource, value, e),
+                }
+            }
+        }
+        released
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::resources::{Resource, Resources};
+
+    #[test]
+    fn release_order() {
+        let mut resources = Resources::default();
+        resources.created(Resource::Context, 0x10);
+        resources.created(Resource::PrimaryContext, 1);
+        resources.created(Resource::PrimaryContext, 1);
+        resources.created(Resource::Event, 0x30);
+        resources.created(Resource::Memory, 0x7_0000_0000);
+        resources.created(Resource::Stream, 0x20);
+        resources.created(Resource::Module, 0x40);
+        resources.created(Resource::Stream, 0x21);
+        resources.destroyed(Resource::Stream, 0x21);
+        resources.destroyed(Resource::Stream, 0x99);
+
+        let held: Vec<_> = resources.held.iter().map(|(key, count)| (*key, *count)).collect();
+        assert_eq!(held, [
+            ((Resource::Stream, 0x20), 1),
+            ((Resource::Event, 0x30), 1),
+            ((Resource::Module, 0x40), 1),
+            ((Resource::Memory, 0x7_0000_0000), 1),
+            ((Resource::Context, 0x10), 1),
+            ((Resource::PrimaryContext, 1), 2),
+        ]);
+
+        resources.destroyed(Resource::PrimaryContext, 1);
+        assert_eq!(resources.held[&(Resource::PrimaryContext, 1)], 1);
+        assert!(!resources.is_empty());
+    }
+}
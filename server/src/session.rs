@@ -0,0 +1,355 @@
+use crate::handles::HandleTable;
+use libloading::Library;
+use crate::module_cache::ModuleCache;
+use crate::resources::{Resource, Resources};
+use cuda_over_ip_common::RPC;
+use cuda_over_ip_common::frame::{Frame, MAX_IN_FLIGHT_REQUESTS};
+use cuda_over_ip_common::handshake::SessionToken;
+use log::{info, warn};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Server-side state of a client, shared by all its connections and kept across reconnects.
+pub(crate) struct Session {
+    pub(crate) token: SessionToken,
+    state: Mutex<SessionState>,
+    /// Shared with the other sessions of the server process.
+    pub(crate) module_cache: Arc<ModuleCache>,
+}
+
+#[derive(Default)]
+pub(crate) struct SessionState {
+    /// Replies sent on every connection slot. A client that lost the connection before receiving them resends
+    /// the requests after resuming, and gets these replies instead of executing the calls twice.
+    pub(crate) sent_replies: HashMap<u32, SentReplies>,
+    pub(crate) handles: HandleTable,
+    /// What the session created and did not destroy, released when the session ends.
+    pub(crate) resources: Resources,
+    /// The driver's current context of the thread serving every connection slot, made current again by the thread
+    /// serving the slot after the client reconnects.
+    pub(crate) current_contexts: HashMap<u32, u64>,
+    /// The driver's functions the session got from every module, whose handles become invalid when it is unloaded.
+    pub(crate) module_functions: HashMap<u64, HashSet<u64>>,
+}
+
+impl SessionState {
+    /// Records that the client destroyed the resource with the handle, which it cannot use anymore.
+    pub(crate) fn destroyed(&mut self, resource: Resource, raw: u64) {
+        self.resources.destroyed(resource, raw);
+        self.handles.forget(raw);
+        match resource {
+            Resource::Context => self.current_contexts.retain(|_, context| *context != raw),
+            Resource::Module => self.module_functions.remove(&raw).into_iter().flatten()
+                .for_each(|function| self.handles.forget(function)),
+            _ => {}
+        }
+    }
+
+    /// Releases all resources, whose handles become invalid, returns how many were released.
+    pub(crate) fn release_all(&mut self, libcuda: &Library) -> usize {
+        let released = self.resources.release_all(libcuda);
+        self.module_functions.clear();
+        self.current_contexts.clear();
+        self.handles = HandleTable::default();
+        released
+    }
+}
+
+/// Bytes of replies kept per connection slot, beyond which the oldest are discarded, the newest one is always kept.
+const MAX_SENT_REPLY_BYTES: usize = 4 * 1024 * 1024;
+
+/// The last `MAX_IN_FLIGHT_REQUESTS` replies of a connection slot, as many as the client can be waiting for, up to
+/// `MAX_SENT_REPLY_BYTES`. The chunks of copies to the host are not kept but executed again, see [`Self::executed_again`].
+#[derive(Default)]
+pub(crate) struct SentReplies {
+    replies: VecDeque<Frame>,
+    bytes: usize,
+    /// Request IDs of a connection slot only increase, so requests up to this one have been executed.
+    last_request_id: Option<u64>,
+}
+
+impl SentReplies {
+    pub(crate) fn executed(&self, request_id: u64) -> bool {
+        self.last_request_id.is_some_and(|last| request_id <= last)
+    }
+
+    pub(crate) fn get(&self, request_id: u64) -> Option<&Frame> {
+        self.replies.iter().find(|reply| reply.header.request_id == request_id)
+    }
+
+    pub(crate) fn push(&mut self, reply: Frame) {
+        self.push_executed(reply.header.request_id);
+        self.bytes += reply.payload.len();
+        self.replies.push_back(reply);
+        while self.replies.len() > MAX_IN_FLIGHT_REQUESTS
+            || (self.bytes > MAX_SENT_REPLY_BYTES && self.replies.len() > 1) {
+            let discarded = self.replies.pop_front().unwrap();
+            self.bytes -= discarded.payload.len();
+        }
+    }
+
+    /// Records that the request was executed without keeping its reply.
+    pub(crate) fn push_executed(&mut self, request_id: u64) {
+        self.last_request_id = self.last_request_id.max(Some(request_id));
+    }
+
+    /// Whether a resent request is executed again instead of getting its reply. Only a copy to the host, whose chunks
+    /// would take up to `MAX_MEMCPY_CHUNK_SIZE` each to keep: reading the memory again has no effect the client
+    /// would notice, as it waits for the copy before using the memory otherwise.
+    pub(crate) fn executed_again(request: &Frame) -> bool {
+        !request.is_batch() && request.header.rpc_id == RPC::cuMemcpyDtoH_v2 as i32
+    }
+}
+
+impl Session {
+    pub(crate) fn state(&self) -> MutexGuard<'_, SessionState> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+struct SessionEntry {
+    session: Arc<Session>,
+    connections: usize,
+    /// When the last connection went away; `None` while connections are attached.
+    detached_since: Option<Instant>,
+}
+
+/// All live sessions. A session without connections is kept for the grace period, then dropped.
+pub(crate) struct SessionRegistry {
+    sessions: Mutex<HashMap<SessionToken, SessionEntry>>,
+    grace_period: Duration,
+    /// How many sessions there may be, also counting the ones waiting for their client to reconnect.
+    max_sessions: Option<usize>,
+    module_cache: Arc<ModuleCache>,
+    /// Called on every session that ended, to release what it holds.
+    on_end: Box<dyn Fn(&Session) + Send + Sync>,
+}
+
+/// Keeps a connection attached to its session, detaches it when dropped.
+pub(crate) struct SessionAttachment {
+    registry: Arc<SessionRegistry>,
+    pub(crate) session: Arc<Session>,
+}
+
+impl SessionRegistry {
+    pub(crate) fn new(grace_period: Duration, max_sessions: Option<usize>, module_cache: ModuleCache,
+                      on_end: impl Fn(&Session) + Send + Sync + 'static) -> Arc<Self> {
+        let registry = Arc::new(SessionRegistry {
+            sessions: Mutex::new(HashMap::new()),
+            grace_period,
+            max_sessions,
+            module_cache: Arc::new(module_cache),
+            on_end: Box::new(on_end),
+        });
+
+        let weak_registry = Arc::downgrade(&registry);
+        thread::spawn(move || {
+            let period = (grace_period / 2).clamp(Duration::from_millis(100), Duration::from_secs(10));
+            loop {
+                thread::sleep(period);
+                match weak_registry.upgrade() {
+                    Some(registry) => registry.expire(),
+                    None => break,
+                }
+            }
+        });
+
+        registry
+    }
+
+    /// Starts a new session, unless there are `max_sessions` already. The error is the reason to tell the client.
+    pub(crate) fn create(self: &Arc<Self>) -> Result<SessionAttachment, String> {
+        let sessions = self.sessions();
+        if let Some(max_sessions) = self.max_sessions {
+            if sessions.len() >= max_sessions {
+                warn!("Rejecting client: {} clients already connected", max_sessions);
+                return Err(format!("{} clients already connected", max_sessions));
+            }
+        }
+        let token = loop {
+            let token = random_token();
+            if !sessions.contains_key(&token) {
+                break token;
+            }
+        };
+        Ok(self.insert(sessions, token))
+    }
+
+    /// Starts a new session with a token chosen by the supervisor of this worker process.
+    pub(crate) fn create_with_token(self: &Arc<Self>, token: SessionToken) -> SessionAttachment {
+        let sessions = self.sessions();
+        assert!(!sessions.contains_key(&token), "session {:032x} exists", token);
+        self.insert(sessions, token)
+    }
+
+    fn insert(self: &Arc<Self>, mut sessions: MutexGuard<'_, HashMap<SessionToken, SessionEntry>>,
+              token: SessionToken) -> SessionAttachment {
+        let session = Arc::new(Session {
+            token,
+            state: Mutex::new(SessionState::default()),
+            module_cache: self.module_cache.clone(),
+        });
+        sessions.insert(token, SessionEntry { session: session.clone(), connections: 1, detached_since: None });
+        info!("Session {:032x} started", token);
+        SessionAttachment { registry: self.clone(), session }
+    }
+
+    /// Attaches a connection to an existing session, `None` if the session is unknown or expired.
+    pub(crate) fn resume(self: &Arc<Self>, token: SessionToken) -> Option<SessionAttachment> {
+        let mut sessions = self.sessions();
+        let entry = sessions.get_mut(&token)?;
+        entry.connections += 1;
+        entry.detached_since = None;
+        info!("Session {:032x} resumed", token);
+        Some(SessionAttachment { registry: self.clone(), session: entry.session.clone() })
+    }
+
+    fn detach(&self, token: SessionToken) {
+        if let Some(entry) = self.sessions().get_mut(&token) {
+            entry.connections -= 1;
+            if entry.connections == 0 {
+                entry.detached_since = Some(Instant::now());
+            }
+        }
+    }
+
+    fn expire(&self) {
+        let now = Instant::now();
+        let mut expired = Vec::new();
+        self.sessions().retain(|token, entry| match entry.detached_since {
+            Some(since) if now.duration_since(since) >= self.grace_period => {
+                warn!("Session {:032x} expired", token);
+                expired.push(entry.session.clone());
+                false
+            }
+            _ => true,
+        });
+        // Outside the lock, releasing can take a while.
+        expired.iter().for_each(|session| (self.on_end)(session));
+    }
+
+    fn sessions(&self) -> MutexGuard<'_, HashMap<SessionToken, SessionEntry>> {
+        self.sessions.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Drop for SessionAttachment {
+    fn drop(&mut self) {
+        self.registry.detach(self.session.token);
+    }
+}
+
+pub(crate) fn random_token() -> SessionToken {
+    let mut bytes = [0_u8; size_of::<SessionToken>()];
+    getrandom::getrandom(&mut bytes).expect("no source of randomness for session tokens");
+    SessionToken::from_ne_bytes(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resume_within_grace_period() {
+        let registry = SessionRegistry::new(Duration::from_millis(200), None, ModuleCache::new(0, None), |_| {});
+        let attachment = registry.create().unwrap();
+        let token = attachment.session.token;
+        drop(attachment);
+
+        let resumed = registry.resume(token).unwrap();
+        assert_eq!(resumed.session.token, token);
+        assert!(registry.resume(token + 1).is_none());
+
+        // An attached session does not expire.
+        thread::sleep(Duration::from_millis(300));
+        registry.expire();
+        drop(resumed);
+        assert!(registry.resume(token).is_some());
+    }
+
+    #[test]
+    fn sent_replies_window() {
+        let mut sent = SentReplies::default();
+        assert!(!sent.executed(0));
+        for request_id in 0..MAX_IN_FLIGHT_REQUESTS as u64 + 2 {
+            sent.push(Frame::reply(&Frame::request(request_id, 1, Vec::new()).header, vec![request_id as u8]));
+        }
+        assert!(sent.executed(0));
+        assert!(sent.get(1).is_none());
+        assert_eq!(sent.get(2).unwrap().payload, vec![2]);
+        assert!(!sent.executed(MAX_IN_FLIGHT_REQUESTS as u64 + 2));
+
+        // Large replies are discarded before there are `MAX_IN_FLIGHT_REQUESTS` of them.
+        let mut sent = SentReplies::default();
+        let chunk_length = MAX_SENT_REPLY_BYTES / 3;
+        for request_id in 0..4 {
+            sent.push(Frame::reply(&Frame::request(request_id, 1, Vec::new()).header, vec![0; chunk_length]));
+        }
+        assert!(sent.get(0).is_none());
+        assert!(sent.get(1).is_some());
+        assert!(sent.executed(0));
+
+        // Requests executed without keeping their replies count too.
+        sent.push_executed(5);
+        sent.push_executed(4);
+        assert!(sent.executed(5));
+        assert!(!sent.executed(6));
+        assert!(SentReplies::executed_again(&Frame::request(6, RPC::cuMemcpyDtoH_v2 as i32, Vec::new())));
+        assert!(!SentReplies::executed_again(&Frame::request(6, RPC::cuMemcpyHtoD_v2 as i32, Vec::new())));
+    }
+
+    #[test]
+    fn expire_after_grace_period() {
+        let ended = Arc::new(Mutex::new(Vec::new()));
+        let registry = SessionRegistry::new(Duration::from_millis(10), None, ModuleCache::new(0, None), {
+            let ended = ended.clone();
+            move |session| ended.lock().unwrap().push(session.token)
+        });
+        let token = registry.create().unwrap().session.token;
+        thread::sleep(Duration::from_millis(20));
+        registry.expire();
+        assert!(registry.resume(token).is_none());
+        assert_eq!(*ended.lock().unwrap(), [token]);
+    }
+
+    #[test]
+    fn destroyed_context_not_restored() {
+        let mut state = SessionState::default();
+        state.resources.created(Resource::Context, 0x5000);
+        state.current_contexts.insert(0, 0x5000);
+        state.current_contexts.insert(1, 0x6000);
+        state.destroyed(Resource::Context, 0x5000);
+        assert_eq!(state.current_contexts, HashMap::from([(1, 0x6000)]));
+    }
+
+    #[test]
+    fn unloaded_module_functions_forgotten() {
+        let mut state = SessionState::default();
+        let module = state.handles.id(0x5000_u64);
+        let function = state.handles.id(0x6000_u64);
+        state.module_functions.entry(0x5000).or_default().insert(0x6000);
+        state.destroyed(Resource::Module, 0x5000);
+        assert!(state.handles.raw::<u64>(module).is_err());
+        assert!(state.handles.raw::<u64>(function).is_err());
+        assert!(state.module_functions.is_empty());
+    }
+
+    #[test]
+    fn max_sessions() {
+        let registry = SessionRegistry::new(Duration::from_millis(10), Some(1), ModuleCache::new(0, None), |_| {});
+        let attachment = registry.create().unwrap();
+        // Also while waiting for the client to reconnect.
+        let token = attachment.session.token;
+        drop(attachment);
+        assert!(registry.create().is_err());
+        // More connections to the session are no more sessions.
+        let resumed = registry.resume(token).unwrap();
+        let other_connection = registry.resume(token).unwrap();
+        drop((resumed, other_connection));
+        thread::sleep(Duration::from_millis(20));
+        registry.expire();
+        assert!(registry.create().is_ok());
+    }
+}
@@ -0,0 +1,262 @@
+//! Process isolation: every session is served by a worker process with its own instance of the driver.
+//!
+//! The supervisor accepts the connections and peeks at their client hello. It starts a worker for a new session and
+//! hands the connections of an existing session to its worker, over a Unix socket. The worker does the handshake
+//! and serves the connections like the server does in thread isolation, and exits when its session ends.
+
+use crate::config::ServerConfig;
+use crate::session::{random_token, SessionRegistry};
+use crate::module_cache::ModuleCache;
+use crate::{release_resources, serve};
+use anyhow::Context;
+use cuda_over_ip_common::handshake::{ClientHello, HandshakeError, ServerHello, SessionToken};
+use libloading::Library;
+use log::{info, warn};
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::io;
+use std::net::{TcpListener, TcpStream};
+use std::os::fd::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::os::unix::process::CommandExt;
+use std::process::{exit, Command};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The file descriptor of the Unix socket a worker receives its connections on.
+const CONTROL_FD: RawFd = 3;
+
+/// How long a client has to send its hello.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Magic, protocol version, API version, capabilities, token flag, token and connection slot.
+const MAX_CLIENT_HELLO_LENGTH: usize = 4 + 2 + 4 + 4 + 1 + 16 + 4;
+
+struct Worker {
+    process_id: u32,
+    control: UnixStream,
+}
+
+/// The running workers, by the session they serve.
+type Workers = Arc<Mutex<HashMap<SessionToken, Worker>>>;
+
+/// Accepts connections and hands them to the workers.
+pub(crate) fn supervise(config: &ServerConfig, listener: TcpListener) -> anyhow::Result<()> {
+    let workers = Workers::default();
+    while let Ok((tcp_stream, client)) = listener.accept() {
+        info!("Client {} connected", client);
+        tcp_stream.set_nodelay(true).expect("set_nodelay call failed");
+        let workers = workers.clone();
+        let max_sessions = config.max_clients;
+        thread::spawn(move || {
+            if let Err(e) = hand_over(tcp_stream, &workers, max_sessions) {
+                warn!("Cannot hand over the connection of client {}: {:#}", client, e);
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Hands the connection to the worker of its session, starting the worker of a new session.
+fn hand_over(tcp_stream: TcpStream, workers: &Workers, max_sessions: Option<usize>) -> anyhow::Result<()> {
+    let client_hello = peek_client_hello(&tcp_stream)?;
+    let mut running_workers = lock(workers);
+    match client_hello.session_token {
+        Some(token) => match running_workers.get(&token) {
+            Some(worker) => send_connection(&worker.control, &tcp_stream)
+                .with_context(|| format!("Cannot reach worker {}", worker.process_id)),
+            // What the server says in thread isolation.
+            None => reject(tcp_stream, format!("session {:032x} is unknown or expired", token)),
+        },
+        None => {
+            if let Some(max_sessions) = max_sessions {
+                if running_workers.len() >= max_sessions {
+                    warn!("Rejecting client: {} clients already connected", max_sessions);
+                    return reject(tcp_stream, format!("{} clients already connected", max_sessions));
+                }
+            }
+            let token = loop {
+                let token = random_token();
+                if !running_workers.contains_key(&token) {
+                    break token;
+                }
+            };
+            let worker = start_worker(token, workers)?;
+            send_connection(&worker.control, &tcp_stream)
+                .with_context(|| format!("Cannot reach worker {}", worker.process_id))?;
+            running_workers.insert(token, worker);
+            Ok(())
+        }
+    }
+}
+
+/// Reads the client hello without consuming it, the worker reads it again.
+fn peek_client_hello(tcp_stream: &TcpStream) -> Result<ClientHello, HandshakeError> {
+    let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+    tcp_stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    let mut buffer = [0; MAX_CLIENT_HELLO_LENGTH];
+    let client_hello = loop {
+        let length = tcp_stream.peek(&mut buffer)?;
+        match ClientHello::read_from(&mut &buffer[..length]) {
+            // The rest of the hello is yet to arrive.
+            Err(HandshakeError::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof && length > 0
+                && Instant::now() < deadline => thread::sleep(Duration::from_millis(10)),
+            result => break result?,
+        }
+    };
+    tcp_stream.set_read_timeout(None)?;
+    Ok(client_hello)
+}
+
+fn reject(mut tcp_stream: TcpStream, reason: String) -> anyhow::Result<()> {
+    // Read the hello first, the client would not get the answer if the connection were closed with unread data.
+    ClientHello::read_from(&mut tcp_stream)?;
+    ServerHello::Rejected { reason: reason.clone() }.write_to(&mut tcp_stream)?;
+    Err(HandshakeError::Rejected(reason).into())
+}
+
+/// Starts the server executable as the worker of the session, with the same arguments.
+fn start_worker(token: SessionToken, workers: &Workers) -> anyhow::Result<Worker> {
+    let (control, worker_control) = UnixStream::pair()?;
+    let worker_control_fd = worker_control.as_raw_fd();
+    let mut command = Command::new(std::env::current_exe()?);
+    command.args(std::env::args_os().skip(1)).arg("--worker-session").arg(format!("{:x}", token));
+    unsafe {
+        command.pre_exec(move || {
+            // The duplicate is not closed on exec, unlike the original.
+            let result = match worker_control_fd {
+                CONTROL_FD => libc::fcntl(CONTROL_FD, libc::F_SETFD, 0),
+                _ => libc::dup2(worker_control_fd, CONTROL_FD),
+            };
+            match result {
+                -1 => Err(io::Error::last_os_error()),
+                _ => Ok(()),
+            }
+        });
+    }
+    let mut child = command.spawn().context("Cannot start a worker process")?;
+    let process_id = child.id();
+    info!("Worker {} serves session {:032x}", process_id, token);
+
+    let workers = workers.clone();
+    thread::spawn(move || {
+        let status = child.wait();
+        lock(&workers).remove(&token);
+        match status {
+            Ok(status) if status.success() => info!("Worker {} of session {:032x} exited", process_id, token),
+            Ok(status) => warn!("Worker {} of session {:032x} failed: {}", process_id, token, status),
+            Err(e) => warn!("Cannot wait for worker {} of session {:032x}: {}", process_id, token, e),
+        }
+    });
+    Ok(Worker { process_id, control })
+}
+
+/// Serves the session in a worker process, until the session ends or the supervisor exits.
+pub(crate) fn serve_session(config: &ServerConfig, token: SessionToken, libcuda: Arc<Library>,
+                            driver_version: i32) -> anyhow::Result<()> {
+    let control = unsafe { UnixStream::from_raw_fd(CONTROL_FD) };
+    let module_cache = ModuleCache::new(config.module_cache_size, config.module_cache_expiry);
+    // The supervisor limits the number of sessions.
+    let sessions = SessionRegistry::new(config.session_grace_period, None, module_cache, {
+        let libcuda = libcuda.clone();
+        move |session| {
+            release_resources(session, &libcuda);
+            exit(0);
+        }
+    });
+
+    // Attached until the first connection closes, so the session does not expire before its client connects.
+    let mut first_attachment = Some(sessions.create_with_token(token));
+    while let Some(tcp_stream) = receive_connection(&control).context("Cannot receive connections")? {
+        let libcuda = libcuda.clone();
+        let sessions = sessions.clone();
+        let attachment = first_attachment.take();
+        thread::spawn(move || {
+            serve(tcp_stream, &libcuda, driver_version, &sessions, Some(token));
+            drop(attachment);
+        });
+    }
+    info!("Supervisor exited, worker of session {:032x} exits too", token);
+    Ok(())
+}
+
+/// Passes the connection to the worker, as ancillary data of a single byte.
+fn send_connection(control: &UnixStream, tcp_stream: &TcpStream) -> io::Result<()> {
+    let mut byte = [0_u8];
+    let mut iov = libc::iovec { iov_base: byte.as_mut_ptr() as *mut c_void, iov_len: byte.len() };
+    let mut control_buffer = [0_u64; 4];
+    unsafe {
+        let mut message: libc::msghdr = std::mem::zeroed();
+        message.msg_iov = &mut iov;
+        message.msg_iovlen = 1;
+        message.msg_control = control_buffer.as_mut_ptr() as *mut c_void;
+        message.msg_controllen = libc::CMSG_SPACE(size_of::<RawFd>() as u32) as _;
+        let header = libc::CMSG_FIRSTHDR(&message);
+        (*header).cmsg_level = libc::SOL_SOCKET;
+        (*header).cmsg_type = libc::SCM_RIGHTS;
+        (*header).cmsg_len = libc::CMSG_LEN(size_of::<RawFd>() as u32) as _;
+        std::ptr::write_unaligned(libc::CMSG_DATA(header) as *mut RawFd, tcp_stream.as_raw_fd());
+        if libc::sendmsg(control.as_raw_fd(), &message, 0) == -1 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Receives the next connection from the supervisor, `None` once the supervisor exited.
+fn receive_connection(control: &UnixStream) -> io::Result<Option<TcpStream>> {
+    let mut byte = [0_u8];
+    let mut iov = libc::iovec { iov_base: byte.as_mut_ptr() as *mut c_void, iov_len: byte.len() };
+    let mut control_buffer = [0_u64; 4];
+    unsafe {
+        let mut message: libc::msghdr = std::mem::zeroed();
+        message.msg_iov = &mut iov;
+        message.msg_iovlen = 1;
+        message.msg_control = control_buffer.as_mut_ptr() as *mut c_void;
+        message.msg_controllen = size_of_val(&control_buffer) as _;
+        match libc::recvmsg(control.as_raw_fd(), &mut message, libc::MSG_CMSG_CLOEXEC) {
+            -1 => return Err(io::Error::last_os_error()),
+            0 => return Ok(None),
+            _ => {}
+        }
+        let header = libc::CMSG_FIRSTHDR(&message);
+        if header.is_null() || (*header).cmsg_level != libc::SOL_SOCKET || (*header).cmsg_type != libc::SCM_RIGHTS {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "message without a connection"));
+        }
+        let fd = std::ptr::read_unaligned(libc::CMSG_DATA(header) as *const RawFd);
+        Ok(Some(TcpStream::from_raw_fd(fd)))
+    }
+}
+
+fn lock(workers: &Workers) -> MutexGuard<'_, HashMap<SessionToken, Worker>> {
+    workers.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::worker::{receive_connection, send_connection};
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::os::unix::net::UnixStream;
+
+    #[test]
+    fn pass_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (accepted, _) = listener.accept().unwrap();
+
+        let (supervisor, worker) = UnixStream::pair().unwrap();
+        send_connection(&supervisor, &accepted).unwrap();
+        drop(accepted);
+        let mut received = receive_connection(&worker).unwrap().unwrap();
+
+        client.write_all(b"hello").unwrap();
+        let mut buffer = [0; 5];
+        received.read_exact(&mut buffer).unwrap();
+        assert_eq!(&buffer, b"hello");
+
+        drop(supervisor);
+        assert!(receive_connection(&worker).unwrap().is_none());
+    }
+}
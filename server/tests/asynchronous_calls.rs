@@ -0,0 +1,90 @@
+//! Calls the functions the client library sends without waiting for their replies. The errors of those stay with
+//! the client, so the calls get a client of their own.
+
+mod harness;
+
+use cuda_over_ip_common::cuda::{CUresult, CUDA_ERROR_ILLEGAL_ADDRESS, CUDA_ERROR_INVALID_HANDLE, CUDA_SUCCESS};
+use harness::Harness;
+use std::ffi::{c_char, c_void};
+use std::ptr::null_mut;
+
+type CuInit = unsafe extern "C" fn(u32) -> CUresult;
+type Handle = *mut c_void;
+type CuCtxCreate = unsafe extern "C" fn(*mut Handle, u32, i32) -> CUresult;
+type CuCtxGetCurrent = unsafe extern "C" fn(*mut Handle) -> CUresult;
+type CuCtxSynchronize = unsafe extern "C" fn() -> CUresult;
+type CuCreate = unsafe extern "C" fn(*mut Handle, u32) -> CUresult;
+type CuDestroy = unsafe extern "C" fn(Handle) -> CUresult;
+type CuEventRecord = unsafe extern "C" fn(Handle, Handle) -> CUresult;
+type CuStreamWaitEvent = unsafe extern "C" fn(Handle, Handle, u32) -> CUresult;
+type CuMemAlloc = unsafe extern "C" fn(*mut u64, usize) -> CUresult;
+type CuModuleLoadData = unsafe extern "C" fn(*mut Handle, *const c_void) -> CUresult;
+type CuModuleGetFunction = unsafe extern "C" fn(*mut Handle, Handle, *const c_char) -> CUresult;
+type CuLaunchKernel = unsafe extern "C" fn(Handle, u32, u32, u32, u32, u32, u32, u32, Handle, *mut *mut c_void,
+                                           *mut *mut c_void) -> CUresult;
+
+#[test]
+fn asynchronous_calls() {
+    let harness = Harness::start();
+    let init = unsafe { harness.client_function::<CuInit>("cuInit") };
+    let ctx_create = unsafe { harness.client_function::<CuCtxCreate>("cuCtxCreate_v2") };
+    let ctx_get_current = unsafe { harness.client_function::<CuCtxGetCurrent>("cuCtxGetCurrent") };
+    let ctx_synchronize = unsafe { harness.client_function::<CuCtxSynchronize>("cuCtxSynchronize") };
+    let stream_create = unsafe { harness.client_function::<CuCreate>("cuStreamCreate") };
+    let stream_synchronize = unsafe { harness.client_function::<CuDestroy>("cuStreamSynchronize") };
+    let stream_wait_event = unsafe { harness.client_function::<CuStreamWaitEvent>("cuStreamWaitEvent") };
+    let event_create = unsafe { harness.client_function::<CuCreate>("cuEventCreate") };
+    let event_record = unsafe { harness.client_function::<CuEventRecord>("cuEventRecord") };
+    let event_destroy = unsafe { harness.client_function::<CuDestroy>("cuEventDestroy_v2") };
+    let mem_alloc = unsafe { harness.client_function::<CuMemAlloc>("cuMemAlloc_v2") };
+    let load_data = unsafe { harness.client_function::<CuModuleLoadData>("cuModuleLoadData") };
+    let get_function = unsafe { harness.client_function::<CuModuleGetFunction>("cuModuleGetFunction") };
+    let launch_kernel = unsafe { harness.client_function::<CuLaunchKernel>("cuLaunchKernel") };
+
+    assert_eq!(unsafe { init(0) }, CUDA_SUCCESS);
+    let mut context = null_mut();
+    assert_eq!(unsafe { ctx_create(&mut context, 0, 0) }, CUDA_SUCCESS);
+    let mut stream = null_mut();
+    assert_eq!(unsafe { stream_create(&mut stream, 0) }, CUDA_SUCCESS);
+    let (mut event, mut destroyed_event) = (null_mut(), null_mut());
+    assert_eq!(unsafe { event_create(&mut event, 0) }, CUDA_SUCCESS);
+    assert_eq!(unsafe { event_create(&mut destroyed_event, 0) }, CUDA_SUCCESS);
+    assert_eq!(unsafe { event_destroy(destroyed_event) }, CUDA_SUCCESS);
+
+    // A batch that succeeds.
+    assert_eq!(unsafe { event_record(event, stream) }, CUDA_SUCCESS);
+    assert_eq!(unsafe { stream_wait_event(null_mut(), event, 0) }, CUDA_SUCCESS);
+    assert_eq!(unsafe { stream_synchronize(stream) }, CUDA_SUCCESS);
+
+    // The calls return before the server executes them. The batch stops at its second call, whose error the next
+    // call that waits for its reply returns.
+    assert_eq!(unsafe { event_record(event, stream) }, CUDA_SUCCESS);
+    assert_eq!(unsafe { event_record(destroyed_event, stream) }, CUDA_SUCCESS);
+    assert_eq!(unsafe { stream_wait_event(stream, event, 0) }, CUDA_SUCCESS);
+    assert_eq!(unsafe { ctx_synchronize() }, CUDA_ERROR_INVALID_HANDLE);
+    // Only once, the error left the context usable.
+    assert_eq!(unsafe { stream_synchronize(stream) }, CUDA_SUCCESS);
+
+    // The error is returned instead of executing the call, which would make a new context current.
+    assert_eq!(unsafe { event_record(destroyed_event, stream) }, CUDA_SUCCESS);
+    let mut new_context = null_mut();
+    assert_eq!(unsafe { ctx_create(&mut new_context, 0, 0) }, CUDA_ERROR_INVALID_HANDLE);
+    let mut current = null_mut();
+    assert_eq!(unsafe { ctx_get_current(&mut current) }, CUDA_SUCCESS);
+    assert_eq!(current, context);
+
+    // A kernel writing past an allocation leaves the context unusable, so every later call returns the error.
+    let mut dptr = 0;
+    assert_eq!(unsafe { mem_alloc(&mut dptr, 64) }, CUDA_SUCCESS);
+    let ptx = c".version 8.0\n.visible .entry fill(.param .u64 dst, .param .u32 value, .param .u32 count)\n{\nret;\n}\n";
+    let (mut module, mut function) = (null_mut(), null_mut());
+    assert_eq!(unsafe { load_data(&mut module, ptx.as_ptr().cast()) }, CUDA_SUCCESS);
+    assert_eq!(unsafe { get_function(&mut function, module, c"fill".as_ptr()) }, CUDA_SUCCESS);
+    let (mut dst, mut value, mut count) = (dptr, 7_u32, 100_u32);
+    let mut params = [(&raw mut dst).cast::<c_void>(), (&raw mut value).cast(), (&raw mut count).cast()];
+    assert_eq!(unsafe { launch_kernel(function, 1, 1, 1, 32, 1, 1, 0, null_mut(), params.as_mut_ptr(), null_mut()) },
+               CUDA_SUCCESS);
+    assert_eq!(unsafe { ctx_synchronize() }, CUDA_ERROR_ILLEGAL_ADDRESS);
+    assert_eq!(unsafe { event_record(event, stream) }, CUDA_ERROR_ILLEGAL_ADDRESS);
+    assert_eq!(unsafe { stream_synchronize(stream) }, CUDA_ERROR_ILLEGAL_ADDRESS);
+}
@@ -0,0 +1,55 @@
+//! Cancels a copy in progress, in a client of its own as it copies in small chunks.
+
+mod harness;
+
+use cuda_over_ip_common::cuda::{CUresult, CUDA_ERROR_NOT_PERMITTED, CUDA_SUCCESS};
+use harness::Harness;
+use std::ffi::c_void;
+use std::ptr::null_mut;
+use std::thread;
+
+type CuInit = unsafe extern "C" fn(u32) -> CUresult;
+type CuCtxCreate = unsafe extern "C" fn(*mut *mut c_void, u32, i32) -> CUresult;
+type CuCtxSetCurrent = unsafe extern "C" fn(*mut c_void) -> CUresult;
+type CuMemAlloc = unsafe extern "C" fn(*mut u64, usize) -> CUresult;
+type CuMemcpyDtoH = unsafe extern "C" fn(*mut c_void, u64, usize) -> CUresult;
+type CancelCopies = unsafe extern "C" fn();
+
+#[test]
+fn cancelled_copies() {
+    std::env::set_var("CUDA_OVER_IP_MEMCPY_CHUNK_SIZE", "4096");
+    let harness = Harness::start();
+    let init = unsafe { harness.client_function::<CuInit>("cuInit") };
+    let ctx_create = unsafe { harness.client_function::<CuCtxCreate>("cuCtxCreate_v2") };
+    let ctx_set_current = *unsafe { harness.client_function::<CuCtxSetCurrent>("cuCtxSetCurrent") };
+    let mem_alloc = unsafe { harness.client_function::<CuMemAlloc>("cuMemAlloc_v2") };
+    let memcpy_dtoh = *unsafe { harness.client_function::<CuMemcpyDtoH>("cuMemcpyDtoH_v2") };
+    let cancel_copies = unsafe { harness.client_function::<CancelCopies>("cuda_over_ip_cancel_copies") };
+
+    assert_eq!(unsafe { init(0) }, CUDA_SUCCESS);
+    let mut context = null_mut();
+    assert_eq!(unsafe { ctx_create(&mut context, 0, 0) }, CUDA_SUCCESS);
+    // Thousands of chunks, which take longer than cancelling.
+    let size = 64 * 1024 * 1024;
+    let mut dptr = 0;
+    assert_eq!(unsafe { mem_alloc(&mut dptr, size) }, CUDA_SUCCESS);
+
+    // Copies started after cancelling are not cancelled.
+    unsafe { cancel_copies() };
+    let mut copy = vec![0_u8; size];
+    assert_eq!(unsafe { memcpy_dtoh(copy.as_mut_ptr() as *mut c_void, dptr, 4096 * 4) }, CUDA_SUCCESS);
+
+    let (context_address, copy_address) = (context as usize, copy.as_mut_ptr() as usize);
+    let copying = thread::spawn(move || unsafe {
+        assert_eq!(ctx_set_current(context_address as *mut c_void), CUDA_SUCCESS);
+        memcpy_dtoh(copy_address as *mut c_void, dptr, size)
+    });
+    while !copying.is_finished() {
+        unsafe { cancel_copies() };
+        thread::yield_now();
+    }
+    assert_eq!(copying.join().unwrap(), CUDA_ERROR_NOT_PERMITTED);
+
+    // Later copies are not cancelled.
+    assert_eq!(unsafe { memcpy_dtoh(copy.as_mut_ptr() as *mut c_void, dptr, 4096 * 4) }, CUDA_SUCCESS);
+}
@@ -0,0 +1,449 @@
+//! Calls the functions exported by the client library, served by a server running against the mock driver.
+
+mod harness;
+
+use cuda_over_ip_common::cuda::{CUresult, CUDA_ERROR_INVALID_CONTEXT, CUDA_ERROR_INVALID_DEVICE, CUDA_ERROR_INVALID_HANDLE, CUDA_ERROR_INVALID_IMAGE, CUDA_ERROR_INVALID_VALUE, CUDA_ERROR_NOT_FOUND, CUDA_ERROR_NOT_SUPPORTED, CUDA_SUCCESS, CU_JIT_ERROR_LOG_BUFFER, CU_JIT_ERROR_LOG_BUFFER_SIZE_BYTES, CU_JIT_GLOBAL_SYMBOL_NAMES, CU_LAUNCH_PARAM_BUFFER_POINTER, CU_LAUNCH_PARAM_BUFFER_SIZE, CU_LAUNCH_PARAM_END, CU_MEMORYTYPE_ARRAY, CU_MEMORYTYPE_DEVICE, CU_MEMORYTYPE_HOST};
+use cuda_over_ip_common::{CUdevprop_st, CUuuid_st, CUDA_MEMCPY2D_st};
+use cuda_over_ip_mock_driver::{device_uuid, DEVICE_COUNT, DEVICE_MEMORY, DRIVER_VERSION, MAX_THREADS_PER_BLOCK, PITCH_ALIGNMENT};
+use std::ffi::{c_char, c_void, CStr};
+use harness::Harness;
+use std::ptr::{null_mut, without_provenance_mut};
+use std::thread;
+
+type CuDriverGetVersion = unsafe extern "C" fn(*mut i32) -> CUresult;
+type CuInit = unsafe extern "C" fn(u32) -> CUresult;
+type CuCtxSynchronize = unsafe extern "C" fn() -> CUresult;
+type CuDeviceGetCount = unsafe extern "C" fn(*mut i32) -> CUresult;
+type CuDeviceGet = unsafe extern "C" fn(*mut i32, i32) -> CUresult;
+type CuDeviceGetName = unsafe extern "C" fn(*mut i8, i32, i32) -> CUresult;
+type CuDeviceGetUuid = unsafe extern "C" fn(*mut CUuuid_st, i32) -> CUresult;
+type CuDeviceGetProperties = unsafe extern "C" fn(*mut CUdevprop_st, i32) -> CUresult;
+type Handle = *mut c_void;
+type CuCtxCreate = unsafe extern "C" fn(*mut Handle, u32, i32) -> CUresult;
+type CuCtxGetCurrent = unsafe extern "C" fn(*mut Handle) -> CUresult;
+type CuCreate = unsafe extern "C" fn(*mut Handle, u32) -> CUresult;
+type CuDestroy = unsafe extern "C" fn(Handle) -> CUresult;
+type CuEventRecord = unsafe extern "C" fn(Handle, Handle) -> CUresult;
+type CuMemGetInfo = unsafe extern "C" fn(*mut usize, *mut usize) -> CUresult;
+type CuMemAlloc = unsafe extern "C" fn(*mut u64, usize) -> CUresult;
+type CuMemAllocPitch = unsafe extern "C" fn(*mut u64, *mut usize, usize, usize, u32) -> CUresult;
+type CuMemFree = unsafe extern "C" fn(u64) -> CUresult;
+type CuMemsetD8 = unsafe extern "C" fn(u64, u8, usize) -> CUresult;
+type CuMemsetD32 = unsafe extern "C" fn(u64, u32, usize) -> CUresult;
+type CuMemcpyHtoD = unsafe extern "C" fn(u64, *const c_void, usize) -> CUresult;
+type CuMemcpyDtoH = unsafe extern "C" fn(*mut c_void, u64, usize) -> CUresult;
+type CuMemcpy2D = unsafe extern "C" fn(*const CUDA_MEMCPY2D_st) -> CUresult;
+type CuModuleLoadData = unsafe extern "C" fn(*mut Handle, *const c_void) -> CUresult;
+type CuModuleLoadDataEx = unsafe extern "C" fn(*mut Handle, *const c_void, u32, *const i32, *mut *mut c_void) -> CUresult;
+type CuModuleGetFunction = unsafe extern "C" fn(*mut Handle, Handle, *const c_char) -> CUresult;
+type CuFuncGetParamInfo = unsafe extern "C" fn(Handle, usize, *mut usize, *mut usize) -> CUresult;
+type CuLaunchKernel = unsafe extern "C" fn(Handle, u32, u32, u32, u32, u32, u32, u32, Handle, *mut *mut c_void,
+                                           *mut *mut c_void) -> CUresult;
+
+// One test, as all scenarios share the server the client library stays connected to.
+#[test]
+fn end_to_end() {
+    let harness = Harness::start();
+    driver_version(&harness);
+    invalid_argument(&harness);
+    concurrent_calls(&harness);
+    generated_functions(&harness);
+    devices(&harness);
+    device_structs(&harness);
+    handles(&harness);
+    memory(&harness);
+    memcpy(&harness);
+    memcpy_2d(&harness);
+    modules(&harness);
+    launch(&harness);
+}
+
+fn driver_version(harness: &Harness) {
+    let driver_get_version = unsafe { harness.client_function::<CuDriverGetVersion>("cuDriverGetVersion") };
+    let mut version = 0;
+    assert_eq!(unsafe { driver_get_version(&mut version) }, CUDA_SUCCESS);
+    assert_eq!(version, DRIVER_VERSION);
+}
+
+fn invalid_argument(harness: &Harness) {
+    let driver_get_version = unsafe { harness.client_function::<CuDriverGetVersion>("cuDriverGetVersion") };
+    assert_eq!(unsafe { driver_get_version(null_mut()) }, CUDA_ERROR_INVALID_VALUE);
+}
+
+fn concurrent_calls(harness: &Harness) {
+    let driver_get_version = *unsafe { harness.client_function::<CuDriverGetVersion>("cuDriverGetVersion") };
+    thread::scope(|scope| {
+        for _ in 0..16 {
+            scope.spawn(|| for _ in 0..100 {
+                let mut version = 0;
+                assert_eq!(unsafe { driver_get_version(&mut version) }, CUDA_SUCCESS);
+                assert_eq!(version, DRIVER_VERSION);
+            });
+        }
+    });
+}
+
+fn generated_functions(harness: &Harness) {
+    let init = unsafe { harness.client_function::<CuInit>("cuInit") };
+    assert_eq!(unsafe { init(1) }, CUDA_ERROR_INVALID_VALUE);
+    assert_eq!(unsafe { init(0) }, CUDA_SUCCESS);
+
+    let ctx_synchronize = unsafe { harness.client_function::<CuCtxSynchronize>("cuCtxSynchronize") };
+    assert_eq!(unsafe { ctx_synchronize() }, CUDA_ERROR_INVALID_CONTEXT);
+}
+
+fn devices(harness: &Harness) {
+    let device_get_count = unsafe { harness.client_function::<CuDeviceGetCount>("cuDeviceGetCount") };
+    let mut count = 0;
+    assert_eq!(unsafe { device_get_count(&mut count) }, CUDA_SUCCESS);
+    assert_eq!(count, DEVICE_COUNT);
+
+    let device_get = unsafe { harness.client_function::<CuDeviceGet>("cuDeviceGet") };
+    let mut device = -1;
+    assert_eq!(unsafe { device_get(&mut device, 1) }, CUDA_SUCCESS);
+    assert_eq!(device, 1);
+    assert_eq!(unsafe { device_get(&mut device, DEVICE_COUNT) }, CUDA_ERROR_INVALID_DEVICE);
+    assert_eq!(device, 1);
+
+    let device_get_name = unsafe { harness.client_function::<CuDeviceGetName>("cuDeviceGetName") };
+    let mut name = [0x55_i8; 64];
+    assert_eq!(unsafe { device_get_name(name.as_mut_ptr(), name.len() as i32, device) }, CUDA_SUCCESS);
+    assert_eq!(unsafe { CStr::from_ptr(name.as_ptr()) }.to_str().unwrap(), "Mock CUDA Device 1");
+    let mut short_name = [0x55_i8; 5];
+    assert_eq!(unsafe { device_get_name(short_name.as_mut_ptr(), 5, device) }, CUDA_SUCCESS);
+    assert_eq!(unsafe { CStr::from_ptr(short_name.as_ptr()) }.to_str().unwrap(), "Mock");
+    assert_eq!(unsafe { device_get_name(name.as_mut_ptr(), -1, device) }, CUDA_ERROR_INVALID_VALUE);
+}
+
+fn device_structs(harness: &Harness) {
+    let device_get_uuid = unsafe { harness.client_function::<CuDeviceGetUuid>("cuDeviceGetUuid") };
+    let mut uuid = CUuuid_st::default();
+    assert_eq!(unsafe { device_get_uuid(&mut uuid, 1) }, CUDA_SUCCESS);
+    assert_eq!(uuid.bytes.map(|byte| byte as u8), device_uuid(1));
+    assert_eq!(unsafe { device_get_uuid(null_mut(), 1) }, CUDA_ERROR_INVALID_VALUE);
+
+    let device_get_properties = unsafe { harness.client_function::<CuDeviceGetProperties>("cuDeviceGetProperties") };
+    let mut properties = CUdevprop_st::default();
+    assert_eq!(unsafe { device_get_properties(&mut properties, 0) }, CUDA_SUCCESS);
+    assert_eq!(properties.maxThreadsPerBlock, MAX_THREADS_PER_BLOCK as i32);
+    assert_eq!(properties.maxThreadsDim, [1024, 1024, 64]);
+    assert_eq!(properties.maxGridSize, [i32::MAX, 65535, 65535]);
+    assert_eq!(properties.textureAlign, 512);
+    assert_eq!(unsafe { device_get_properties(&mut properties, DEVICE_COUNT) }, CUDA_ERROR_INVALID_DEVICE);
+}
+
+fn handles(harness: &Harness) {
+    let ctx_create = unsafe { harness.client_function::<CuCtxCreate>("cuCtxCreate_v2") };
+    let ctx_get_current = unsafe { harness.client_function::<CuCtxGetCurrent>("cuCtxGetCurrent") };
+    let ctx_destroy = unsafe { harness.client_function::<CuDestroy>("cuCtxDestroy_v2") };
+    let stream_create = unsafe { harness.client_function::<CuCreate>("cuStreamCreate") };
+    let stream_synchronize = unsafe { harness.client_function::<CuDestroy>("cuStreamSynchronize") };
+    let stream_destroy = unsafe { harness.client_function::<CuDestroy>("cuStreamDestroy_v2") };
+    let event_create = unsafe { harness.client_function::<CuCreate>("cuEventCreate") };
+    let event_record = unsafe { harness.client_function::<CuEventRecord>("cuEventRecord") };
+    let event_destroy = unsafe { harness.client_function::<CuDestroy>("cuEventDestroy_v2") };
+
+    let mut context = null_mut();
+    assert_eq!(unsafe { ctx_create(&mut context, 0, 0) }, CUDA_SUCCESS);
+    let mut current = null_mut();
+    assert_eq!(unsafe { ctx_get_current(&mut current) }, CUDA_SUCCESS);
+    assert_eq!(current, context);
+
+    let mut stream = null_mut();
+    assert_eq!(unsafe { stream_create(&mut stream, 0) }, CUDA_SUCCESS);
+    let mut event = null_mut();
+    assert_eq!(unsafe { event_create(&mut event, 0) }, CUDA_SUCCESS);
+    assert_ne!(stream, event);
+    assert_eq!(unsafe { event_record(event, stream) }, CUDA_SUCCESS);
+    assert_eq!(unsafe { stream_synchronize(stream) }, CUDA_SUCCESS);
+    // The default stream is not translated.
+    assert_eq!(unsafe { stream_synchronize(null_mut()) }, CUDA_SUCCESS);
+
+    // The client sees IDs, not the driver's handles, and can't make up handles.
+    let forged_stream = without_provenance_mut(stream as usize + 0x100);
+    assert_eq!(unsafe { stream_synchronize(forged_stream) }, CUDA_ERROR_INVALID_HANDLE);
+    // Small values other than null would reach the driver as addresses.
+    assert_eq!(unsafe { ctx_destroy(without_provenance_mut(2)) }, CUDA_ERROR_INVALID_HANDLE);
+
+    assert_eq!(unsafe { event_destroy(event) }, CUDA_SUCCESS);
+    assert_eq!(unsafe { stream_destroy(stream) }, CUDA_SUCCESS);
+    assert_eq!(unsafe { ctx_destroy(context) }, CUDA_SUCCESS);
+}
+
+fn memory(harness: &Harness) {
+    let ctx_create = unsafe { harness.client_function::<CuCtxCreate>("cuCtxCreate_v2") };
+    let ctx_destroy = unsafe { harness.client_function::<CuDestroy>("cuCtxDestroy_v2") };
+    let mem_get_info = unsafe { harness.client_function::<CuMemGetInfo>("cuMemGetInfo_v2") };
+    let mem_alloc = unsafe { harness.client_function::<CuMemAlloc>("cuMemAlloc_v2") };
+    let mem_alloc_pitch = unsafe { harness.client_function::<CuMemAllocPitch>("cuMemAllocPitch_v2") };
+    let mem_free = unsafe { harness.client_function::<CuMemFree>("cuMemFree_v2") };
+    let memset_d8 = unsafe { harness.client_function::<CuMemsetD8>("cuMemsetD8_v2") };
+    let memset_d32 = unsafe { harness.client_function::<CuMemsetD32>("cuMemsetD32_v2") };
+
+    let mut context = null_mut();
+    assert_eq!(unsafe { ctx_create(&mut context, 0, 0) }, CUDA_SUCCESS);
+    let (mut free, mut total) = (0, 0);
+    assert_eq!(unsafe { mem_get_info(&mut free, &mut total) }, CUDA_SUCCESS);
+    assert_eq!(total as u64, DEVICE_MEMORY);
+
+    let mut dptr = 0;
+    assert_eq!(unsafe { mem_alloc(&mut dptr, 1000) }, CUDA_SUCCESS);
+    assert_eq!(dptr % 256, 0);
+    let mut free_after_alloc = 0;
+    assert_eq!(unsafe { mem_get_info(&mut free_after_alloc, &mut total) }, CUDA_SUCCESS);
+    assert_eq!(free_after_alloc, free - 1000);
+
+    // Pointers within the allocation resolve on the server, others are not the client's.
+    assert_eq!(unsafe { memset_d8(dptr + 100, 0xff, 900) }, CUDA_SUCCESS);
+    assert_eq!(unsafe { memset_d32(dptr + 4, 0xdead_beef, 2) }, CUDA_SUCCESS);
+    assert_eq!(unsafe { memset_d8(dptr + 999, 0, 2) }, CUDA_ERROR_INVALID_VALUE);
+    assert_eq!(unsafe { memset_d32(dptr + 996, 0, 2) }, CUDA_ERROR_INVALID_VALUE);
+    assert_eq!(unsafe { memset_d32(dptr, 0, usize::MAX / 2) }, CUDA_ERROR_INVALID_VALUE);
+    assert_eq!(unsafe { memset_d8(dptr + 1000, 0, 1) }, CUDA_ERROR_INVALID_HANDLE);
+
+    let (mut pitched, mut pitch) = (0, 0);
+    assert_eq!(unsafe { mem_alloc_pitch(&mut pitched, &mut pitch, 100, 3, 4) }, CUDA_SUCCESS);
+    assert_eq!(pitch, PITCH_ALIGNMENT);
+    assert_eq!(unsafe { memset_d8(pitched + 3 * pitch as u64 - 1, 0, 1) }, CUDA_SUCCESS);
+    assert_eq!(unsafe { memset_d8(pitched + 3 * pitch as u64, 0, 1) }, CUDA_ERROR_INVALID_HANDLE);
+    assert_eq!(unsafe { mem_alloc_pitch(&mut pitched, &mut pitch, 100, 3, 3) }, CUDA_ERROR_INVALID_VALUE);
+
+    assert_eq!(unsafe { mem_free(dptr) }, CUDA_SUCCESS);
+    assert_eq!(unsafe { mem_free(pitched) }, CUDA_SUCCESS);
+    assert_eq!(unsafe { mem_free(dptr) }, CUDA_ERROR_INVALID_HANDLE);
+    assert_eq!(unsafe { mem_get_info(&mut free_after_alloc, &mut total) }, CUDA_SUCCESS);
+    assert_eq!(free_after_alloc, free);
+    assert_eq!(unsafe { ctx_destroy(context) }, CUDA_SUCCESS);
+}
+
+fn memcpy(harness: &Harness) {
+    let ctx_create = unsafe { harness.client_function::<CuCtxCreate>("cuCtxCreate_v2") };
+    let ctx_destroy = unsafe { harness.client_function::<CuDestroy>("cuCtxDestroy_v2") };
+    let mem_alloc = unsafe { harness.client_function::<CuMemAlloc>("cuMemAlloc_v2") };
+    let mem_free = unsafe { harness.client_function::<CuMemFree>("cuMemFree_v2") };
+    let memset_d8 = unsafe { harness.client_function::<CuMemsetD8>("cuMemsetD8_v2") };
+    let memcpy_htod = unsafe { harness.client_function::<CuMemcpyHtoD>("cuMemcpyHtoD_v2") };
+    let memcpy_dtoh = unsafe { harness.client_function::<CuMemcpyDtoH>("cuMemcpyDtoH_v2") };
+
+    let mut context = null_mut();
+    assert_eq!(unsafe { ctx_create(&mut context, 0, 0) }, CUDA_SUCCESS);
+    // Several chunks of the default size, the last one partial.
+    let size = 3 * 1024 * 1024 + 1000;
+    let mut dptr = 0;
+    assert_eq!(unsafe { mem_alloc(&mut dptr, size) }, CUDA_SUCCESS);
+
+    let data: Vec<u8> = (0..size).map(|i| (i * 7 % 251) as u8).collect();
+    assert_eq!(unsafe { memcpy_htod(dptr, data.as_ptr() as *const c_void, size) }, CUDA_SUCCESS);
+    let mut copy = vec![0_u8; size];
+    assert_eq!(unsafe { memcpy_dtoh(copy.as_mut_ptr() as *mut c_void, dptr, size) }, CUDA_SUCCESS);
+    assert!(copy == data);
+
+    // Copies within the allocation see the other calls.
+    assert_eq!(unsafe { memset_d8(dptr + 10, 0xab, 5) }, CUDA_SUCCESS);
+    let mut bytes = [0_u8; 8];
+    assert_eq!(unsafe { memcpy_dtoh(bytes.as_mut_ptr() as *mut c_void, dptr + 8, 8) }, CUDA_SUCCESS);
+    assert_eq!(bytes, [data[8], data[9], 0xab, 0xab, 0xab, 0xab, 0xab, data[15]]);
+    assert_eq!(unsafe { memcpy_htod(dptr + 1, [1_u8, 2].as_ptr() as *const c_void, 2) }, CUDA_SUCCESS);
+    assert_eq!(unsafe { memcpy_dtoh(bytes.as_mut_ptr() as *mut c_void, dptr, 4) }, CUDA_SUCCESS);
+    assert_eq!(bytes[..4], [data[0], 1, 2, data[3]]);
+    assert_eq!(unsafe { memcpy_dtoh(bytes.as_mut_ptr() as *mut c_void, dptr, 0) }, CUDA_SUCCESS);
+
+    // A copy running past the allocation fails at the chunk that does, the chunks before it are copied.
+    copy.fill(0);
+    let offset = size as u64 - 1024 * 1024 - 100;
+    assert_eq!(unsafe { memcpy_dtoh(copy.as_mut_ptr() as *mut c_void, dptr + offset, 3 * 1024 * 1024) },
+               CUDA_ERROR_INVALID_VALUE);
+    assert!(copy[..1024 * 1024] == data[offset as usize..offset as usize + 1024 * 1024]);
+    assert_eq!(unsafe { memcpy_htod(dptr + size as u64 - 1, [1_u8, 2].as_ptr() as *const c_void, 2) },
+               CUDA_ERROR_INVALID_VALUE);
+    assert_eq!(unsafe { memcpy_htod(dptr, null_mut(), 1) }, CUDA_ERROR_INVALID_VALUE);
+    assert_eq!(unsafe { memcpy_dtoh(bytes.as_mut_ptr() as *mut c_void, dptr + size as u64, 1) },
+               CUDA_ERROR_INVALID_HANDLE);
+    // Addresses past the end of the address space.
+    assert_eq!(unsafe { memcpy_htod(u64::MAX - 1, [1_u8, 2, 3].as_ptr() as *const c_void, 3) },
+               CUDA_ERROR_INVALID_VALUE);
+    assert_eq!(unsafe { memcpy_dtoh(bytes.as_mut_ptr() as *mut c_void, u64::MAX, 2) }, CUDA_ERROR_INVALID_VALUE);
+
+    assert_eq!(unsafe { mem_free(dptr) }, CUDA_SUCCESS);
+    assert_eq!(unsafe { ctx_destroy(context) }, CUDA_SUCCESS);
+}
+
+fn memcpy_2d(harness: &Harness) {
+    let ctx_create = unsafe { harness.client_function::<CuCtxCreate>("cuCtxCreate_v2") };
+    let ctx_destroy = unsafe { harness.client_function::<CuDestroy>("cuCtxDestroy_v2") };
+    let mem_alloc = unsafe { harness.client_function::<CuMemAlloc>("cuMemAlloc_v2") };
+    let mem_free = unsafe { harness.client_function::<CuMemFree>("cuMemFree_v2") };
+    let memcpy_htod = unsafe { harness.client_function::<CuMemcpyHtoD>("cuMemcpyHtoD_v2") };
+    let memcpy_dtoh = unsafe { harness.client_function::<CuMemcpyDtoH>("cuMemcpyDtoH_v2") };
+    let memcpy_2d = unsafe { harness.client_function::<CuMemcpy2D>("cuMemcpy2D_v2") };
+
+    let mut context = null_mut();
+    assert_eq!(unsafe { ctx_create(&mut context, 0, 0) }, CUDA_SUCCESS);
+    // 4 rows of 16 bytes, copied to rows of 8 bytes.
+    let (mut src, mut dst) = (0, 0);
+    assert_eq!(unsafe { mem_alloc(&mut src, 64) }, CUDA_SUCCESS);
+    assert_eq!(unsafe { mem_alloc(&mut dst, 32) }, CUDA_SUCCESS);
+    let data: Vec<u8> = (0..64).collect();
+    assert_eq!(unsafe { memcpy_htod(src, data.as_ptr() as *const c_void, 64) }, CUDA_SUCCESS);
+    assert_eq!(unsafe { memcpy_htod(dst, [0_u8; 32].as_ptr() as *const c_void, 32) }, CUDA_SUCCESS);
+
+    let copy = CUDA_MEMCPY2D_st {
+        srcXInBytes: 2,
+        srcY: 1,
+        srcMemoryType: CU_MEMORYTYPE_DEVICE,
+        srcDevice: src,
+        srcPitch: 16,
+        dstMemoryType: CU_MEMORYTYPE_DEVICE,
+        dstDevice: dst,
+        dstPitch: 8,
+        WidthInBytes: 4,
+        Height: 3,
+        ..Default::default()
+    };
+    assert_eq!(unsafe { memcpy_2d(&copy) }, CUDA_SUCCESS);
+    let mut bytes = [0_u8; 24];
+    assert_eq!(unsafe { memcpy_dtoh(bytes.as_mut_ptr() as *mut c_void, dst, 24) }, CUDA_SUCCESS);
+    assert_eq!(bytes, [18, 19, 20, 21, 0, 0, 0, 0, 34, 35, 36, 37, 0, 0, 0, 0, 50, 51, 52, 53, 0, 0, 0, 0]);
+
+    // The server checks the rows are within the allocations, the driver's neighbours are out of reach.
+    assert_eq!(unsafe { memcpy_2d(&CUDA_MEMCPY2D_st { Height: 4, ..copy }) }, CUDA_ERROR_INVALID_VALUE);
+    assert_eq!(unsafe { memcpy_2d(&CUDA_MEMCPY2D_st { dstPitch: 16, ..copy }) }, CUDA_ERROR_INVALID_VALUE);
+    assert_eq!(unsafe { memcpy_2d(&CUDA_MEMCPY2D_st { dstDevice: dst + 32, ..copy }) }, CUDA_ERROR_INVALID_HANDLE);
+    // Host memory is the client's, arrays are handles.
+    let host = CUDA_MEMCPY2D_st { srcMemoryType: CU_MEMORYTYPE_HOST, srcHost: data.as_ptr() as *const c_void, ..copy };
+    assert_eq!(unsafe { memcpy_2d(&host) }, CUDA_ERROR_NOT_SUPPORTED);
+    let array = CUDA_MEMCPY2D_st {
+        srcMemoryType: CU_MEMORYTYPE_ARRAY,
+        srcArray: without_provenance_mut(0x1234),
+        ..copy
+    };
+    assert_eq!(unsafe { memcpy_2d(&array) }, CUDA_ERROR_INVALID_HANDLE);
+    assert_eq!(unsafe { memcpy_2d(std::ptr::null()) }, CUDA_ERROR_INVALID_VALUE);
+
+    assert_eq!(unsafe { mem_free(src) }, CUDA_SUCCESS);
+    assert_eq!(unsafe { mem_free(dst) }, CUDA_SUCCESS);
+    assert_eq!(unsafe { ctx_destroy(context) }, CUDA_SUCCESS);
+}
+
+fn modules(harness: &Harness) {
+    let ctx_create = unsafe { harness.client_function::<CuCtxCreate>("cuCtxCreate_v2") };
+    let ctx_destroy = unsafe { harness.client_function::<CuDestroy>("cuCtxDestroy_v2") };
+    let load_data = unsafe { harness.client_function::<CuModuleLoadData>("cuModuleLoadData") };
+    let load_data_ex = unsafe { harness.client_function::<CuModuleLoadDataEx>("cuModuleLoadDataEx") };
+    let load_fat_binary = unsafe { harness.client_function::<CuModuleLoadData>("cuModuleLoadFatBinary") };
+    let get_function = unsafe { harness.client_function::<CuModuleGetFunction>("cuModuleGetFunction") };
+    let unload = unsafe { harness.client_function::<CuDestroy>("cuModuleUnload") };
+
+    let mut context = null_mut();
+    assert_eq!(unsafe { ctx_create(&mut context, 0, 0) }, CUDA_SUCCESS);
+
+    // The kernels of PTX are known to the mock driver, so only the image up to the NUL reached the server.
+    let ptx = c".version 8.0\n.visible .entry add_one(\n.param .u64 p)\n{\nret;\n}\n";
+    let (mut module, mut function) = (null_mut(), null_mut());
+    assert_eq!(unsafe { load_data(&mut module, ptx.as_ptr().cast()) }, CUDA_SUCCESS);
+    assert_eq!(unsafe { get_function(&mut function, module, c"add_one".as_ptr()) }, CUDA_SUCCESS);
+    assert!(!function.is_null());
+    assert_eq!(unsafe { get_function(&mut function, module, c"add".as_ptr()) }, CUDA_ERROR_NOT_FOUND);
+    assert_eq!(unsafe { unload(module) }, CUDA_SUCCESS);
+    assert_eq!(unsafe { get_function(&mut function, module, c"add_one".as_ptr()) }, CUDA_ERROR_INVALID_HANDLE);
+
+    // The error log the driver writes on the server is copied to the client's buffer.
+    let mut log = [0_u8; 64];
+    let options = [CU_JIT_ERROR_LOG_BUFFER_SIZE_BYTES, CU_JIT_ERROR_LOG_BUFFER];
+    let mut values = [log.len() as *mut c_void, log.as_mut_ptr().cast()];
+    assert_eq!(unsafe { load_data_ex(&mut module, c"not ptx".as_ptr().cast(), 2, options.as_ptr(), values.as_mut_ptr()) },
+               CUDA_ERROR_INVALID_IMAGE);
+    assert_eq!(values, [10 as *mut c_void, log.as_mut_ptr().cast()]);
+    assert_eq!(&log[..10], b"error 200\0");
+    assert_eq!(unsafe { load_data_ex(&mut module, ptx.as_ptr().cast(), 2, options.as_ptr(), values.as_mut_ptr()) },
+               CUDA_SUCCESS);
+    assert_eq!(unsafe { unload(module) }, CUDA_SUCCESS);
+    let options = [CU_JIT_GLOBAL_SYMBOL_NAMES];
+    assert_eq!(unsafe { load_data_ex(&mut module, ptx.as_ptr().cast(), 1, options.as_ptr(), values.as_mut_ptr()) },
+               CUDA_ERROR_NOT_SUPPORTED);
+
+    // Binary images, whose size the client takes from their headers.
+    let mut fatbin = vec![0; 16];
+    fatbin[..4].copy_from_slice(&0xBA55_ED50_u32.to_le_bytes());
+    fatbin[6..8].copy_from_slice(&16_u16.to_le_bytes());
+    fatbin[8..16].copy_from_slice(&1000_u64.to_le_bytes());
+    fatbin.resize(16 + 1000, 0xff);
+    assert_eq!(unsafe { load_fat_binary(&mut module, fatbin.as_ptr().cast()) }, CUDA_SUCCESS);
+    assert_eq!(unsafe { get_function(&mut function, module, c"any".as_ptr()) }, CUDA_SUCCESS);
+    assert_eq!(unsafe { unload(module) }, CUDA_SUCCESS);
+    let mut cubin = [0_u8; 64];
+    cubin[..6].copy_from_slice(b"\x7fELF\x02\x01");
+    assert_eq!(unsafe { load_data(&mut module, cubin.as_ptr().cast()) }, CUDA_SUCCESS);
+    assert_eq!(unsafe { unload(module) }, CUDA_SUCCESS);
+    cubin[4] = 1;
+    assert_eq!(unsafe { load_data(&mut module, cubin.as_ptr().cast()) }, CUDA_ERROR_INVALID_IMAGE);
+
+    assert_eq!(unsafe { ctx_destroy(context) }, CUDA_SUCCESS);
+}
+
+fn launch(harness: &Harness) {
+    let ctx_create = unsafe { harness.client_function::<CuCtxCreate>("cuCtxCreate_v2") };
+    let ctx_destroy = unsafe { harness.client_function::<CuDestroy>("cuCtxDestroy_v2") };
+    let mem_alloc = unsafe { harness.client_function::<CuMemAlloc>("cuMemAlloc_v2") };
+    let mem_free = unsafe { harness.client_function::<CuMemFree>("cuMemFree_v2") };
+    let memcpy_dtoh = unsafe { harness.client_function::<CuMemcpyDtoH>("cuMemcpyDtoH_v2") };
+    let load_data = unsafe { harness.client_function::<CuModuleLoadData>("cuModuleLoadData") };
+    let get_function = unsafe { harness.client_function::<CuModuleGetFunction>("cuModuleGetFunction") };
+    let unload = unsafe { harness.client_function::<CuDestroy>("cuModuleUnload") };
+    let launch_kernel = unsafe { harness.client_function::<CuLaunchKernel>("cuLaunchKernel") };
+    let func_get_param_info = unsafe { harness.client_function::<CuFuncGetParamInfo>("cuFuncGetParamInfo") };
+
+    let mut context = null_mut();
+    assert_eq!(unsafe { ctx_create(&mut context, 0, 0) }, CUDA_SUCCESS);
+    let mut dptr = 0;
+    assert_eq!(unsafe { mem_alloc(&mut dptr, 64) }, CUDA_SUCCESS);
+    let ptx = c".version 8.0\n.visible .entry fill(.param .u64 dst, .param .u32 value, .param .u32 count)\n{\nret;\n}\n";
+    let (mut module, mut function) = (null_mut(), null_mut());
+    assert_eq!(unsafe { load_data(&mut module, ptx.as_ptr().cast()) }, CUDA_SUCCESS);
+    assert_eq!(unsafe { get_function(&mut function, module, c"fill".as_ptr()) }, CUDA_SUCCESS);
+    let mut words = [0_u32; 16];
+
+    // The layout comes from the server, which translates the pointer within the allocation.
+    let (mut dst, mut value, mut count) = (dptr + 8, 7_u32, 4_u32);
+    let mut params = [(&raw mut dst).cast::<c_void>(), (&raw mut value).cast(), (&raw mut count).cast()];
+    assert_eq!(unsafe { launch_kernel(function, 1, 1, 1, 32, 1, 1, 0, null_mut(), params.as_mut_ptr(), null_mut()) },
+               CUDA_SUCCESS);
+    assert_eq!(unsafe { memcpy_dtoh(words.as_mut_ptr().cast(), dptr, 64) }, CUDA_SUCCESS);
+    assert_eq!(words[..8], [0, 0, 7, 7, 7, 7, 0, 0]);
+
+    // The same parameters in one buffer, whose layout the server looks up.
+    let mut buffer = [0_u8; 16];
+    buffer[..8].copy_from_slice(&dptr.to_ne_bytes());
+    buffer[8..12].copy_from_slice(&9_u32.to_ne_bytes());
+    buffer[12..].copy_from_slice(&2_u32.to_ne_bytes());
+    let mut buffer_size = buffer.len();
+    let mut extra = [without_provenance_mut(CU_LAUNCH_PARAM_BUFFER_POINTER), buffer.as_mut_ptr().cast(),
+                     without_provenance_mut(CU_LAUNCH_PARAM_BUFFER_SIZE), (&raw mut buffer_size).cast(),
+                     without_provenance_mut(CU_LAUNCH_PARAM_END)];
+    assert_eq!(unsafe { launch_kernel(function, 1, 1, 1, 32, 1, 1, 0, null_mut(), null_mut(), extra.as_mut_ptr()) },
+               CUDA_SUCCESS);
+    assert_eq!(unsafe { memcpy_dtoh(words.as_mut_ptr().cast(), dptr, 64) }, CUDA_SUCCESS);
+    assert_eq!(words[..8], [9, 9, 7, 7, 7, 7, 0, 0]);
+
+    // The driver's errors of a launch come with a later call, see tests/asynchronous_calls.rs. The client returns these.
+    assert_eq!(unsafe { launch_kernel(function, 1, 1, 1, 32, 1, 1, 0, null_mut(), params.as_mut_ptr(), extra.as_mut_ptr()) },
+               CUDA_ERROR_INVALID_VALUE);
+    assert_eq!(unsafe { unload(module) }, CUDA_SUCCESS);
+    // The functions of the module are gone with it.
+    let (mut offset, mut size) = (0, 0);
+    assert_eq!(unsafe { func_get_param_info(function, 0, &mut offset, &mut size) }, CUDA_ERROR_INVALID_HANDLE);
+
+    // Without `.nv.info` sections the layout of a kernel of a cubin is unknown, which only the buffer does without.
+    let mut cubin = [0_u8; 64];
+    cubin[..6].copy_from_slice(b"\x7fELF\x02\x01");
+    assert_eq!(unsafe { load_data(&mut module, cubin.as_ptr().cast()) }, CUDA_SUCCESS);
+    assert_eq!(unsafe { get_function(&mut function, module, c"fill".as_ptr()) }, CUDA_SUCCESS);
+    assert_eq!(unsafe { launch_kernel(function, 1, 1, 1, 32, 1, 1, 0, null_mut(), params.as_mut_ptr(), null_mut()) },
+               CUDA_ERROR_NOT_SUPPORTED);
+    assert_eq!(unsafe { launch_kernel(function, 1, 1, 1, 32, 1, 1, 0, null_mut(), null_mut(), extra.as_mut_ptr()) },
+               CUDA_SUCCESS);
+    assert_eq!(unsafe { unload(module) }, CUDA_SUCCESS);
+
+    assert_eq!(unsafe { mem_free(dptr) }, CUDA_SUCCESS);
+    assert_eq!(unsafe { ctx_destroy(context) }, CUDA_SUCCESS);
+}
@@ -0,0 +1,71 @@
+//! Runs the server against the mock driver and loads the client library into the test process.
+
+#![allow(dead_code)] // Each test binary uses part of the harness.
+
+use libloading::{Library, Symbol};
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+
+pub struct Harness {
+    server: Child,
+    client: Library,
+}
+
+impl Harness {
+    /// Starts the server on an ephemeral port and loads the client library configured to use it.
+    ///
+    /// The client library connects once per process, so a test binary can have only one harness.
+    pub fn start() -> Self {
+        Self::start_with_args(&[])
+    }
+
+    /// Like `start`, passing more arguments to the server.
+    pub fn start_with_args(args: &[&str]) -> Self {
+        let mut server = Command::new(env!("CARGO_BIN_EXE_cuda-over-ip-server"))
+            .arg("--port").arg("0")
+            .arg("--driver-library").arg(shared_library("cuda_over_ip_mock_driver"))
+            .args(args)
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("cannot start the server");
+
+        // The server logs the address it listens on, and keeps logging to the test output.
+        let mut log = BufReader::new(server.stderr.take().unwrap()).lines();
+        let address = loop {
+            let line = log.next().expect("server exited before listening").unwrap();
+            eprintln!("{}", line);
+            if let Some((_, address)) = line.split_once("Listening on ") {
+                break address.to_string();
+            }
+        };
+        thread::spawn(move || log.map_while(Result::ok).for_each(|line| eprintln!("{}", line)));
+
+        std::env::set_var("CUDA_OVER_IP_SERVER", address);
+        let client = unsafe { Library::new(shared_library("cuda_over_ip_client")) }
+            .expect("cannot load the client library");
+        Harness { server, client }
+    }
+
+    /// A function exported by the client library.
+    ///
+    /// # Safety
+    /// `T` must be the type of the function.
+    pub unsafe fn client_function<T>(&self, name: &str) -> Symbol<'_, T> {
+        self.client.get(name.as_bytes()).unwrap_or_else(|e| panic!("no function {} in the client library: {}", name, e))
+    }
+}
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        let _ = self.server.kill();
+        let _ = self.server.wait();
+    }
+}
+
+/// A shared library of the workspace. Cargo builds those of the dev-dependencies next to the test binary.
+fn shared_library(name: &str) -> PathBuf {
+    let deps_dir = std::env::current_exe().unwrap().parent().unwrap().to_path_buf();
+    deps_dir.join(format!("{}{}{}", std::env::consts::DLL_PREFIX, name, std::env::consts::DLL_SUFFIX))
+}
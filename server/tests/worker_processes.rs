@@ -0,0 +1,55 @@
+//! Calls the functions exported by the client library, served by a worker process of the server.
+
+mod harness;
+
+use cuda_over_ip_common::cuda::{CUresult, CUDA_SUCCESS};
+use cuda_over_ip_mock_driver::DRIVER_VERSION;
+use harness::Harness;
+use std::ffi::c_void;
+use std::ptr::null_mut;
+use std::thread;
+
+type CuDriverGetVersion = unsafe extern "C" fn(*mut i32) -> CUresult;
+type CuInit = unsafe extern "C" fn(u32) -> CUresult;
+type Handle = *mut c_void;
+type CuCtxCreate = unsafe extern "C" fn(*mut Handle, u32, i32) -> CUresult;
+type CuCtxSetCurrent = unsafe extern "C" fn(Handle) -> CUresult;
+type CuCtxDestroy = unsafe extern "C" fn(Handle) -> CUresult;
+type CuModuleLoadData = unsafe extern "C" fn(*mut Handle, *const c_void) -> CUresult;
+type CuModuleUnload = unsafe extern "C" fn(Handle) -> CUresult;
+
+#[test]
+fn worker_processes() {
+    let harness = Harness::start_with_args(&["--isolation", "process", "--module-cache-size", "0"]);
+    let driver_get_version = unsafe { harness.client_function::<CuDriverGetVersion>("cuDriverGetVersion") };
+    let init = unsafe { harness.client_function::<CuInit>("cuInit") };
+    let ctx_create = unsafe { harness.client_function::<CuCtxCreate>("cuCtxCreate_v2") };
+    let ctx_set_current = *unsafe { harness.client_function::<CuCtxSetCurrent>("cuCtxSetCurrent") };
+    let ctx_destroy = unsafe { harness.client_function::<CuCtxDestroy>("cuCtxDestroy_v2") };
+    let module_load_data = unsafe { harness.client_function::<CuModuleLoadData>("cuModuleLoadData") };
+    let module_unload = unsafe { harness.client_function::<CuModuleUnload>("cuModuleUnload") };
+
+    let mut version = 0;
+    assert_eq!(unsafe { driver_get_version(&mut version) }, CUDA_SUCCESS);
+    assert_eq!(version, DRIVER_VERSION);
+    assert_eq!(unsafe { init(0) }, CUDA_SUCCESS);
+    let mut context = null_mut();
+    assert_eq!(unsafe { ctx_create(&mut context, 0, 0) }, CUDA_SUCCESS);
+
+    // The connections the other threads open reach the same worker, which knows the handle.
+    let context = context as usize;
+    thread::scope(|scope| {
+        for _ in 0..4 {
+            scope.spawn(|| assert_eq!(unsafe { ctx_set_current(context as Handle) }, CUDA_SUCCESS));
+        }
+    });
+
+    // Without a module cache, the client sends every image after its hash.
+    let ptx = c".version 8.0\n.visible .entry k()\n{\nret;\n}\n";
+    for _ in 0..2 {
+        let mut module = null_mut();
+        assert_eq!(unsafe { module_load_data(&mut module, ptx.as_ptr().cast()) }, CUDA_SUCCESS);
+        assert_eq!(unsafe { module_unload(module) }, CUDA_SUCCESS);
+    }
+    assert_eq!(unsafe { ctx_destroy(context as Handle) }, CUDA_SUCCESS);
+}